@@ -0,0 +1,267 @@
+/* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
+
+use clap::ValueEnum;
+use miette::Diagnostic;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command as StdCommand;
+use tracing::{debug, span, Level};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CompilerBackend {
+    /// Compile in-process, no external binary needed
+    Protox,
+    /// Run the `protoc` binary at `PROTOC_PATH`
+    Protoc,
+}
+
+/// A failed compilation, with the location of the first error when the compiler reports one.
+#[derive(Debug)]
+pub struct CompileError {
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl CompileError {
+    fn new(message: impl Into<String>) -> Self {
+        CompileError {
+            file: None,
+            line: None,
+            column: None,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.file, self.line, self.column) {
+            (Some(file), Some(line), Some(column)) => {
+                write!(f, "{}:{}:{}: {}", file, line, column, self.message)
+            }
+            (Some(file), _, _) => write!(f, "{}: {}", file, self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// Compiles `file` into a `FileDescriptorSet` written to `output_file`, including every file it
+/// imports. Imports are looked up in `include_dirs`; the google/protobuf well-known types are
+/// always available.
+pub fn compile_proto_file(
+    backend: CompilerBackend,
+    include_dirs: &[PathBuf],
+    file: &Path,
+    output_file: &Path,
+) -> Result<(), CompileError> {
+    match backend {
+        CompilerBackend::Protox => compile_with_protox(include_dirs, file, output_file),
+        CompilerBackend::Protoc => {
+            let protoc_path = std::env::var("PROTOC_PATH")
+                .map_err(|_| CompileError::new("PROTOC_PATH must be set to compile with protoc"))?;
+            compile_with_protoc(&protoc_path, include_dirs, file, output_file)
+        }
+    }
+}
+
+fn compile_with_protox(
+    include_dirs: &[PathBuf],
+    file: &Path,
+    output_file: &Path,
+) -> Result<(), CompileError> {
+    let span = span!(Level::INFO, "compile_with_protox");
+    let _enter = span.enter();
+
+    let mut compiler = protox::Compiler::new(include_dirs).map_err(|err| protox_error(&err))?;
+    compiler
+        .include_imports(true)
+        .open_file(file)
+        .map_err(|err| protox_error(&err))?;
+
+    std::fs::write(output_file, compiler.encode_file_descriptor_set()).map_err(|err| {
+        CompileError::new(format!(
+            "Failed to write {}: {}",
+            output_file.display(),
+            err
+        ))
+    })?;
+    debug!("Compiled {:?} with protox", file);
+    Ok(())
+}
+
+fn protox_error(err: &protox::Error) -> CompileError {
+    let mut compile_error = CompileError::new(err.to_string());
+    compile_error.file = err.file().map(str::to_string);
+
+    if let (Some(source_code), Some(mut labels)) = (err.source_code(), err.labels()) {
+        if let Some(contents) = labels
+            .next()
+            .and_then(|label| source_code.read_span(label.inner(), 0, 0).ok())
+        {
+            compile_error.line = Some(contents.line() + 1);
+            compile_error.column = Some(contents.column() + 1);
+        }
+    }
+    compile_error
+}
+
+fn compile_with_protoc(
+    protoc_path: &str,
+    include_dirs: &[PathBuf],
+    file: &Path,
+    output_file: &Path,
+) -> Result<(), CompileError> {
+    let span = span!(Level::INFO, "compile_with_protoc");
+    let _enter = span.enter();
+
+    let mut command = StdCommand::new(protoc_path);
+    for include_dir in include_dirs {
+        command.arg("--proto_path").arg(include_dir);
+    }
+    let output = command
+        .arg("--include_imports")
+        .arg("--descriptor_set_out")
+        .arg(output_file)
+        .arg(file)
+        .output()
+        .map_err(|err| CompileError::new(format!("Failed to run {}: {}", protoc_path, err)))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        debug!("protoc failed for {:?}: {}", file, stderr.trim());
+        return Err(parse_protoc_error(&stderr));
+    }
+
+    Ok(())
+}
+
+// protoc reports errors as `file:line:column: message`, one per line, and as `file: message` when
+// the error has no position in the file.
+fn parse_protoc_error(stderr: &str) -> CompileError {
+    let first_line = stderr.lines().find(|line| !line.trim().is_empty());
+    let Some(first_line) = first_line else {
+        return CompileError::new("protoc failed without an error message");
+    };
+
+    let Some((file, mut message)) = first_line
+        .split_once(':')
+        .filter(|(file, _)| file.ends_with(".proto"))
+    else {
+        return CompileError::new(first_line.trim());
+    };
+    let mut location = Vec::with_capacity(2);
+    while location.len() < 2 {
+        let Some((number, rest)) = message.split_once(':') else {
+            break;
+        };
+        let Ok(number) = number.parse() else {
+            break;
+        };
+        location.push(number);
+        message = rest;
+    }
+    CompileError {
+        file: Some(file.to_string()),
+        line: location.first().copied(),
+        column: location.get(1).copied(),
+        message: message.trim().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(err: &CompileError) -> (Option<&str>, Option<usize>, Option<usize>, &str) {
+        (
+            err.file.as_deref(),
+            err.line,
+            err.column,
+            err.message.as_str(),
+        )
+    }
+
+    #[test]
+    fn test_parse_protoc_error() {
+        let cases = [
+            (
+                "shop/order.proto:7:5: \"Itm\" is not defined.\n",
+                (
+                    Some("shop/order.proto"),
+                    Some(7),
+                    Some(5),
+                    "\"Itm\" is not defined.",
+                ),
+            ),
+            (
+                "order.proto:3:1: Import \"missing.proto\" was not found or had errors.\n\
+                 order.proto:6:3: \"Missing\" is not defined.\n",
+                (
+                    Some("order.proto"),
+                    Some(3),
+                    Some(1),
+                    "Import \"missing.proto\" was not found or had errors.",
+                ),
+            ),
+            (
+                "order.proto:12: Expected \";\".\n",
+                (Some("order.proto"), Some(12), None, "Expected \";\"."),
+            ),
+            (
+                "missing.proto: File not found.\n",
+                (Some("missing.proto"), None, None, "File not found."),
+            ),
+            (
+                "Could not make proto path relative: order.proto: No such file or directory\n",
+                (
+                    None,
+                    None,
+                    None,
+                    "Could not make proto path relative: order.proto: No such file or directory",
+                ),
+            ),
+            (
+                "\n  \n",
+                (None, None, None, "protoc failed without an error message"),
+            ),
+        ];
+
+        for (stderr, expected) in cases {
+            assert_eq!(
+                location(&parse_protoc_error(stderr)),
+                expected,
+                "{}",
+                stderr
+            );
+        }
+    }
+
+    #[test]
+    fn test_protox_error_has_location() {
+        let directory = std::env::temp_dir().join(format!("compiler-test-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("broken.proto"),
+            "syntax = \"proto3\";\n\nmessage Broken {\n  Missing field = 1;\n}\n",
+        )
+        .unwrap();
+
+        let result = compile_proto_file(
+            CompilerBackend::Protox,
+            std::slice::from_ref(&directory),
+            &directory.join("broken.proto"),
+            &directory.join("broken.pb"),
+        );
+        let _ = std::fs::remove_dir_all(&directory);
+
+        let err = result.unwrap_err();
+        assert_eq!(err.file.as_deref(), Some("broken.proto"));
+        assert_eq!((err.line, err.column), (Some(4), Some(3)));
+        assert!(err.message.contains("Missing"), "{}", err.message);
+        assert!(err.to_string().starts_with("broken.proto:4:3: "), "{}", err);
+    }
+}
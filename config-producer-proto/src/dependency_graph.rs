@@ -0,0 +1,111 @@
+/* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
+
+use prost_types::FileDescriptorSet;
+use std::collections::{BTreeSet, HashMap};
+
+/// Which `.proto` files every compiled file imports, directly or through other imports. The
+/// imports are taken from the compiled descriptor set, which contains all of them because it is
+/// compiled with `--include_imports`.
+#[derive(Debug, Default)]
+pub struct DependencyGraph {
+    imports: HashMap<String, BTreeSet<String>>,
+}
+
+impl DependencyGraph {
+    pub fn update(&mut self, file_name: &str, file_descriptor_set: &FileDescriptorSet) {
+        let imports = file_descriptor_set
+            .file
+            .iter()
+            .map(|file| file.name().to_string())
+            .filter(|name| name != file_name)
+            .collect();
+        self.imports.insert(file_name.to_string(), imports);
+    }
+
+    pub fn remove(&mut self, file_name: &str) {
+        self.imports.remove(file_name);
+    }
+
+    /// Files that import `file_name`, directly or indirectly.
+    pub fn dependents(&self, file_name: &str) -> BTreeSet<String> {
+        self.imports
+            .iter()
+            .filter(|(_, imports)| imports.contains(file_name))
+            .map(|(dependent, _)| dependent.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protox::file::{File, FileResolver};
+
+    const SOURCES: [(&str, &str); 4] = [
+        ("common.proto", "syntax = \"proto3\"; message Money { int64 cents = 1; }"),
+        (
+            "item.proto",
+            "syntax = \"proto3\"; import \"common.proto\"; message Item { Money price = 1; }",
+        ),
+        (
+            "order.proto",
+            "syntax = \"proto3\"; import \"item.proto\"; message Order { repeated Item items = 1; }",
+        ),
+        ("customer.proto", "syntax = \"proto3\"; message Customer { string id = 1; }"),
+    ];
+
+    struct SourceResolver;
+
+    impl FileResolver for SourceResolver {
+        fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+            let (_, source) = SOURCES
+                .iter()
+                .find(|(file_name, _)| *file_name == name)
+                .ok_or_else(|| protox::Error::file_not_found(name))?;
+            File::from_source(name, source)
+        }
+    }
+
+    // The descriptor set of one file with its imports, as the producer compiles it.
+    fn compile(file_name: &str) -> FileDescriptorSet {
+        let mut compiler = protox::Compiler::with_file_resolver(SourceResolver);
+        compiler.include_imports(true).open_file(file_name).unwrap();
+        compiler.file_descriptor_set()
+    }
+
+    fn graph() -> DependencyGraph {
+        let mut graph = DependencyGraph::default();
+        for (file_name, _) in SOURCES {
+            graph.update(file_name, &compile(file_name));
+        }
+        graph
+    }
+
+    fn names(file_names: &[&str]) -> BTreeSet<String> {
+        file_names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_dependents_are_direct_and_transitive() {
+        let graph = graph();
+        assert_eq!(
+            graph.dependents("common.proto"),
+            names(&["item.proto", "order.proto"])
+        );
+        assert_eq!(graph.dependents("item.proto"), names(&["order.proto"]));
+        assert_eq!(graph.dependents("order.proto"), names(&[]));
+        assert_eq!(graph.dependents("customer.proto"), names(&[]));
+    }
+
+    #[test]
+    fn test_removed_file_is_no_dependent() {
+        let mut graph = graph();
+        graph.remove("order.proto");
+        assert_eq!(graph.dependents("common.proto"), names(&["item.proto"]));
+        assert_eq!(graph.dependents("item.proto"), names(&[]));
+
+        // A removed import still names the files that imported it.
+        graph.remove("common.proto");
+        assert_eq!(graph.dependents("common.proto"), names(&["item.proto"]));
+    }
+}
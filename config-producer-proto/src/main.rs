@@ -1,46 +1,95 @@
 /* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
 
 use anyhow::Result;
-use base64;
 use clap::Parser;
-use dotenvy;
-use prost::Message;
+use notify::{recommended_watcher, Event, EventKind, RecursiveMode, Watcher};
 use prost_types::FileDescriptorSet;
-use reqwest::{Client, StatusCode};
-use serde::{Deserialize, Serialize};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, StatusCode, Url};
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap, HashSet},
     env,
     error::Error,
     ffi::OsStr,
     path::{Path, PathBuf},
-    process::Command as StdCommand,
-    sync::Arc,
 };
 use tokio::fs;
-use tokio::sync::Mutex as TokioMutex;
+use tokio::sync::mpsc;
 use tokio::time::{sleep, Duration};
-use tracing::{error, info, span, Level};
+use tracing::{debug, error, info, span, Level};
 use walkdir::WalkDir;
 
+use compiler::{compile_proto_file, CompilerBackend};
 use data_quality_settings::{load_env_variables, load_logging_config, parse_log_level};
+use dependency_graph::DependencyGraph;
+
+mod compiler;
+mod dependency_graph;
+
+type ProtobufDefinitions = HashMap<String, (Vec<u8>, u64)>;
+
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
 
 #[derive(Parser, Debug)]
 #[command(version, about = "Proto Producer", long_about = None)]
 struct Args {
-    /// Run the program in a loop
-    #[arg(long, action(clap::ArgAction::SetTrue))]
-    loop_mode: bool,
+    /// Keep running and upload .proto files again whenever they change
+    // `--loop-mode` is the name this flag had before the watcher replaced the polling loop.
+    #[arg(long, alias = "loop-mode", action(clap::ArgAction::SetTrue))]
+    watch: bool,
 
-    /// Interval in seconds for each iteration if loop mode is enabled
-    #[arg(long, default_value = "10", value_parser = clap::value_parser!(u64))]
-    interval: u64,
+    /// Compiler used for .proto files
+    #[clap(long, value_enum, default_value_t = CompilerBackend::Protox)]
+    compiler: CompilerBackend,
+
+    /// Additional directory to resolve imports from, after the input directory
+    #[clap(long = "include-dir")]
+    include_dirs: Vec<PathBuf>,
 
     /// Logging level
     #[clap(short, long, default_value = "info")]
     log_level: String,
 }
 
+#[derive(Debug)]
+struct ProducerConfig {
+    proto_schema_input_dir: String,
+    include_dirs: Vec<PathBuf>,
+    compiler: CompilerBackend,
+    server_address: String,
+}
+
+/// What has been compiled and uploaded so far, kept between runs so only changes are uploaded.
+#[derive(Debug, Default)]
+struct ProducerState {
+    definitions: ProtobufDefinitions,
+    file_timestamps: HashMap<String, u64>,
+    dependency_graph: DependencyGraph,
+}
+
+/// The outcome of one pass over the `.proto` files.
+#[derive(Debug, Default)]
+struct ProtoFileChanges {
+    updated_files: Vec<String>,
+    removed_files: Vec<String>,
+}
+
+/// A `.proto` file found in the input directory or one of the include directories.
+#[derive(Debug)]
+struct FoundProtoFile {
+    file_name: String,
+    modified_time: u64,
+    // Only files of the input directory are compiled and uploaded, the others are only imported.
+    is_input: bool,
+}
+
+/// What a pass over the `.proto` files has to compile and remove.
+#[derive(Debug, Default, PartialEq)]
+struct ProtoFileDiff {
+    files_to_compile: BTreeSet<String>,
+    removed_files: Vec<String>,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli_args = Args::parse();
@@ -48,128 +97,155 @@ async fn main() -> Result<()> {
     let _ = load_logging_config(log_level);
     load_env_variables();
 
-    loop {
-        let span = span!(Level::INFO, "proto producer");
-        let _enter = span.enter();
+    let span = span!(Level::INFO, "proto producer");
+    let _enter = span.enter();
 
-        let proto_schema_input_dir = dotenvy::var("PROTO_SCHEMA_INPUT_DIR")?;
-        let server_ip = dotenvy::var("DATA_QUALITY_SERVER_IP_HOST")?;
-        let server_port = dotenvy::var("DATA_QUALITY_SERVER_PORT")?;
-        let server_address = format!("{}:{}", server_ip, server_port);
+    let server_ip = dotenvy::var("DATA_QUALITY_SERVER_IP_HOST")?;
+    let server_port = dotenvy::var("DATA_QUALITY_SERVER_PORT")?;
 
-        let protobuf_definitions: Arc<TokioMutex<HashMap<String, (Vec<u8>, u64)>>> =
-            Arc::new(TokioMutex::new(HashMap::new()));
-        let file_timestamps: Arc<TokioMutex<HashMap<String, u64>>> =
-            Arc::new(TokioMutex::new(HashMap::new()));
+    let mut include_dirs = cli_args.include_dirs.clone();
+    if let Some(env_include_dirs) = env::var_os("PROTO_SCHEMA_INCLUDE_DIRS") {
+        include_dirs.extend(env::split_paths(&env_include_dirs));
+    }
 
-        {
-            let mut definitions = protobuf_definitions.lock().await;
-            let mut timestamps = file_timestamps.lock().await;
-
-            match load_proto_files(&proto_schema_input_dir, &mut definitions, &mut timestamps).await
-            {
-                Ok(_) => info!("Successfully loaded proto files."),
-                Err(err) => {
-                    eprintln!("Error loading proto files: {}", err);
-                    continue;
-                }
-            }
-        }
+    let config = ProducerConfig {
+        proto_schema_input_dir: dotenvy::var("PROTO_SCHEMA_INPUT_DIR")?,
+        include_dirs,
+        compiler: cli_args.compiler,
+        server_address: format!("{}:{}", server_ip, server_port),
+    };
+    let mut state = ProducerState::default();
 
-        for (file_name, (file_content, _)) in &*protobuf_definitions.lock().await {
-            info!(
-                "Serializing and sending FileDescriptorSet for: {}",
-                file_name
-            );
+    sync_proto_files(&config, &mut state).await;
 
-            let fd_set_result = prost::Message::decode(file_content.as_slice());
-            let fd_set = match fd_set_result {
-                Ok(fd_set) => fd_set,
-                Err(err) => {
-                    eprintln!(
-                        "Failed to decode FileDescriptorSet for {}: {}",
-                        file_name, err
-                    );
-                    continue;
-                }
-            };
+    if cli_args.watch {
+        watch_proto_files(&config, &mut state).await?;
+    }
+
+    Ok(())
+}
+
+/// Watches the input and include directories and syncs once the events of a change have settled.
+async fn watch_proto_files(config: &ProducerConfig, state: &mut ProducerState) -> Result<()> {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut watcher = recommended_watcher(move |event: notify::Result<Event>| {
+        let _ = sender.send(event);
+    })?;
+
+    let watched_dirs = std::iter::once(Path::new(&config.proto_schema_input_dir))
+        .chain(config.include_dirs.iter().map(PathBuf::as_path));
+    for watched_dir in watched_dirs {
+        watcher.watch(watched_dir, RecursiveMode::Recursive)?;
+        info!("Watching {} for .proto changes", watched_dir.display());
+    }
 
-            let serialized_fd_set = serialize_file_descriptor_set(&fd_set);
-            let descriptor_server_url = format!("http://{}/load_descriptor", server_address);
-            info!("descriptor_server_url: {}", &descriptor_server_url);
-
-            match send_to_data_quality_server(
-                &descriptor_server_url,
-                &file_name,
-                &serialized_fd_set,
-            )
-            .await
-            {
-                Ok(success_message) => info!("{}", success_message),
-                Err(err) => eprintln!("Error sending FileDescriptorSet for {}: {}", file_name, err),
+    while let Some(event) = receiver.recv().await {
+        match event {
+            Ok(event) if is_proto_change(&event) => {
+                debug!("Proto directory event: {:?}", event.kind)
+            }
+            Ok(_) => continue,
+            Err(err) => {
+                error!("Error watching proto directories: {}", err);
+                continue;
             }
         }
 
-        if !cli_args.loop_mode {
-            break;
-        }
+        // Saving a file produces a burst of events; wait for it to settle.
+        sleep(WATCH_DEBOUNCE).await;
+        while receiver.try_recv().is_ok() {}
 
-        sleep(Duration::from_secs(cli_args.interval)).await;
+        sync_proto_files(config, state).await;
     }
 
     Ok(())
 }
 
-#[tracing::instrument]
-fn serialize_file_descriptor_set(fd_set: &FileDescriptorSet) -> Vec<u8> {
-    info!("serialize_file_descriptor_set");
-
-    let mut buf = Vec::new();
-    fd_set
-        .encode(&mut buf)
-        .expect("Failed to encode FileDescriptorSet");
-    buf
-}
-
-#[derive(Serialize, Deserialize)]
-struct LoadDescriptorRequest {
-    file_name: String,
-    file_content: String,
+// Directories have no extension; removing or renaming one can remove .proto files inside it.
+fn is_proto_change(event: &Event) -> bool {
+    !matches!(event.kind, EventKind::Access(_))
+        && event.paths.iter().any(|path| {
+            path.extension()
+                .map_or(true, |extension| extension == "proto")
+        })
 }
 
-async fn compile_with_protoc(
-    protoc_path: &str,
-    file: &Path,
-    output_file: &Path,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let span = span!(Level::INFO, "compile_with_protoc");
-    let _enter = span.enter();
-
-    let status = StdCommand::new(protoc_path)
-        .arg("--proto_path")
-        .arg(file.parent().unwrap().to_str().unwrap())
-        .arg("--descriptor_set_out")
-        .arg(output_file)
-        .arg(file)
-        .status()?;
+/// Compiles the changed `.proto` files and their dependents, uploads them in one request and
+/// removes the descriptors of deleted files from the server.
+async fn sync_proto_files(config: &ProducerConfig, state: &mut ProducerState) {
+    let changes = match load_proto_files(
+        &config.proto_schema_input_dir,
+        &config.include_dirs,
+        config.compiler,
+        &mut state.definitions,
+        &mut state.file_timestamps,
+        &mut state.dependency_graph,
+    )
+    .await
+    {
+        Ok(changes) => {
+            info!("Successfully loaded proto files.");
+            changes
+        }
+        Err(err) => {
+            error!("Error loading proto files: {}", err);
+            return;
+        }
+    };
 
-    if !status.success() {
-        return Err("Protoc compilation failed".into());
+    if !changes.updated_files.is_empty() {
+        let files: Vec<(&str, &[u8])> = changes
+            .updated_files
+            .iter()
+            .filter_map(|file_name| {
+                let (file_content, _) = state.definitions.get(file_name)?;
+                Some((file_name.as_str(), file_content.as_slice()))
+            })
+            .collect();
+
+        let descriptor_server_url = format!("http://{}/load_descriptor", config.server_address);
+        info!("descriptor_server_url: {}", &descriptor_server_url);
+
+        match send_to_data_quality_server(&descriptor_server_url, &files).await {
+            Ok(success_message) => info!("{}", success_message),
+            Err(err) => {
+                error!("Error sending FileDescriptorSets: {}", err);
+                // Forget the timestamps so the next change uploads these files again.
+                for file_name in &changes.updated_files {
+                    state.file_timestamps.remove(file_name);
+                }
+            }
+        }
     }
 
-    Ok(())
+    for file_name in &changes.removed_files {
+        if let Err(err) = delete_from_data_quality_server(&config.server_address, file_name).await {
+            error!("Error removing descriptor {}: {}", file_name, err);
+        }
+    }
 }
 
 #[tracing::instrument]
-fn generate_output_file(file: &Path, proto_output_dir: &Path) -> PathBuf {
+fn generate_output_file(file_name: &str, proto_output_dir: &Path) -> PathBuf {
     info!("generate_output_file");
 
     proto_output_dir.join(format!(
         "{}.pb",
-        file.file_stem().unwrap().to_str().unwrap()
+        file_name.strip_suffix(".proto").unwrap_or(file_name)
     ))
 }
 
+// Files are named by their path relative to the include directory they were found in, which is
+// also the name used to import them.
+fn proto_file_name(include_dir: &Path, file: &Path) -> Option<String> {
+    let relative_path = file.strip_prefix(include_dir).ok()?;
+    let components: Option<Vec<&str>> = relative_path
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect();
+    Some(components?.join("/"))
+}
+
 #[tracing::instrument]
 async fn get_modified_time(file: &Path) -> Result<u64, Box<dyn std::error::Error>> {
     let span = span!(Level::INFO, "get_modified_time");
@@ -179,7 +255,7 @@ async fn get_modified_time(file: &Path) -> Result<u64, Box<dyn std::error::Error
     let modified_time = metadata
         .modified()?
         .duration_since(std::time::UNIX_EPOCH)?
-        .as_secs();
+        .as_millis() as u64;
     Ok(modified_time)
 }
 
@@ -197,12 +273,18 @@ fn get_proto_files_in_directory(
         .collect()
 }
 
+/// Compiles the `.proto` files in the input directory that are new or changed, together with
+/// every file that imports a changed or deleted file. Files in the additional include directories
+/// are only compiled as imports. Deleted input files are forgotten and returned as removed.
 #[tracing::instrument]
 async fn load_proto_files(
     proto_schema_input_dir: &str,
+    include_dirs: &[PathBuf],
+    compiler: CompilerBackend,
     definitions: &mut HashMap<String, (Vec<u8>, u64)>,
     file_timestamps: &mut HashMap<String, u64>,
-) -> Result<(), Box<dyn std::error::Error>> {
+    dependency_graph: &mut DependencyGraph,
+) -> Result<ProtoFileChanges, Box<dyn std::error::Error>> {
     let span = span!(Level::INFO, "load_proto_files");
     let _enter = span.enter();
 
@@ -210,39 +292,140 @@ async fn load_proto_files(
     let proto_output_dir = resolve_relative_path(&env::var("PROTO_SCHEMA_GENPB_DIR")?)?;
     fs::create_dir_all(&proto_output_dir).await?;
 
-    let protoc_path = env::var("PROTOC_PATH")?;
-    let mut updated_files = Vec::new();
+    let mut import_roots = vec![proto_schema_input_dir.clone()];
+    for include_dir in include_dirs {
+        import_roots.push(resolve_relative_path(&include_dir.to_string_lossy())?);
+    }
+
+    let mut seen_files = HashSet::new();
+    let mut input_files = HashMap::new();
+    let mut found_files = Vec::new();
+    for (root_index, import_root) in import_roots.iter().enumerate() {
+        for file in get_proto_files_in_directory(import_root)? {
+            let Some(file_name) = proto_file_name(import_root, &file) else {
+                continue;
+            };
+            // A file shadowed by an earlier import root is never imported.
+            if !seen_files.insert(file_name.clone()) {
+                continue;
+            }
+            found_files.push(FoundProtoFile {
+                file_name: file_name.clone(),
+                modified_time: get_modified_time(&file).await?,
+                is_input: root_index == 0,
+            });
+            if root_index == 0 {
+                input_files.insert(file_name, file);
+            }
+        }
+    }
 
-    for file in get_proto_files_in_directory(&proto_schema_input_dir)? {
-        let metadata = file.metadata()?;
-        let modified_time = metadata
-            .modified()?
-            .duration_since(std::time::UNIX_EPOCH)?
-            .as_secs();
-
-        let file_name = file.file_name().unwrap().to_string_lossy().into_owned();
-
-        if file_timestamps.get(&file_name).copied() != Some(modified_time) {
-            info!("File changed or new: {:?}", file);
-
-            if let Err(e) = process_proto_file(
-                &file,
-                &proto_output_dir,
-                &protoc_path,
-                definitions,
-                &mut updated_files,
-            )
-            .await
-            {
-                error!("Error processing file {:?}: {}", file, e);
-            } else {
-                file_timestamps.insert(file_name, modified_time);
+    let ProtoFileDiff {
+        files_to_compile,
+        removed_files,
+    } = diff_proto_files(&found_files, definitions, file_timestamps, dependency_graph);
+    for file_name in &removed_files {
+        let output_file = generate_output_file(file_name, &proto_output_dir);
+        if let Err(err) = fs::remove_file(&output_file).await {
+            error!("Error removing {:?}: {}", output_file, err);
+        }
+    }
+
+    let mut updated_files = Vec::new();
+    for file_name in files_to_compile {
+        let Some(file) = input_files.get(&file_name) else {
+            continue;
+        };
+        match process_proto_file(
+            file,
+            &file_name,
+            &proto_output_dir,
+            &import_roots,
+            compiler,
+            definitions,
+            dependency_graph,
+        )
+        .await
+        {
+            Ok(modified_time) => {
+                file_timestamps.insert(file_name.clone(), modified_time);
+                updated_files.push(file_name);
             }
+            Err(e) => error!("Error processing file {:?}: {}", file, e),
         }
     }
 
     log_updated_files(&updated_files, definitions);
-    Ok(())
+    Ok(ProtoFileChanges {
+        updated_files,
+        removed_files,
+    })
+}
+
+/// Compares `found_files` with the files seen in earlier passes. Input files that are new or
+/// changed are compiled, together with every file that imports a changed or deleted file. Deleted
+/// files are forgotten; the uploaded ones among them are returned as removed, dependents first.
+/// Input files are only recorded as seen once they compiled, so a failed file is tried again.
+fn diff_proto_files(
+    found_files: &[FoundProtoFile],
+    definitions: &mut ProtobufDefinitions,
+    file_timestamps: &mut HashMap<String, u64>,
+    dependency_graph: &mut DependencyGraph,
+) -> ProtoFileDiff {
+    let mut changed_files = Vec::new();
+    for found_file in found_files {
+        if file_timestamps.get(&found_file.file_name).copied() != Some(found_file.modified_time) {
+            info!("File changed or new: {}", found_file.file_name);
+            changed_files.push(found_file.file_name.clone());
+            if !found_file.is_input {
+                file_timestamps.insert(found_file.file_name.clone(), found_file.modified_time);
+            }
+        }
+    }
+
+    let found_names: HashSet<&str> = found_files
+        .iter()
+        .map(|found_file| found_file.file_name.as_str())
+        .collect();
+    let mut deleted_files: Vec<String> = file_timestamps
+        .keys()
+        .filter(|file_name| !found_names.contains(file_name.as_str()))
+        .cloned()
+        .collect();
+    deleted_files.sort();
+    // Dependents first, as the server refuses to remove a descriptor that others still import.
+    deleted_files.sort_by_key(|file_name| dependency_graph.dependents(file_name).len());
+
+    let mut removed_files = Vec::new();
+    for file_name in deleted_files {
+        info!("File removed: {}", file_name);
+        file_timestamps.remove(&file_name);
+        dependency_graph.remove(&file_name);
+        // Files of the include directories were never uploaded, so there is nothing to remove.
+        if definitions.remove(&file_name).is_some() {
+            removed_files.push(file_name.clone());
+        }
+        changed_files.push(file_name);
+    }
+
+    let mut files_to_compile = BTreeSet::new();
+    for file_name in &changed_files {
+        if found_files
+            .iter()
+            .any(|found_file| found_file.is_input && found_file.file_name == *file_name)
+        {
+            files_to_compile.insert(file_name.clone());
+        }
+        for dependent in dependency_graph.dependents(file_name) {
+            info!("{} imports changed file {}", dependent, file_name);
+            files_to_compile.insert(dependent);
+        }
+    }
+
+    ProtoFileDiff {
+        files_to_compile,
+        removed_files,
+    }
 }
 
 #[tracing::instrument]
@@ -258,34 +441,31 @@ fn log_updated_files(updated_files: &[String], definitions: &HashMap<String, (Ve
 #[tracing::instrument]
 async fn process_proto_file(
     file: &Path,
+    file_name: &str,
     proto_output_dir: &Path,
-    protoc_path: &str,
+    import_roots: &[PathBuf],
+    compiler: CompilerBackend,
     definitions: &mut HashMap<String, (Vec<u8>, u64)>,
-    updated_files: &mut Vec<String>,
-) -> Result<(), Box<dyn std::error::Error>> {
+    dependency_graph: &mut DependencyGraph,
+) -> Result<u64, Box<dyn std::error::Error>> {
     let span = span!(Level::INFO, "process_proto_file");
     let _enter = span.enter();
 
-    let output_file = generate_output_file(file, proto_output_dir);
-    compile_with_protoc(protoc_path, file, &output_file).await?;
+    let output_file = generate_output_file(file_name, proto_output_dir);
+    if let Some(output_dir) = output_file.parent() {
+        fs::create_dir_all(output_dir).await?;
+    }
+    compile_proto_file(compiler, import_roots, file, &output_file)?;
 
     let file_content = fs::read(&output_file).await?;
-
-    let file_name = file.file_name().unwrap().to_string_lossy().into_owned();
+    let file_descriptor_set: FileDescriptorSet = prost::Message::decode(file_content.as_slice())?;
+    dependency_graph.update(file_name, &file_descriptor_set);
 
     let modified_time = get_modified_time(file).await?;
+    info!("File compiled: {}", file_name);
+    definitions.insert(file_name.to_string(), (file_content, modified_time));
 
-    if definitions
-        .entry(file_name.clone())
-        .or_insert((file_content.clone(), modified_time))
-        .1
-        != modified_time
-    {
-        info!("File changed: {}", file_name);
-        updated_files.push(file_name.clone());
-    }
-
-    Ok(())
+    Ok(modified_time)
 }
 
 #[tracing::instrument]
@@ -300,38 +480,260 @@ fn resolve_relative_path(path: &str) -> Result<PathBuf, Box<dyn std::error::Erro
     })
 }
 
-#[tracing::instrument]
+/// Uploads the descriptor sets in one multipart request, so the server applies them together.
+#[tracing::instrument(skip(files))]
 async fn send_to_data_quality_server(
     url: &str,
-    file_name: &str,
-    data: &[u8],
+    files: &[(&str, &[u8])],
 ) -> Result<String, Box<dyn Error>> {
     let span = span!(Level::INFO, "send_to_axum_server");
     let _enter = span.enter();
 
     let client = Client::new();
 
-    let payload = LoadDescriptorRequest {
-        file_name: file_name.to_string(),
-        file_content: base64::encode(data),
-    };
+    let mut form = Form::new();
+    for (file_name, file_content) in files {
+        let part_name = format!(
+            "{}.pb",
+            file_name.strip_suffix(".proto").unwrap_or(file_name)
+        );
+        form = form.part(
+            "files",
+            Part::bytes(file_content.to_vec()).file_name(part_name),
+        );
+    }
+    let file_names: Vec<&str> = files.iter().map(|(file_name, _)| *file_name).collect();
 
-    let response = client.post(url).json(&payload).send().await?;
+    let response = client.post(url).multipart(form).send().await?;
 
     if response.status() == StatusCode::OK {
-        let success_message = format!("Successfully sent FileDescriptorSet for: {}", file_name);
+        let success_message = format!(
+            "Successfully sent FileDescriptorSets for: {}",
+            file_names.join(", ")
+        );
         info!("{}", success_message);
         Ok(success_message)
     } else {
+        let status = response.status();
         let error_message = format!(
-            "Failed to send FileDescriptorSet for {}: {:?}",
-            file_name,
-            response.status()
+            "Failed to send FileDescriptorSets for {}: {:?} {}",
+            file_names.join(", "),
+            status,
+            response.text().await.unwrap_or_default()
         );
         error!("{}", error_message);
-        Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            error_message,
-        )))
+        Err(Box::new(std::io::Error::other(error_message)))
+    }
+}
+
+#[tracing::instrument]
+async fn delete_from_data_quality_server(
+    server_address: &str,
+    file_name: &str,
+) -> Result<String, Box<dyn Error>> {
+    let mut url = Url::parse(&format!("http://{}/descriptors", server_address))?;
+    // A single path segment, so the slashes in nested file names are escaped.
+    url.path_segments_mut()
+        .map_err(|_| std::io::Error::other("Invalid server address"))?
+        .push(file_name);
+
+    let response = Client::new().delete(url).send().await?;
+
+    match response.status() {
+        StatusCode::OK | StatusCode::NOT_FOUND => {
+            let success_message = format!("Removed descriptor {}", file_name);
+            info!("{}", success_message);
+            Ok(success_message)
+        }
+        status => {
+            let error_message = format!(
+                "Failed to remove descriptor {}: {:?} {}",
+                file_name,
+                status,
+                response.text().await.unwrap_or_default()
+            );
+            error!("{}", error_message);
+            Err(Box::new(std::io::Error::other(error_message)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{AccessKind, CreateKind, ModifyKind, RemoveKind};
+    use prost_types::FileDescriptorProto;
+
+    fn event(kind: EventKind, path: &str) -> Event {
+        Event::new(kind).add_path(PathBuf::from(path))
+    }
+
+    #[test]
+    fn test_is_proto_change() {
+        let modify = EventKind::Modify(ModifyKind::Any);
+        assert!(is_proto_change(&event(modify, "/protos/order.proto")));
+        assert!(is_proto_change(&event(
+            EventKind::Create(CreateKind::File),
+            "/protos/shop/item.proto"
+        )));
+        assert!(is_proto_change(&event(
+            EventKind::Remove(RemoveKind::Folder),
+            "/protos/shop"
+        )));
+        assert!(!is_proto_change(&event(modify, "/protos/notes.txt")));
+        assert!(!is_proto_change(&event(modify, "/protos/order.proto.swp")));
+        assert!(!is_proto_change(&event(
+            EventKind::Access(AccessKind::Any),
+            "/protos/order.proto"
+        )));
+    }
+
+    fn found(file_name: &str, modified_time: u64, is_input: bool) -> FoundProtoFile {
+        FoundProtoFile {
+            file_name: file_name.to_string(),
+            modified_time,
+            is_input,
+        }
+    }
+
+    fn names(file_names: &[&str]) -> BTreeSet<String> {
+        file_names.iter().map(|name| name.to_string()).collect()
+    }
+
+    /// Records `file_name` as compiled and uploaded, the way `load_proto_files` does.
+    fn compiled(state: &mut ProducerState, file_name: &str, modified_time: u64, imports: &[&str]) {
+        let file_descriptor_set = FileDescriptorSet {
+            file: std::iter::once(file_name)
+                .chain(imports.iter().copied())
+                .map(|name| FileDescriptorProto {
+                    name: Some(name.to_string()),
+                    ..FileDescriptorProto::default()
+                })
+                .collect(),
+        };
+        state
+            .dependency_graph
+            .update(file_name, &file_descriptor_set);
+        state
+            .definitions
+            .insert(file_name.to_string(), (Vec::new(), modified_time));
+        state
+            .file_timestamps
+            .insert(file_name.to_string(), modified_time);
+    }
+
+    fn diff(state: &mut ProducerState, found_files: &[FoundProtoFile]) -> ProtoFileDiff {
+        diff_proto_files(
+            found_files,
+            &mut state.definitions,
+            &mut state.file_timestamps,
+            &mut state.dependency_graph,
+        )
+    }
+
+    // `item.proto` and `order.proto` are input files, both importing `common.proto` from an include
+    // directory; `order.proto` also imports `item.proto`.
+    fn synced_state() -> ProducerState {
+        let mut state = ProducerState::default();
+        let found_files = [
+            found("item.proto", 1, true),
+            found("order.proto", 1, true),
+            found("common.proto", 1, false),
+        ];
+        assert_eq!(
+            diff(&mut state, &found_files),
+            ProtoFileDiff {
+                files_to_compile: names(&["item.proto", "order.proto"]),
+                removed_files: Vec::new(),
+            }
+        );
+        compiled(&mut state, "item.proto", 1, &["common.proto"]);
+        compiled(
+            &mut state,
+            "order.proto",
+            1,
+            &["item.proto", "common.proto"],
+        );
+        assert_eq!(diff(&mut state, &found_files), ProtoFileDiff::default());
+        state
+    }
+
+    #[test]
+    fn test_changed_import_recompiles_dependents() {
+        let mut state = synced_state();
+        let changes = diff(
+            &mut state,
+            &[
+                found("item.proto", 1, true),
+                found("order.proto", 1, true),
+                found("common.proto", 2, false),
+            ],
+        );
+        assert_eq!(
+            changes.files_to_compile,
+            names(&["item.proto", "order.proto"])
+        );
+        assert!(changes.removed_files.is_empty());
+
+        let mut state = synced_state();
+        let changes = diff(
+            &mut state,
+            &[
+                found("item.proto", 2, true),
+                found("order.proto", 1, true),
+                found("common.proto", 1, false),
+            ],
+        );
+        assert_eq!(
+            changes.files_to_compile,
+            names(&["item.proto", "order.proto"])
+        );
+    }
+
+    #[test]
+    fn test_removed_file_is_deleted() {
+        let mut state = synced_state();
+        let changes = diff(
+            &mut state,
+            &[
+                found("item.proto", 1, true),
+                found("common.proto", 1, false),
+            ],
+        );
+        assert_eq!(
+            changes,
+            ProtoFileDiff {
+                files_to_compile: BTreeSet::new(),
+                removed_files: vec!["order.proto".to_string()],
+            }
+        );
+        assert!(!state.definitions.contains_key("order.proto"));
+        assert!(!state.file_timestamps.contains_key("order.proto"));
+        assert!(state.dependency_graph.dependents("item.proto").is_empty());
+    }
+
+    #[test]
+    fn test_removed_files_are_deleted_dependents_first() {
+        let mut state = synced_state();
+        let changes = diff(&mut state, &[found("common.proto", 1, false)]);
+        assert_eq!(changes.removed_files, vec!["order.proto", "item.proto"]);
+    }
+
+    #[test]
+    fn test_removed_import_recompiles_dependents() {
+        let mut state = synced_state();
+        let changes = diff(
+            &mut state,
+            &[found("item.proto", 1, true), found("order.proto", 1, true)],
+        );
+        // The import was never uploaded; its dependents are compiled again to report the error.
+        assert_eq!(
+            changes,
+            ProtoFileDiff {
+                files_to_compile: names(&["item.proto", "order.proto"]),
+                removed_files: Vec::new(),
+            }
+        );
+        assert!(!state.file_timestamps.contains_key("common.proto"));
     }
 }
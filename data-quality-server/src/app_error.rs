@@ -1,22 +1,91 @@
 /* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
 
-use axum::{extract::Json, http::StatusCode, response::IntoResponse};
+use axum::{
+    extract::{rejection::JsonRejection, Json},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use dynamic_message::PopulateError;
+
+use crate::protobuf_descriptors::LoadReport;
+use crate::rule_engine::RuleResult;
+use crate::schema_compatibility::{CompatibilityMode, SchemaChange};
 use serde_json::json;
 
 use thiserror::Error;
-use tracing::{debug, error, info, span, Level, trace, warn};
+use tracing::trace;
 
 #[derive(Error, Debug)]
 pub enum AppError {
     #[error("Failed to load descriptor")]
     LoadDescriptorError(#[source] std::io::Error),
 
+    #[error("Invalid descriptor: {0}")]
+    DescriptorError(String),
+
+    #[error("Failed to load descriptors: {}", .0.failed_file_names().join(", "))]
+    DescriptorLoadFailed(LoadReport),
+
+    #[error(
+        "Failed to compile .proto source{}: {message}",
+        source_location(file, line, column)
+    )]
+    ProtoCompileError {
+        file: Option<String>,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String,
+    },
+
+    #[error(
+        "Descriptor '{}' is not {compatibility} compatible with its previous version",
+        .file_names.join("', '")
+    )]
+    IncompatibleSchema {
+        file_names: Vec<String>,
+        compatibility: CompatibilityMode,
+        changes: Vec<SchemaChange>,
+    },
+
+    #[error("Descriptor '{0}' not found")]
+    DescriptorNotFound(String),
+
+    #[error("Validation profile '{0}' not found")]
+    ProfileNotFound(String),
+
+    #[error("Validation profile '{0}' already exists")]
+    ProfileExists(String),
+
+    #[error("Message '{0}' not found in descriptor pool")]
+    MessageNotFound(String),
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
     #[error("Failed to parse JSON: {0}")]
     JsonParseError(#[source] serde_json::Error),
 
     #[error("Failed to unescape JSON: {0}")]
     JsonUnescapeError(String),
 
+    #[error("JSON does not match the protobuf definition: {} violation(s)", .0.len())]
+    PopulationError(Vec<PopulateError>),
+
+    #[error("Failed to serialize message: {0}")]
+    SerializationError(String),
+
+    #[error("Field check failed: {0}")]
+    FieldCheckError(String),
+
+    #[error("{} of {} rule(s) failed", .0.iter().filter(|result| !result.passed).count(), .0.len())]
+    RuleCheckFailed(Vec<RuleResult>),
+
+    #[error("Too many concurrent requests, please try again later")]
+    ServiceUnavailable,
+
+    #[error("Failed to access the descriptor store: {0}")]
+    StorageError(String),
+
     #[error("Missing environment variable: {0}")]
     MissingEnvVarError(String),
 
@@ -28,19 +97,112 @@ impl AppError {
     pub fn to_status_code(&self) -> StatusCode {
         match self {
             AppError::JsonUnescapeError(_) => StatusCode::BAD_REQUEST,
+            AppError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
             AppError::JsonParseError(_) => StatusCode::BAD_REQUEST,
             AppError::LoadDescriptorError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::DescriptorError(_) => StatusCode::BAD_REQUEST,
+            AppError::DescriptorLoadFailed(_) => StatusCode::BAD_REQUEST,
+            AppError::ProtoCompileError { .. } => StatusCode::BAD_REQUEST,
+            AppError::IncompatibleSchema { .. } => StatusCode::CONFLICT,
+            AppError::DescriptorNotFound(_) => StatusCode::NOT_FOUND,
+            AppError::ProfileNotFound(_) => StatusCode::NOT_FOUND,
+            AppError::ProfileExists(_) => StatusCode::CONFLICT,
+            AppError::MessageNotFound(_) => StatusCode::NOT_FOUND,
+            AppError::PopulationError(_) => StatusCode::BAD_REQUEST,
+            AppError::SerializationError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::FieldCheckError(_) => StatusCode::BAD_REQUEST,
+            AppError::RuleCheckFailed(_) => StatusCode::BAD_REQUEST,
+            AppError::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::StorageError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::MissingEnvVarError(_) => StatusCode::BAD_REQUEST,
             AppError::UnknownError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    /// Machine-readable code returned in the `code` field of error responses. These are part of
+    /// the API and must not change.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::LoadDescriptorError(_) => "load_descriptor_error",
+            AppError::DescriptorError(_) => "descriptor_error",
+            AppError::DescriptorLoadFailed(_) => "descriptor_load_failed",
+            AppError::ProtoCompileError { .. } => "proto_compile_error",
+            AppError::IncompatibleSchema { .. } => "incompatible_schema",
+            AppError::DescriptorNotFound(_) => "descriptor_not_found",
+            AppError::ProfileNotFound(_) => "profile_not_found",
+            AppError::ProfileExists(_) => "profile_exists",
+            AppError::MessageNotFound(_) => "message_not_found",
+            AppError::InvalidRequest(_) => "invalid_request",
+            AppError::JsonParseError(_) => "json_parse_error",
+            AppError::JsonUnescapeError(_) => "json_unescape_error",
+            AppError::PopulationError(_) => "population_error",
+            AppError::SerializationError(_) => "serialization_error",
+            AppError::FieldCheckError(_) => "field_check_error",
+            AppError::RuleCheckFailed(_) => "rule_check_failed",
+            AppError::ServiceUnavailable => "service_unavailable",
+            AppError::StorageError(_) => "storage_error",
+            AppError::MissingEnvVarError(_) => "missing_env_var",
+            AppError::UnknownError(_) => "unknown_error",
+        }
+    }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> axum::response::Response {
-        trace!("Converting AppError to response with status code: {:?}", self.to_status_code());
+        trace!(
+            "Converting AppError to response with status code: {:?}",
+            self.to_status_code()
+        );
         let status_code = self.to_status_code();
-        let body = Json(json!({ "error": self.to_string() }));
+        let body = match &self {
+            AppError::PopulationError(violations) => Json(json!({
+                "code": self.code(),
+                "error": self.to_string(),
+                "violations": violations,
+            })),
+            AppError::DescriptorLoadFailed(report) => Json(json!({
+                "code": self.code(),
+                "error": self.to_string(),
+                "failed": report.failed,
+                "unresolved_imports": report.unresolved_imports,
+            })),
+            AppError::ProtoCompileError {
+                file, line, column, ..
+            } => Json(json!({
+                "code": self.code(),
+                "error": self.to_string(),
+                "file": file,
+                "line": line,
+                "column": column,
+            })),
+            AppError::RuleCheckFailed(rule_results) => Json(json!({
+                "code": self.code(),
+                "error": self.to_string(),
+                "rules": rule_results,
+            })),
+            AppError::IncompatibleSchema { changes, .. } => Json(json!({
+                "code": self.code(),
+                "error": self.to_string(),
+                "changes": changes,
+            })),
+            _ => Json(json!({ "code": self.code(), "error": self.to_string() })),
+        };
         (status_code, body).into_response()
     }
 }
+
+// Malformed request bodies, including rules, field paths and profiles that fail to deserialize,
+// are answered like any other invalid request instead of with axum's plain-text 422.
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        AppError::InvalidRequest(rejection.body_text())
+    }
+}
+
+fn source_location(file: &Option<String>, line: &Option<usize>, column: &Option<usize>) -> String {
+    match (file, line, column) {
+        (Some(file), Some(line), Some(column)) => format!(" {}:{}:{}", file, line, column),
+        (Some(file), _, _) => format!(" {}", file),
+        _ => String::new(),
+    }
+}
@@ -0,0 +1,298 @@
+/* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
+
+use anyhow::{Context, Result};
+use notify::{recommended_watcher, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+use walkdir::WalkDir;
+
+use crate::app_error::AppError;
+use crate::descriptor_registry::{register_descriptors, unregister_descriptor};
+use crate::protobuf_descriptors::descriptor_file_name;
+use crate::AppState;
+
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Reads every `.pb` file in `descriptor_dir` and its subdirectories. Files are named after the
+/// `.proto` file they were compiled from, relative to `descriptor_dir`, the same way
+/// `config-producer-proto` names its uploads: `dq/rules.pb` is registered as `dq/rules.proto`.
+pub fn read_descriptor_dir(descriptor_dir: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut files = Vec::new();
+
+    for dir_entry in WalkDir::new(descriptor_dir).follow_links(true) {
+        let dir_entry = dir_entry.with_context(|| {
+            format!(
+                "Failed to read descriptor directory {}",
+                descriptor_dir.display()
+            )
+        })?;
+        let path = dir_entry.path();
+        if !dir_entry.file_type().is_file()
+            || path.extension().and_then(|e| e.to_str()) != Some("pb")
+        {
+            continue;
+        }
+        let Some(file_name) = relative_file_name(descriptor_dir, path) else {
+            warn!(
+                "Skipping descriptor with a non UTF-8 name: {}",
+                path.display()
+            );
+            continue;
+        };
+        let file_content =
+            fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        files.push((descriptor_file_name(&file_name), file_content));
+    }
+
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(files)
+}
+
+// `path` relative to `descriptor_dir`, with `/` separators like `.proto` imports.
+fn relative_file_name(descriptor_dir: &Path, path: &Path) -> Option<String> {
+    let components = path
+        .strip_prefix(descriptor_dir)
+        .ok()?
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+    Some(components.join("/"))
+}
+
+/// Registers the descriptors found in `descriptor_dir` as one batch and returns the names of the
+/// ones that are now loaded. Files that fail to load against the registered descriptors, or that
+/// are not compatible with their previous version, are left out and the rest is registered again.
+pub async fn load_descriptor_dir(state: &AppState, descriptor_dir: &Path) -> Result<Vec<String>> {
+    info!("Loading descriptors from {}", descriptor_dir.display());
+    let mut files = read_descriptor_dir(descriptor_dir)?;
+
+    let mut loaded_files = Vec::new();
+    while !files.is_empty() {
+        let err =
+            match register_descriptors(state, files.clone(), state.default_compatibility).await {
+                Ok(outcomes) => {
+                    loaded_files = outcomes
+                        .into_iter()
+                        .map(|(file_name, _)| file_name)
+                        .collect();
+                    break;
+                }
+                Err(err) => err,
+            };
+        error!(
+            "Failed to register the descriptors of {}: {}",
+            descriptor_dir.display(),
+            err
+        );
+        let rejected_files: HashSet<String> = match err {
+            AppError::DescriptorLoadFailed(report) => {
+                report.failed_file_names().into_iter().collect()
+            }
+            AppError::IncompatibleSchema { file_names, .. } => file_names.into_iter().collect(),
+            _ => break,
+        };
+
+        let file_count = files.len();
+        files.retain(|(file_name, _)| !rejected_files.contains(file_name));
+        // Only registered descriptors failed, so leaving out files of the directory cannot help.
+        if files.len() == file_count {
+            break;
+        }
+    }
+
+    info!(
+        "Loaded {} descriptors from {}",
+        loaded_files.len(),
+        descriptor_dir.display()
+    );
+    Ok(loaded_files)
+}
+
+/// Reloads `descriptor_dir` whenever its contents change. Descriptors whose file disappears are
+/// removed again. The returned watcher must be kept alive for as long as the directory is watched.
+pub fn watch_descriptor_dir(
+    state: AppState,
+    descriptor_dir: PathBuf,
+    loaded_files: Vec<String>,
+) -> Result<RecommendedWatcher> {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut watcher = recommended_watcher(move |event: notify::Result<Event>| {
+        let _ = sender.send(event);
+    })?;
+    watcher.watch(&descriptor_dir, RecursiveMode::Recursive)?;
+    info!(
+        "Watching {} for descriptor changes",
+        descriptor_dir.display()
+    );
+
+    tokio::spawn(async move {
+        let mut known_files: HashSet<String> = loaded_files.into_iter().collect();
+
+        while let Some(event) = receiver.recv().await {
+            match event {
+                // Reading the directory produces access events of its own.
+                Ok(event) if matches!(event.kind, EventKind::Access(_)) => continue,
+                Ok(event) => debug!("Descriptor directory event: {:?}", event.kind),
+                Err(err) => {
+                    error!("Error watching descriptor directory: {}", err);
+                    continue;
+                }
+            }
+
+            // Writing a file produces a burst of events; wait for it to settle.
+            tokio::time::sleep(WATCH_DEBOUNCE).await;
+            while receiver.try_recv().is_ok() {}
+
+            match reload_descriptor_dir(&state, &descriptor_dir, &known_files).await {
+                Ok(registered_files) => known_files = registered_files,
+                Err(err) => error!("{:#}", err),
+            }
+        }
+    });
+
+    Ok(watcher)
+}
+
+/// Brings the registered descriptors in line with `descriptor_dir` after it changed: descriptors
+/// in `known_files` whose file is gone are removed and the directory is loaded again. Returns the
+/// files of the directory that are registered afterwards.
+pub async fn reload_descriptor_dir(
+    state: &AppState,
+    descriptor_dir: &Path,
+    known_files: &HashSet<String>,
+) -> Result<HashSet<String>> {
+    let present_files: HashSet<String> = read_descriptor_dir(descriptor_dir)?
+        .into_iter()
+        .map(|(file_name, _)| file_name)
+        .collect();
+    for file_name in known_files.difference(&present_files) {
+        if let Err(err) = unregister_descriptor(state, file_name).await {
+            error!("Failed to remove descriptor {}: {}", file_name, err);
+        }
+    }
+
+    if let Err(err) = load_descriptor_dir(state, descriptor_dir).await {
+        error!("{:#}", err);
+    }
+    // A file that failed to reload still has its previous version registered.
+    let descriptor_map = state.descriptor_map.read().await;
+    Ok(present_files
+        .into_iter()
+        .filter(|file_name| descriptor_map.contains_key(file_name))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor_store::MemoryStore;
+    use crate::proto_compiler::compile_proto_sources;
+    use crate::test_support::{test_state, TestDirectory};
+    use prost_reflect::DescriptorPool;
+    use std::sync::Arc;
+
+    const ITEM: &str = "syntax = \"proto3\"; package shop; message Item { string sku = 1; }";
+    const ORDER: &str = "syntax = \"proto3\"; package shop; import \"shop/item.proto\"; \
+                         message Order { repeated Item items = 1; }";
+    const CUSTOMER: &str = "syntax = \"proto3\"; package shop; message Customer { string id = 1; }";
+
+    /// Compiles `sources` and writes each descriptor set to `directory` as `<name>.pb`.
+    fn write_descriptors(directory: &Path, sources: &[(&str, &str)]) {
+        let sources = sources
+            .iter()
+            .map(|(file_name, source)| (file_name.to_string(), source.to_string()))
+            .collect();
+        for (file_name, file_content) in
+            compile_proto_sources(sources, &DescriptorPool::global()).unwrap()
+        {
+            let path = directory.join(file_name.replace(".proto", ".pb"));
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, file_content).unwrap();
+        }
+    }
+
+    async fn registered_files(state: &AppState) -> Vec<String> {
+        let mut file_names: Vec<String> =
+            state.descriptor_map.read().await.keys().cloned().collect();
+        file_names.sort();
+        file_names
+    }
+
+    #[test]
+    fn test_read_descriptor_dir_names_files_relative_to_the_root() {
+        let directory = TestDirectory::new();
+        fs::create_dir_all(directory.0.join("nested/deeper")).unwrap();
+        fs::write(directory.0.join("top.pb"), b"top").unwrap();
+        fs::write(directory.0.join("nested/deeper/inner.pb"), b"inner").unwrap();
+        fs::write(directory.0.join("nested/notes.txt"), b"not a descriptor").unwrap();
+
+        assert_eq!(
+            read_descriptor_dir(&directory.0).unwrap(),
+            vec![
+                ("nested/deeper/inner.proto".to_string(), b"inner".to_vec()),
+                ("top.proto".to_string(), b"top".to_vec()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_descriptor_dir_skips_rejected_files() {
+        let directory = TestDirectory::new();
+        write_descriptors(
+            &directory.0,
+            &[("shop/item.proto", ITEM), ("shop/order.proto", ORDER)],
+        );
+        fs::write(directory.0.join("broken.pb"), b"\x0a\x05abc").unwrap();
+        let state = test_state(Arc::new(MemoryStore));
+
+        let loaded_files = load_descriptor_dir(&state, &directory.0).await.unwrap();
+
+        assert_eq!(loaded_files, vec!["shop/item.proto", "shop/order.proto"]);
+        assert_eq!(
+            registered_files(&state).await,
+            vec!["shop/item.proto", "shop/order.proto"]
+        );
+        let pool = state.descriptor_pool.load();
+        assert!(pool
+            .descriptor_pool
+            .get_message_by_name("shop.Order")
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_reload_descriptor_dir_adds_and_removes_files() {
+        let directory = TestDirectory::new();
+        write_descriptors(
+            &directory.0,
+            &[("shop/item.proto", ITEM), ("shop/order.proto", ORDER)],
+        );
+        let state = test_state(Arc::new(MemoryStore));
+        let known_files: HashSet<String> = load_descriptor_dir(&state, &directory.0)
+            .await
+            .unwrap()
+            .into_iter()
+            .collect();
+
+        fs::remove_file(directory.0.join("shop/order.pb")).unwrap();
+        write_descriptors(&directory.0, &[("shop/customer.proto", CUSTOMER)]);
+        let mut known_files: Vec<String> =
+            reload_descriptor_dir(&state, &directory.0, &known_files)
+                .await
+                .unwrap()
+                .into_iter()
+                .collect();
+        known_files.sort();
+
+        assert_eq!(known_files, vec!["shop/customer.proto", "shop/item.proto"]);
+        assert_eq!(registered_files(&state).await, known_files);
+        let pool = state.descriptor_pool.load();
+        assert!(pool
+            .descriptor_pool
+            .get_message_by_name("shop.Order")
+            .is_none());
+    }
+}
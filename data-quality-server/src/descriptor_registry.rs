@@ -0,0 +1,409 @@
+/* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use tracing::{error, info, trace};
+
+use crate::app_error::AppError;
+use crate::protobuf_descriptors::{
+    own_proto_files, proto_file_names, rebuild_descriptor_pool, DescriptorEntry, DescriptorVersions,
+};
+use crate::schema_compatibility::{breaking_changes, diff_schemas, CompatibilityMode};
+use crate::AppState;
+
+pub enum RegisterOutcome {
+    Unchanged(u32),
+    Loaded(u32),
+}
+
+/// Adds `file_content` as the next version of `file_name`. The descriptor map, the store and the
+/// cached pool are only changed when the new version builds, passes the compatibility check and
+/// is persisted.
+pub async fn register_descriptor(
+    state: &AppState,
+    file_name: &str,
+    file_content: Vec<u8>,
+    compatibility: CompatibilityMode,
+) -> Result<RegisterOutcome, AppError> {
+    let outcomes = register_descriptors(
+        state,
+        vec![(file_name.to_string(), file_content)],
+        compatibility,
+    )
+    .await?;
+    outcomes
+        .into_iter()
+        .next()
+        .map(|(_, outcome)| outcome)
+        .ok_or_else(|| AppError::UnknownError(format!("{} was not registered", file_name)))
+}
+
+/// Registers several descriptor files as one change: either every file gets its new version or
+/// none of them does.
+pub async fn register_descriptors(
+    state: &AppState,
+    files: Vec<(String, Vec<u8>)>,
+    compatibility: CompatibilityMode,
+) -> Result<Vec<(String, RegisterOutcome)>, AppError> {
+    let mut seen_files = HashSet::new();
+    if let Some((file_name, _)) = files
+        .iter()
+        .find(|(file_name, _)| !seen_files.insert(file_name.as_str()))
+    {
+        return Err(AppError::InvalidRequest(format!(
+            "{} is uploaded more than once",
+            file_name
+        )));
+    }
+
+    let mut descriptor_map = state.descriptor_map.write().await;
+    let mut outcomes = Vec::with_capacity(files.len());
+    let mut added: Vec<(String, Option<DescriptorEntry>, DescriptorEntry)> = Vec::new();
+
+    for (file_name, file_content) in files {
+        let versions = descriptor_map.entry(file_name.clone()).or_default();
+        let previous_entry = versions.last().cloned();
+        let new_entry = DescriptorEntry::new(
+            previous_entry.as_ref().map_or(1, |entry| entry.version + 1),
+            file_content,
+        );
+
+        if let Some(previous_entry) = &previous_entry {
+            if previous_entry.sha256 == new_entry.sha256 {
+                info!(
+                    "Descriptor {} is unchanged, keeping version {}.",
+                    file_name, previous_entry.version
+                );
+                outcomes.push((
+                    file_name,
+                    RegisterOutcome::Unchanged(previous_entry.version),
+                ));
+                continue;
+            }
+        }
+        versions.push(new_entry.clone());
+        outcomes.push((
+            file_name.clone(),
+            RegisterOutcome::Loaded(new_entry.version),
+        ));
+        added.push((file_name, previous_entry, new_entry));
+    }
+    if added.is_empty() {
+        return Ok(outcomes);
+    }
+
+    trace!("Rebuilding descriptor pool.");
+    let (pool, report) = rebuild_descriptor_pool(&descriptor_map);
+    if !report.is_success() {
+        error!(
+            "Rejecting descriptors, {} file(s) failed to load: {}",
+            report.failed.len(),
+            report.failed_file_names().join(", ")
+        );
+        remove_added_versions(&mut descriptor_map, &added);
+        return Err(AppError::DescriptorLoadFailed(report));
+    }
+
+    let old_pool = state.descriptor_pool.load();
+    let mut breaking = Vec::new();
+    let mut incompatible_files = Vec::new();
+    for (file_name, previous_entry, new_entry) in &added {
+        let Some(previous_entry) = previous_entry else {
+            continue;
+        };
+        let changes = match proto_file_names(previous_entry)
+            .and_then(|old_files| Ok((old_files, proto_file_names(new_entry)?)))
+        {
+            Ok((old_files, new_files)) => diff_schemas(
+                &old_pool.descriptor_pool,
+                &own_proto_files(&descriptor_map, file_name, &old_files),
+                &pool.descriptor_pool,
+                &own_proto_files(&descriptor_map, file_name, &new_files),
+            ),
+            Err(err) => {
+                remove_added_versions(&mut descriptor_map, &added);
+                return Err(AppError::DescriptorError(err));
+            }
+        };
+        let file_breaking = breaking_changes(changes, compatibility);
+        if !file_breaking.is_empty() {
+            error!(
+                "Descriptor {} is not {} compatible with version {}: {} breaking change(s)",
+                file_name,
+                compatibility,
+                previous_entry.version,
+                file_breaking.len()
+            );
+            incompatible_files.push(file_name.clone());
+            breaking.extend(file_breaking);
+        }
+    }
+    if !breaking.is_empty() {
+        remove_added_versions(&mut descriptor_map, &added);
+        return Err(AppError::IncompatibleSchema {
+            file_names: incompatible_files,
+            compatibility,
+            changes: breaking,
+        });
+    }
+
+    let entries: Vec<(&str, &DescriptorEntry)> = added
+        .iter()
+        .map(|(file_name, _, new_entry)| (file_name.as_str(), new_entry))
+        .collect();
+    if let Err(err) = state.descriptor_store.save_all(&entries) {
+        error!("Failed to persist descriptors: {}", err);
+        remove_added_versions(&mut descriptor_map, &added);
+        return Err(AppError::StorageError(err.to_string()));
+    }
+    state.descriptor_pool.store(Arc::new(pool));
+    state.version_pools.clear();
+
+    for (file_name, _, new_entry) in &added {
+        info!(
+            "Descriptor {} loaded successfully as version {}.",
+            file_name, new_entry.version
+        );
+    }
+    Ok(outcomes)
+}
+
+/// Removes every version of `file_name`, unless other descriptors still depend on it.
+pub async fn unregister_descriptor(state: &AppState, file_name: &str) -> Result<(), AppError> {
+    let mut descriptor_map = state.descriptor_map.write().await;
+    let removed_versions = descriptor_map
+        .remove(file_name)
+        .ok_or_else(|| AppError::DescriptorNotFound(file_name.to_string()))?;
+
+    let (pool, report) = rebuild_descriptor_pool(&descriptor_map);
+    if !report.is_success() {
+        error!(
+            "Cannot delete descriptor {}, other descriptors depend on it: {}",
+            file_name,
+            report.failed_file_names().join(", ")
+        );
+        descriptor_map.insert(file_name.to_string(), removed_versions);
+        return Err(AppError::DescriptorLoadFailed(report));
+    }
+
+    if let Err(err) = state.descriptor_store.delete(file_name) {
+        error!(
+            "Failed to delete {} from the descriptor store: {}",
+            file_name, err
+        );
+        descriptor_map.insert(file_name.to_string(), removed_versions);
+        return Err(AppError::StorageError(err.to_string()));
+    }
+    state.descriptor_pool.store(Arc::new(pool));
+    state.version_pools.clear();
+
+    info!("Descriptor {} deleted.", file_name);
+    Ok(())
+}
+
+fn remove_added_versions(
+    descriptor_map: &mut DescriptorVersions,
+    added: &[(String, Option<DescriptorEntry>, DescriptorEntry)],
+) {
+    for (file_name, _, _) in added {
+        if let Some(versions) = descriptor_map.get_mut(file_name) {
+            versions.pop();
+            if versions.is_empty() {
+                descriptor_map.remove(file_name);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor_store::DescriptorStore;
+    use crate::proto_compiler::compile_proto_sources;
+    use crate::test_support::test_state;
+    use prost_reflect::DescriptorPool;
+    use std::sync::Mutex;
+
+    /// Records the versions it is asked to save.
+    #[derive(Default)]
+    struct RecordingStore {
+        saved: Mutex<Vec<(String, u32)>>,
+    }
+
+    impl DescriptorStore for RecordingStore {
+        fn load_all(&self) -> anyhow::Result<DescriptorVersions> {
+            Ok(DescriptorVersions::new())
+        }
+
+        fn save(&self, file_name: &str, entry: &DescriptorEntry) -> anyhow::Result<()> {
+            self.saved
+                .lock()
+                .unwrap()
+                .push((file_name.to_string(), entry.version));
+            Ok(())
+        }
+
+        fn delete(&self, file_name: &str) -> anyhow::Result<()> {
+            self.saved
+                .lock()
+                .unwrap()
+                .retain(|(saved_file_name, _)| saved_file_name != file_name);
+            Ok(())
+        }
+    }
+
+    fn descriptor_set(file_name: &str, source: &str) -> (String, Vec<u8>) {
+        compile_proto_sources(
+            vec![(file_name.to_string(), source.to_string())],
+            &DescriptorPool::global(),
+        )
+        .unwrap()
+        .into_iter()
+        .next()
+        .unwrap()
+    }
+
+    const ITEM_V1: &str = "syntax = \"proto3\"; message Item { string sku = 1; }";
+    const ITEM_V2: &str = "syntax = \"proto3\"; message Item { string sku = 1; int32 qty = 2; }";
+
+    async fn assert_only_item_v1(state: &AppState, store: &RecordingStore) {
+        let descriptor_map = state.descriptor_map.read().await;
+        assert_eq!(descriptor_map.len(), 1);
+        assert_eq!(descriptor_map["item.proto"].len(), 1);
+        assert_eq!(
+            *store.saved.lock().unwrap(),
+            vec![("item.proto".to_string(), 1)]
+        );
+
+        let pool = state.descriptor_pool.load();
+        let item = pool.descriptor_pool.get_message_by_name("Item").unwrap();
+        assert!(item.get_field_by_name("qty").is_none());
+        assert!(pool.descriptor_pool.get_message_by_name("Order").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_batch_with_invalid_file_registers_nothing() {
+        let store = Arc::new(RecordingStore::default());
+        let state = test_state(store.clone());
+        register_descriptors(
+            &state,
+            vec![descriptor_set("item.proto", ITEM_V1)],
+            CompatibilityMode::Backward,
+        )
+        .await
+        .unwrap();
+
+        let err = register_descriptors(
+            &state,
+            vec![
+                descriptor_set("item.proto", ITEM_V2),
+                descriptor_set("order.proto", "syntax = \"proto3\"; message Order {}"),
+                ("broken.proto".to_string(), b"\x0a\x05abc".to_vec()),
+            ],
+            CompatibilityMode::Backward,
+        )
+        .await
+        .err()
+        .unwrap();
+
+        let AppError::DescriptorLoadFailed(report) = err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(report.failed_file_names(), vec!["broken.proto"]);
+        assert_only_item_v1(&state, &store).await;
+    }
+
+    #[tokio::test]
+    async fn test_batch_with_incompatible_file_registers_nothing() {
+        let store = Arc::new(RecordingStore::default());
+        let state = test_state(store.clone());
+        register_descriptors(
+            &state,
+            vec![descriptor_set("item.proto", ITEM_V1)],
+            CompatibilityMode::Backward,
+        )
+        .await
+        .unwrap();
+
+        let err = register_descriptors(
+            &state,
+            vec![
+                descriptor_set("order.proto", "syntax = \"proto3\"; message Order {}"),
+                descriptor_set(
+                    "item.proto",
+                    "syntax = \"proto3\"; message Item { int64 sku = 1; }",
+                ),
+            ],
+            CompatibilityMode::Backward,
+        )
+        .await
+        .err()
+        .unwrap();
+
+        let AppError::IncompatibleSchema { file_names, .. } = err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(file_names, vec!["item.proto"]);
+        assert_only_item_v1(&state, &store).await;
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_file_names_are_rejected() {
+        let store = Arc::new(RecordingStore::default());
+        let state = test_state(store.clone());
+        register_descriptors(
+            &state,
+            vec![descriptor_set("item.proto", ITEM_V1)],
+            CompatibilityMode::Backward,
+        )
+        .await
+        .unwrap();
+
+        let err = register_descriptors(
+            &state,
+            vec![
+                descriptor_set("item.proto", ITEM_V2),
+                descriptor_set("item.proto", ITEM_V2),
+            ],
+            CompatibilityMode::Backward,
+        )
+        .await
+        .err()
+        .unwrap();
+
+        assert!(
+            matches!(&err, AppError::InvalidRequest(message) if message == "item.proto is uploaded more than once"),
+            "{}",
+            err
+        );
+        assert_only_item_v1(&state, &store).await;
+    }
+
+    #[tokio::test]
+    async fn test_register_descriptor_returns_its_outcome() {
+        let store = Arc::new(RecordingStore::default());
+        let state = test_state(store.clone());
+        let (file_name, file_content) = descriptor_set("item.proto", ITEM_V1);
+
+        let outcome = register_descriptor(
+            &state,
+            &file_name,
+            file_content.clone(),
+            CompatibilityMode::Backward,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(outcome, RegisterOutcome::Loaded(1)));
+
+        let outcome = register_descriptor(
+            &state,
+            &file_name,
+            file_content,
+            CompatibilityMode::Backward,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(outcome, RegisterOutcome::Unchanged(1)));
+        assert_only_item_v1(&state, &store).await;
+    }
+}
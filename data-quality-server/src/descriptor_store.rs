@@ -0,0 +1,674 @@
+/* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use redb::{Database, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{debug, info};
+
+use crate::protobuf_descriptors::{DescriptorEntry, DescriptorVersions};
+use crate::validation_profiles::{ValidationProfile, ValidationProfiles};
+
+/// Persists uploaded descriptors so they survive a restart. Every version is saved on upload and
+/// all versions of a file are dropped when it is deleted.
+pub trait DescriptorStore: Send + Sync {
+    fn load_all(&self) -> Result<DescriptorVersions>;
+    fn save(&self, file_name: &str, entry: &DescriptorEntry) -> Result<()>;
+    fn delete(&self, file_name: &str) -> Result<()>;
+
+    /// Saves the entries of one upload. Stores that support it do so in a single transaction.
+    fn save_all(&self, entries: &[(&str, &DescriptorEntry)]) -> Result<()> {
+        for (file_name, entry) in entries {
+            self.save(file_name, entry)?;
+        }
+        Ok(())
+    }
+}
+
+/// Persists validation profiles so they survive a restart.
+pub trait ProfileStore: Send + Sync {
+    fn load_profiles(&self) -> Result<ValidationProfiles>;
+    fn save_profile(&self, profile: &ValidationProfile) -> Result<()>;
+    fn delete_profile(&self, name: &str) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StoreKind {
+    Memory,
+    Directory,
+    Redb,
+}
+
+/// The descriptor and profile store of one `--store`, sharing the same directory or database.
+pub struct Stores {
+    pub descriptors: Arc<dyn DescriptorStore>,
+    pub profiles: Arc<dyn ProfileStore>,
+}
+
+impl Stores {
+    fn new<S: DescriptorStore + ProfileStore + 'static>(store: S) -> Self {
+        let store = Arc::new(store);
+        Stores {
+            descriptors: store.clone(),
+            profiles: store,
+        }
+    }
+}
+
+pub fn open_store(kind: StoreKind, path: &Path) -> Result<Stores> {
+    info!("Opening {:?} descriptor store at {}", kind, path.display());
+    match kind {
+        StoreKind::Memory => Ok(Stores::new(MemoryStore)),
+        StoreKind::Directory => Ok(Stores::new(DirectoryStore::new(path)?)),
+        StoreKind::Redb => Ok(Stores::new(RedbStore::new(path)?)),
+    }
+}
+
+/// Keeps nothing; descriptors and profiles only live as long as the process.
+pub struct MemoryStore;
+
+impl DescriptorStore for MemoryStore {
+    fn load_all(&self) -> Result<DescriptorVersions> {
+        Ok(DescriptorVersions::new())
+    }
+
+    fn save(&self, _file_name: &str, _entry: &DescriptorEntry) -> Result<()> {
+        Ok(())
+    }
+
+    fn delete(&self, _file_name: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ProfileStore for MemoryStore {
+    fn load_profiles(&self) -> Result<ValidationProfiles> {
+        Ok(ValidationProfiles::new())
+    }
+
+    fn save_profile(&self, _profile: &ValidationProfile) -> Result<()> {
+        Ok(())
+    }
+
+    fn delete_profile(&self, _name: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct EntryMetadata {
+    file_name: String,
+    version: u32,
+    uploaded_at: u64,
+}
+
+/// Stores every version as `<file name>.v<version>.pb` with a `.json` metadata file next to it,
+/// and every validation profile as `profiles/<name>.json`.
+pub struct DirectoryStore {
+    directory: PathBuf,
+}
+
+impl DirectoryStore {
+    pub fn new(directory: &Path) -> Result<Self> {
+        fs::create_dir_all(directory).with_context(|| {
+            format!(
+                "Failed to create descriptor store directory {}",
+                directory.display()
+            )
+        })?;
+        Ok(DirectoryStore {
+            directory: directory.to_path_buf(),
+        })
+    }
+
+    fn entry_path(&self, file_name: &str, version: u32, extension: &str) -> PathBuf {
+        self.directory.join(format!(
+            "{}.v{}.{}",
+            encode_file_name(file_name),
+            version,
+            extension
+        ))
+    }
+
+    fn profiles_directory(&self) -> PathBuf {
+        self.directory.join("profiles")
+    }
+
+    fn profile_path(&self, name: &str) -> PathBuf {
+        self.profiles_directory()
+            .join(format!("{}.json", encode_file_name(name)))
+    }
+}
+
+impl DescriptorStore for DirectoryStore {
+    fn load_all(&self) -> Result<DescriptorVersions> {
+        let mut descriptor_map = DescriptorVersions::new();
+
+        for dir_entry in fs::read_dir(&self.directory)? {
+            let metadata_path = dir_entry?.path();
+            if metadata_path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+
+            let metadata: EntryMetadata = serde_json::from_slice(&fs::read(&metadata_path)?)
+                .with_context(|| format!("Failed to read {}", metadata_path.display()))?;
+            let content_path = self.entry_path(&metadata.file_name, metadata.version, "pb");
+            let file_content = fs::read(&content_path)
+                .with_context(|| format!("Failed to read {}", content_path.display()))?;
+
+            debug!(
+                "Loaded {} version {} from {}",
+                metadata.file_name,
+                metadata.version,
+                content_path.display()
+            );
+            descriptor_map
+                .entry(metadata.file_name)
+                .or_default()
+                .push(DescriptorEntry::restore(
+                    metadata.version,
+                    file_content,
+                    metadata.uploaded_at,
+                ));
+        }
+
+        for versions in descriptor_map.values_mut() {
+            versions.sort_by_key(|entry| entry.version);
+        }
+        Ok(descriptor_map)
+    }
+
+    fn save(&self, file_name: &str, entry: &DescriptorEntry) -> Result<()> {
+        self.save_all(&[(file_name, entry)])
+    }
+
+    fn delete(&self, file_name: &str) -> Result<()> {
+        let prefix = format!("{}.v", encode_file_name(file_name));
+
+        for dir_entry in fs::read_dir(&self.directory)? {
+            let path = dir_entry?.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let is_entry_file = name
+                .strip_prefix(&prefix)
+                .and_then(|rest| rest.split_once('.'))
+                .is_some_and(|(version, extension)| {
+                    version.parse::<u32>().is_ok() && (extension == "pb" || extension == "json")
+                });
+            if is_entry_file {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn save_all(&self, entries: &[(&str, &DescriptorEntry)]) -> Result<()> {
+        let mut content_files = Vec::with_capacity(entries.len());
+        let mut metadata_files = Vec::with_capacity(entries.len());
+        for (file_name, entry) in entries {
+            let metadata = EntryMetadata {
+                file_name: file_name.to_string(),
+                version: entry.version,
+                uploaded_at: entry.uploaded_at,
+            };
+            content_files.push((
+                self.entry_path(file_name, entry.version, "pb"),
+                entry.file_content.clone(),
+            ));
+            metadata_files.push((
+                self.entry_path(file_name, entry.version, "json"),
+                serde_json::to_vec_pretty(&metadata)?,
+            ));
+        }
+
+        // The metadata file marks an entry as complete, so metadata files are moved in last.
+        content_files.extend(metadata_files);
+        write_files(&content_files)
+    }
+}
+
+impl ProfileStore for DirectoryStore {
+    fn load_profiles(&self) -> Result<ValidationProfiles> {
+        let mut profiles = ValidationProfiles::new();
+        let profiles_directory = self.profiles_directory();
+        if !profiles_directory.exists() {
+            return Ok(profiles);
+        }
+
+        for dir_entry in fs::read_dir(&profiles_directory)? {
+            let path = dir_entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let profile: ValidationProfile = serde_json::from_slice(&fs::read(&path)?)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            debug!("Loaded profile {} from {}", profile.name, path.display());
+            profiles.insert(profile.name.clone(), profile);
+        }
+        Ok(profiles)
+    }
+
+    fn save_profile(&self, profile: &ValidationProfile) -> Result<()> {
+        fs::create_dir_all(self.profiles_directory())?;
+        // A partly written profile would keep the server from starting, so it is moved in whole.
+        write_files(&[(
+            self.profile_path(&profile.name),
+            serde_json::to_vec_pretty(profile)?,
+        )])
+    }
+
+    fn delete_profile(&self, name: &str) -> Result<()> {
+        let path = self.profile_path(name);
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+}
+
+// Writes every file to a temporary file first and only renames them into place once all of them
+// are written, so a failure part way leaves none of the files behind.
+fn write_files(files: &[(PathBuf, Vec<u8>)]) -> Result<()> {
+    let temporary_paths: Vec<PathBuf> = files
+        .iter()
+        .map(|(path, _)| {
+            let mut temporary_path = path.clone().into_os_string();
+            temporary_path.push(".tmp");
+            PathBuf::from(temporary_path)
+        })
+        .collect();
+
+    let written =
+        files
+            .iter()
+            .zip(&temporary_paths)
+            .try_for_each(|((path, content), temporary_path)| {
+                fs::write(temporary_path, content)
+                    .with_context(|| format!("Failed to write {}", path.display()))
+            });
+    if let Err(err) = written {
+        for temporary_path in &temporary_paths {
+            let _ = fs::remove_file(temporary_path);
+        }
+        return Err(err);
+    }
+
+    for (index, ((path, _), temporary_path)) in files.iter().zip(&temporary_paths).enumerate() {
+        if let Err(err) = fs::rename(temporary_path, path) {
+            for (path, _) in &files[..index] {
+                let _ = fs::remove_file(path);
+            }
+            for temporary_path in &temporary_paths[index..] {
+                let _ = fs::remove_file(temporary_path);
+            }
+            return Err(err).with_context(|| format!("Failed to write {}", path.display()));
+        }
+    }
+    Ok(())
+}
+
+// File names come from clients, so anything that could escape the store directory is encoded.
+fn encode_file_name(file_name: &str) -> String {
+    let mut encoded = String::with_capacity(file_name.len());
+    for byte in file_name.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    if encoded.starts_with('.') {
+        encoded.replace_range(0..1, "%2E");
+    }
+    encoded
+}
+
+const DESCRIPTORS: TableDefinition<(&str, u32), &[u8]> = TableDefinition::new("descriptors");
+const UPLOADED_AT: TableDefinition<(&str, u32), u64> = TableDefinition::new("uploaded_at");
+const PROFILES: TableDefinition<&str, &[u8]> = TableDefinition::new("profiles");
+
+/// Stores descriptors in an embedded redb database file.
+pub struct RedbStore {
+    database: Database,
+}
+
+impl RedbStore {
+    pub fn new(path: &Path) -> Result<Self> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let database = Database::create(path)
+            .with_context(|| format!("Failed to open descriptor database {}", path.display()))?;
+
+        let write_txn = database.begin_write()?;
+        write_txn.open_table(DESCRIPTORS)?;
+        write_txn.open_table(UPLOADED_AT)?;
+        write_txn.open_table(PROFILES)?;
+        write_txn.commit()?;
+
+        Ok(RedbStore { database })
+    }
+}
+
+impl DescriptorStore for RedbStore {
+    fn load_all(&self) -> Result<DescriptorVersions> {
+        let mut descriptor_map = DescriptorVersions::new();
+        let read_txn = self.database.begin_read()?;
+        let descriptors = read_txn.open_table(DESCRIPTORS)?;
+        let uploaded_at = read_txn.open_table(UPLOADED_AT)?;
+
+        // Keys are ordered by file name and version, so versions come out oldest first.
+        for row in descriptors.iter()? {
+            let (key, file_content) = row?;
+            let (file_name, version) = key.value();
+            let entry_uploaded_at = uploaded_at
+                .get((file_name, version))?
+                .map(|value| value.value())
+                .unwrap_or_default();
+
+            descriptor_map
+                .entry(file_name.to_string())
+                .or_default()
+                .push(DescriptorEntry::restore(
+                    version,
+                    file_content.value().to_vec(),
+                    entry_uploaded_at,
+                ));
+        }
+        Ok(descriptor_map)
+    }
+
+    fn save(&self, file_name: &str, entry: &DescriptorEntry) -> Result<()> {
+        self.save_all(&[(file_name, entry)])
+    }
+
+    fn delete(&self, file_name: &str) -> Result<()> {
+        let write_txn = self.database.begin_write()?;
+        {
+            let mut descriptors = write_txn.open_table(DESCRIPTORS)?;
+            descriptors.retain_in((file_name, 0)..=(file_name, u32::MAX), |_, _| false)?;
+            let mut uploaded_at = write_txn.open_table(UPLOADED_AT)?;
+            uploaded_at.retain_in((file_name, 0)..=(file_name, u32::MAX), |_, _| false)?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    fn save_all(&self, entries: &[(&str, &DescriptorEntry)]) -> Result<()> {
+        let write_txn = self.database.begin_write()?;
+        {
+            let mut descriptors = write_txn.open_table(DESCRIPTORS)?;
+            let mut uploaded_at = write_txn.open_table(UPLOADED_AT)?;
+            for (file_name, entry) in entries {
+                descriptors.insert((*file_name, entry.version), entry.file_content.as_slice())?;
+                uploaded_at.insert((*file_name, entry.version), entry.uploaded_at)?;
+            }
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+}
+
+impl ProfileStore for RedbStore {
+    fn load_profiles(&self) -> Result<ValidationProfiles> {
+        let mut profiles = ValidationProfiles::new();
+        let read_txn = self.database.begin_read()?;
+        let table = read_txn.open_table(PROFILES)?;
+
+        for row in table.iter()? {
+            let (name, profile) = row?;
+            let profile: ValidationProfile = serde_json::from_slice(profile.value())
+                .with_context(|| format!("Failed to read profile {}", name.value()))?;
+            profiles.insert(profile.name.clone(), profile);
+        }
+        Ok(profiles)
+    }
+
+    fn save_profile(&self, profile: &ValidationProfile) -> Result<()> {
+        let profile_json = serde_json::to_vec(profile)?;
+        let write_txn = self.database.begin_write()?;
+        {
+            let mut table = write_txn.open_table(PROFILES)?;
+            table.insert(profile.name.as_str(), profile_json.as_slice())?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    fn delete_profile(&self, name: &str) -> Result<()> {
+        let write_txn = self.database.begin_write()?;
+        {
+            let mut table = write_txn.open_table(PROFILES)?;
+            table.remove(name)?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDirectory;
+
+    fn loaded_versions(store: &dyn DescriptorStore, file_name: &str) -> Vec<(u32, Vec<u8>, u64)> {
+        store
+            .load_all()
+            .unwrap()
+            .remove(file_name)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| (entry.version, entry.file_content, entry.uploaded_at))
+            .collect()
+    }
+
+    fn assert_round_trip(store: &dyn DescriptorStore) {
+        let first = DescriptorEntry::restore(1, b"first".to_vec(), 100);
+        let second = DescriptorEntry::restore(2, b"second".to_vec(), 200);
+        let escaped = DescriptorEntry::restore(1, b"escaped".to_vec(), 300);
+        let hidden = DescriptorEntry::restore(1, b"hidden".to_vec(), 400);
+        let similar = DescriptorEntry::restore(1, b"similar".to_vec(), 500);
+        store
+            .save_all(&[
+                ("a.proto", &second),
+                ("a.proto", &first),
+                ("../dir/a b.proto", &escaped),
+                (".hidden.proto", &hidden),
+                ("a.proto.v2.proto", &similar),
+            ])
+            .unwrap();
+
+        let descriptor_map = store.load_all().unwrap();
+        assert_eq!(descriptor_map.len(), 4);
+        assert_eq!(
+            loaded_versions(store, "a.proto"),
+            vec![(1, b"first".to_vec(), 100), (2, b"second".to_vec(), 200)]
+        );
+        assert_eq!(
+            loaded_versions(store, "../dir/a b.proto"),
+            vec![(1, b"escaped".to_vec(), 300)]
+        );
+        assert_eq!(
+            loaded_versions(store, ".hidden.proto"),
+            vec![(1, b"hidden".to_vec(), 400)]
+        );
+        assert_eq!(
+            descriptor_map["a.proto"][1].sha256,
+            DescriptorEntry::new(2, b"second".to_vec()).sha256
+        );
+
+        store.delete("a.proto").unwrap();
+        store.delete("../dir/a b.proto").unwrap();
+        let descriptor_map = store.load_all().unwrap();
+        let mut remaining: Vec<&String> = descriptor_map.keys().collect();
+        remaining.sort();
+        assert_eq!(remaining, vec![".hidden.proto", "a.proto.v2.proto"]);
+    }
+
+    fn assert_overwrite_reloads(store: &dyn DescriptorStore) {
+        store
+            .save(
+                "a.proto",
+                &DescriptorEntry::restore(1, b"old".to_vec(), 100),
+            )
+            .unwrap();
+        store
+            .save(
+                "a.proto",
+                &DescriptorEntry::restore(1, b"new".to_vec(), 200),
+            )
+            .unwrap();
+
+        assert_eq!(
+            loaded_versions(store, "a.proto"),
+            vec![(1, b"new".to_vec(), 200)]
+        );
+    }
+
+    #[test]
+    fn test_directory_store_round_trip() {
+        let directory = TestDirectory::new();
+        let store = DirectoryStore::new(&directory.0).unwrap();
+        assert_round_trip(&store);
+
+        // Everything written stays inside the store directory.
+        let mut names: Vec<String> = fs::read_dir(&directory.0)
+            .unwrap()
+            .map(|dir_entry| dir_entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "%2Ehidden.proto.v1.json",
+                "%2Ehidden.proto.v1.pb",
+                "a.proto.v2.proto.v1.json",
+                "a.proto.v2.proto.v1.pb",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_directory_store_overwrite() {
+        let directory = TestDirectory::new();
+        let store = DirectoryStore::new(&directory.0).unwrap();
+        assert_overwrite_reloads(&store);
+
+        // A reopened store sees the overwritten entry and no temporary files are left behind.
+        let reopened = DirectoryStore::new(&directory.0).unwrap();
+        assert_eq!(
+            loaded_versions(&reopened, "a.proto"),
+            vec![(1, b"new".to_vec(), 200)]
+        );
+        assert_eq!(fs::read_dir(&directory.0).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_redb_store_round_trip() {
+        let directory = TestDirectory::new();
+        let store = RedbStore::new(&directory.0.join("store.redb")).unwrap();
+        assert_round_trip(&store);
+    }
+
+    #[test]
+    fn test_redb_store_overwrite() {
+        let directory = TestDirectory::new();
+        let path = directory.0.join("store.redb");
+        {
+            let store = RedbStore::new(&path).unwrap();
+            assert_overwrite_reloads(&store);
+        }
+
+        let reopened = RedbStore::new(&path).unwrap();
+        assert_eq!(
+            loaded_versions(&reopened, "a.proto"),
+            vec![(1, b"new".to_vec(), 200)]
+        );
+    }
+
+    fn profile(name: &str, protobuf: &str) -> ValidationProfile {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "protobuf": protobuf,
+            "rules": [{ "field": "status", "operator": "eq", "value": "ACTIVE" }],
+            "collect_errors": true,
+        }))
+        .unwrap()
+    }
+
+    fn loaded_profiles(store: &dyn ProfileStore) -> Vec<ValidationProfile> {
+        let mut profiles: Vec<ValidationProfile> =
+            store.load_profiles().unwrap().into_values().collect();
+        profiles.sort_by(|a, b| a.name.cmp(&b.name));
+        profiles
+    }
+
+    fn assert_profiles_round_trip(store: &dyn ProfileStore) {
+        assert!(store.load_profiles().unwrap().is_empty());
+
+        store.save_profile(&profile("orders", "Order")).unwrap();
+        store.save_profile(&profile("items.v2", "Item")).unwrap();
+        store.save_profile(&profile("orders", "OrderV2")).unwrap();
+        assert_eq!(
+            loaded_profiles(store),
+            vec![profile("items.v2", "Item"), profile("orders", "OrderV2")]
+        );
+
+        store.delete_profile("items.v2").unwrap();
+        store.delete_profile("missing").unwrap();
+        assert_eq!(loaded_profiles(store), vec![profile("orders", "OrderV2")]);
+    }
+
+    #[test]
+    fn test_directory_store_profiles_round_trip() {
+        let directory = TestDirectory::new();
+        assert_profiles_round_trip(&DirectoryStore::new(&directory.0).unwrap());
+
+        // A reopened store sees the same profiles and no temporary files are left behind.
+        let reopened = DirectoryStore::new(&directory.0).unwrap();
+        assert_eq!(
+            loaded_profiles(&reopened),
+            vec![profile("orders", "OrderV2")]
+        );
+        let names: Vec<String> = fs::read_dir(directory.0.join("profiles"))
+            .unwrap()
+            .map(|dir_entry| dir_entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(names, vec!["orders.json"]);
+        // Profiles do not show up as descriptors.
+        assert!(reopened.load_all().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_redb_store_profiles_round_trip() {
+        let directory = TestDirectory::new();
+        let path = directory.0.join("store.redb");
+        {
+            let store = RedbStore::new(&path).unwrap();
+            assert_profiles_round_trip(&store);
+        }
+
+        let reopened = RedbStore::new(&path).unwrap();
+        assert_eq!(
+            loaded_profiles(&reopened),
+            vec![profile("orders", "OrderV2")]
+        );
+        assert!(reopened.load_all().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_encode_file_name() {
+        assert_eq!(encode_file_name("orders.proto"), "orders.proto");
+        assert_eq!(encode_file_name("dir/a b.proto"), "dir%2Fa%20b.proto");
+        assert_eq!(encode_file_name("../up.proto"), "%2E.%2Fup.proto");
+        assert_eq!(encode_file_name(".hidden"), "%2Ehidden");
+        assert_eq!(encode_file_name("ü.proto"), "%C3%BC.proto");
+    }
+}
@@ -0,0 +1,496 @@
+/* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value as JsonValue;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    /// An object key, or an array index when applied to an array.
+    Key(String),
+    /// Every element of an array.
+    Wildcard,
+}
+
+/// A path to a field in a JSON document, written either as a JSON Pointer (RFC 6901) such as
+/// `/order/items/0/sku`, or in dotted/bracket syntax such as `order.items[0].sku`. Only the
+/// dotted form has wildcards: `items[*].price` selects every element of `items`, while in a
+/// pointer `/items/*/price` the `*` is a key like any other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldPath {
+    path: String,
+    segments: Vec<Segment>,
+}
+
+/// The value found at one concrete path, e.g. `items[2].price` for `items[*].price`.
+#[derive(Debug)]
+pub struct FieldMatch<'a> {
+    pub path: String,
+    pub lookup: Lookup<'a>,
+}
+
+#[derive(Debug)]
+pub enum Lookup<'a> {
+    Found(&'a JsonValue),
+    Missing,
+    /// A wildcard was applied to a value that is not an array.
+    NotAnArray(&'a JsonValue),
+}
+
+impl FieldPath {
+    /// A path to the top-level field `key`, taken literally even when it contains `.` or `[` or
+    /// starts with `/`.
+    pub fn key(key: &str) -> Self {
+        FieldPath {
+            path: key.to_string(),
+            segments: vec![Segment::Key(key.to_string())],
+        }
+    }
+
+    pub fn has_wildcard(&self) -> bool {
+        self.segments.contains(&Segment::Wildcard)
+    }
+
+    /// Returns one match per concrete path. A path without wildcards always has exactly one
+    /// match; a wildcard over an empty array has none.
+    pub fn resolve<'a>(&self, json_value: &'a JsonValue) -> Vec<FieldMatch<'a>> {
+        let mut matches = Vec::new();
+        self.resolve_from(json_value, 0, &mut Vec::new(), &mut matches);
+        matches
+    }
+
+    fn resolve_from<'a>(
+        &self,
+        json_value: &'a JsonValue,
+        index: usize,
+        concrete_path: &mut Vec<String>,
+        matches: &mut Vec<FieldMatch<'a>>,
+    ) {
+        let Some(segment) = self.segments.get(index) else {
+            matches.push(self.field_match(concrete_path, Lookup::Found(json_value)));
+            return;
+        };
+
+        match segment {
+            Segment::Key(key) => {
+                let child = match json_value {
+                    JsonValue::Object(map) => map.get(key),
+                    JsonValue::Array(elements) => {
+                        key.parse::<usize>().ok().and_then(|i| elements.get(i))
+                    }
+                    _ => None,
+                };
+                concrete_path.push(key.clone());
+                match child {
+                    Some(child) => self.resolve_from(child, index + 1, concrete_path, matches),
+                    None => matches.push(self.field_match(concrete_path, Lookup::Missing)),
+                }
+                concrete_path.pop();
+            }
+            Segment::Wildcard => {
+                let JsonValue::Array(elements) = json_value else {
+                    matches.push(self.field_match(concrete_path, Lookup::NotAnArray(json_value)));
+                    return;
+                };
+                for (element_index, element) in elements.iter().enumerate() {
+                    concrete_path.push(element_index.to_string());
+                    self.resolve_from(element, index + 1, concrete_path, matches);
+                    concrete_path.pop();
+                }
+            }
+        }
+    }
+
+    fn field_match<'a>(&self, concrete_path: &[String], lookup: Lookup<'a>) -> FieldMatch<'a> {
+        FieldMatch {
+            path: self.format_path(concrete_path),
+            lookup,
+        }
+    }
+
+    // Formats a concrete path in the dotted syntax; only dotted paths have wildcards.
+    fn format_path(&self, keys: &[String]) -> String {
+        if !self.has_wildcard() {
+            return self.path.clone();
+        }
+
+        let mut path = String::new();
+        for (key, segment) in keys.iter().zip(&self.segments) {
+            let is_index = matches!(segment, Segment::Wildcard) || key.parse::<usize>().is_ok();
+            if is_index {
+                path.push_str(&format!("[{}]", key));
+            } else if key.contains(['.', '[', ']']) || key.is_empty() {
+                path.push_str(&format!("[\"{}\"]", key));
+            } else {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(key);
+            }
+        }
+        path
+    }
+}
+
+impl FromStr for FieldPath {
+    type Err = String;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let segments = match path.strip_prefix('/') {
+            Some(pointer) => parse_pointer(pointer),
+            None => parse_dotted(path)?,
+        };
+        if segments.is_empty() {
+            return Err("Field path must not be empty".to_string());
+        }
+
+        Ok(FieldPath {
+            path: path.to_string(),
+            segments,
+        })
+    }
+}
+
+// RFC 6901 has no wildcards, so every token, `*` included, is a key.
+fn parse_pointer(pointer: &str) -> Vec<Segment> {
+    pointer
+        .split('/')
+        .map(|token| Segment::Key(token.replace("~1", "/").replace("~0", "~")))
+        .collect()
+}
+
+fn parse_dotted(path: &str) -> Result<Vec<Segment>, String> {
+    let invalid = |reason: &str| format!("Invalid field path '{}': {}", path, reason);
+
+    let mut segments = Vec::new();
+    let mut chars = path.chars().peekable();
+    // Whether a key may start here: at the start of the path and after a '.'.
+    let mut expect_key = true;
+
+    while let Some(&character) = chars.peek() {
+        match character {
+            '.' => {
+                if expect_key {
+                    return Err(invalid("empty segment"));
+                }
+                chars.next();
+                expect_key = true;
+            }
+            '[' => {
+                if expect_key && !segments.is_empty() {
+                    return Err(invalid("'[' after '.'"));
+                }
+                chars.next();
+                let mut content = String::new();
+                let mut closed = false;
+                for character in chars.by_ref() {
+                    if character == ']' && !is_open_quote(&content) {
+                        closed = true;
+                        break;
+                    }
+                    content.push(character);
+                }
+                if !closed {
+                    return Err(invalid("missing ']'"));
+                }
+                segments.push(parse_bracket(&content).ok_or_else(|| {
+                    invalid("brackets must contain an index, '*' or a quoted key")
+                })?);
+                expect_key = false;
+            }
+            _ => {
+                if !expect_key {
+                    return Err(invalid("expected '.' or '['"));
+                }
+                let mut key = String::new();
+                while let Some(&character) = chars.peek() {
+                    if character == '.' || character == '[' {
+                        break;
+                    }
+                    if character == ']' {
+                        return Err(invalid("unexpected ']'"));
+                    }
+                    key.push(character);
+                    chars.next();
+                }
+                segments.push(if key == "*" {
+                    Segment::Wildcard
+                } else {
+                    Segment::Key(key)
+                });
+                expect_key = false;
+            }
+        }
+    }
+    if expect_key && !segments.is_empty() {
+        return Err(invalid("trailing '.'"));
+    }
+
+    Ok(segments)
+}
+
+fn is_open_quote(content: &str) -> bool {
+    let mut chars = content.chars();
+    match chars.next() {
+        Some(quote @ ('"' | '\'')) => content.len() == 1 || !content.ends_with(quote),
+        _ => false,
+    }
+}
+
+fn parse_bracket(content: &str) -> Option<Segment> {
+    let content = content.trim();
+    if content == "*" {
+        return Some(Segment::Wildcard);
+    }
+    if content.parse::<usize>().is_ok() {
+        return Some(Segment::Key(content.to_string()));
+    }
+    ['"', '\'']
+        .into_iter()
+        .find_map(|quote| content.strip_prefix(quote)?.strip_suffix(quote))
+        .map(|key| Segment::Key(key.to_string()))
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path)
+    }
+}
+
+impl Serialize for FieldPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.path)
+    }
+}
+
+impl<'de> Deserialize<'de> for FieldPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let path = String::deserialize(deserializer)?;
+        path.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn path(path: &str) -> FieldPath {
+        path.parse().unwrap()
+    }
+
+    // The concrete path and the value found there, `None` when missing.
+    fn resolve(path: &FieldPath, json_value: &JsonValue) -> Vec<(String, Option<JsonValue>)> {
+        path.resolve(json_value)
+            .into_iter()
+            .map(|field_match| {
+                let value = match field_match.lookup {
+                    Lookup::Found(value) => Some(value.clone()),
+                    Lookup::Missing => None,
+                    Lookup::NotAnArray(value) => panic!("{} is not an array", value),
+                };
+                (field_match.path, value)
+            })
+            .collect()
+    }
+
+    fn found(path: &str, value: JsonValue) -> (String, Option<JsonValue>) {
+        (path.to_string(), Some(value))
+    }
+
+    #[test]
+    fn test_dotted_and_pointer_paths() {
+        let document = json!({ "order": { "items": [{ "sku": "A1" }, { "sku": "B2" }] } });
+
+        for written in [
+            "order.items[1].sku",
+            "/order/items/1/sku",
+            "order['items'][1].sku",
+        ] {
+            assert_eq!(
+                resolve(&path(written), &document),
+                vec![found(written, json!("B2"))]
+            );
+        }
+        assert_eq!(
+            resolve(&path("order.items[2].sku"), &document),
+            vec![("order.items[2].sku".to_string(), None)]
+        );
+        assert_eq!(
+            resolve(&path("order.items.sku"), &document),
+            vec![("order.items.sku".to_string(), None)]
+        );
+    }
+
+    #[test]
+    fn test_pointer_escapes() {
+        let document = json!({ "a/b": { "c~d": 1, "~1": 2 }, "list/": [{ "x~": 3 }] });
+
+        assert_eq!(
+            resolve(&path("/a~1b/c~0d"), &document),
+            vec![found("/a~1b/c~0d", json!(1))]
+        );
+        // `~01` is `~1`, not `/`.
+        assert_eq!(
+            resolve(&path("/a~1b/~01"), &document),
+            vec![found("/a~1b/~01", json!(2))]
+        );
+        assert_eq!(
+            resolve(&path("/list~1/0/x~0"), &document),
+            vec![found("/list~1/0/x~0", json!(3))]
+        );
+    }
+
+    #[test]
+    fn test_quoted_bracket_segments() {
+        let document = json!({
+            "order": { "key.with.dots": 1, "a]b": 2, "[x]": 3, "": 4, "0": 5 },
+            "lines": [{ "unit.price": 6 }]
+        });
+
+        let cases = [
+            ("order[\"key.with.dots\"]", 1),
+            ("order['key.with.dots']", 1),
+            ("order[\"a]b\"]", 2),
+            ("order['[x]']", 3),
+            ("order[\"\"]", 4),
+            ("order[0]", 5),
+            ("order[ '0' ]", 5),
+        ];
+        for (written, value) in cases {
+            assert_eq!(
+                resolve(&path(written), &document),
+                vec![found(written, json!(value))],
+                "{}",
+                written
+            );
+        }
+        assert_eq!(
+            resolve(&path("lines[*][\"unit.price\"]"), &document),
+            vec![found("lines[0][\"unit.price\"]", json!(6))]
+        );
+    }
+
+    #[test]
+    fn test_wildcards() {
+        let document = json!({ "items": [{ "price": 1 }, { "qty": 2 }, { "price": 3 }] });
+
+        let field_path = path("items[*].price");
+        assert!(field_path.has_wildcard());
+        assert_eq!(
+            resolve(&field_path, &document),
+            vec![
+                found("items[0].price", json!(1)),
+                ("items[1].price".to_string(), None),
+                found("items[2].price", json!(3)),
+            ]
+        );
+        assert!(!path("items[0].price").has_wildcard());
+        assert_eq!(resolve(&path("items.*.price"), &document).len(), 3);
+    }
+
+    #[test]
+    fn test_pointer_star_is_a_key() {
+        let document = json!({ "items": { "*": { "price": 1 } }, "list": [{ "price": 2 }] });
+
+        let field_path = path("/items/*/price");
+        assert!(!field_path.has_wildcard());
+        assert_eq!(
+            resolve(&field_path, &document),
+            vec![found("/items/*/price", json!(1))]
+        );
+        assert_eq!(
+            resolve(&path("/list/*/price"), &document),
+            vec![("/list/*/price".to_string(), None)]
+        );
+    }
+
+    #[test]
+    fn test_wildcard_over_empty_array() {
+        assert!(path("items[*].price")
+            .resolve(&json!({ "items": [] }))
+            .is_empty());
+        assert!(path("matrix[*][*]")
+            .resolve(&json!({ "matrix": [[], []] }))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_wildcard_over_non_array() {
+        for items in [json!({ "price": 1 }), json!("none"), json!(null)] {
+            let document = json!({ "items": items });
+            let matches = path("items[*].price").resolve(&document);
+
+            assert_eq!(matches.len(), 1);
+            assert_eq!(matches[0].path, "items");
+            assert!(matches!(matches[0].lookup, Lookup::NotAnArray(value) if value == &items));
+        }
+
+        let document = json!({});
+        let matches = path("items[*]").resolve(&document);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].path, "items");
+        assert!(matches!(matches[0].lookup, Lookup::Missing));
+    }
+
+    #[test]
+    fn test_invalid_paths() {
+        let cases = [
+            ("", "Field path must not be empty"),
+            ("a..b", "Invalid field path 'a..b': empty segment"),
+            (".a", "Invalid field path '.a': empty segment"),
+            ("a.", "Invalid field path 'a.': trailing '.'"),
+            ("a.[0]", "Invalid field path 'a.[0]': '[' after '.'"),
+            ("a[0", "Invalid field path 'a[0': missing ']'"),
+            ("a['b]", "Invalid field path 'a['b]': missing ']'"),
+            ("a]b", "Invalid field path 'a]b': unexpected ']'"),
+            ("a[0]b", "Invalid field path 'a[0]b': expected '.' or '['"),
+            (
+                "a[b]",
+                "Invalid field path 'a[b]': brackets must contain an index, '*' or a quoted key",
+            ),
+            (
+                "a[-1]",
+                "Invalid field path 'a[-1]': brackets must contain an index, '*' or a quoted key",
+            ),
+        ];
+
+        for (written, error) in cases {
+            assert_eq!(
+                written.parse::<FieldPath>(),
+                Err(error.to_string()),
+                "{}",
+                written
+            );
+        }
+        assert!(serde_json::from_value::<FieldPath>(json!("a..b")).is_err());
+    }
+
+    #[test]
+    fn test_literal_key() {
+        let document = json!({ "a.b": 1, "/c": 2, "x[0]": 3, "a": { "b": 4 } });
+
+        for (key, value) in [("a.b", 1), ("/c", 2), ("x[0]", 3)] {
+            let field_path = FieldPath::key(key);
+            assert_eq!(field_path.to_string(), key);
+            assert_eq!(
+                resolve(&field_path, &document),
+                vec![found(key, json!(value))]
+            );
+        }
+        assert_eq!(
+            resolve(&path("a.b"), &document),
+            vec![found("a.b", json!(4))]
+        );
+    }
+
+    #[test]
+    fn test_serde_keeps_the_written_path() {
+        let field_path: FieldPath = serde_json::from_value(json!("order['id']")).unwrap();
+        assert_eq!(
+            serde_json::to_value(&field_path).unwrap(),
+            json!("order['id']")
+        );
+    }
+}
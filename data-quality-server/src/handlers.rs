@@ -1,99 +1,282 @@
 /* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
 
 use axum::{
-    extract::{Json, State},
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{FromRequest, Json, Multipart, Path, Query, Request, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
 };
-use base64;
+use base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine};
+use clap::ValueEnum;
+use dynamic_message::{BytesEncoding, FieldNaming, PopulateOptions, UnknownEnumPolicy};
 use serde::Deserialize;
-use serde_json::{json, Value as JsonValue};
-use tracing::{debug, error, info, span, trace, warn, Level};
+use serde_json::json;
+use std::sync::Arc;
+use tracing::{error, info, span, trace, warn, Level};
 
-use crate::json_validation::{unescape_json, validate_json};
-use crate::protobuf_descriptors::{rebuild_descriptor_pool, LoadDescriptorRequest};
+use crate::app_error::AppError;
+use crate::descriptor_registry::{
+    register_descriptor, register_descriptors, unregister_descriptor, RegisterOutcome,
+};
+use crate::json_validation::{unescape_json, validate_json, ValidationInput};
+use crate::proto_compiler::compile_proto_sources;
+use crate::proto_text::file_to_proto_text;
+use crate::protobuf_descriptors::{
+    describe_descriptor, descriptor_file_name, proto_file_names, DescriptorEntry,
+    DescriptorSummary, DescriptorVersions, LoadDescriptorRequest, ValidationPool,
+};
+use crate::rule_engine::FieldRule;
+use crate::schema_compatibility::CompatibilityMode;
+use crate::validation_profiles::{delete_profile, save_profile, SaveOutcome, ValidationProfile};
 use crate::AppState;
 
+/// `Json` whose rejection is an `AppError`, so malformed bodies get a JSON error with a `code`.
+#[derive(FromRequest)]
+#[from_request(via(Json), rejection(AppError))]
+pub struct AppJson<T>(pub T);
+
 #[derive(Deserialize)]
 pub struct ValidationRequest {
+    pub profile: Option<String>,
     pub protobuf: Option<String>,
     pub json: serde_json::Value,
     pub json_escaped: Option<bool>,
     pub field_check: Option<bool>,
     pub field_name: Option<String>,
     pub field_value_check: Option<serde_json::Value>,
+    #[serde(default)]
+    pub rules: Vec<FieldRule>,
+    pub bytes_encoding: Option<BytesEncoding>,
+    pub unknown_enum_policy: Option<UnknownEnumPolicy>,
+    pub field_naming: Option<FieldNaming>,
+    pub collect_errors: Option<bool>,
+    pub descriptor: Option<String>,
+    pub version: Option<u32>,
+}
+
+impl ValidationRequest {
+    /// Fills in what the request leaves out from `profile`. The profile's rules are evaluated
+    /// first, followed by the rules of the request.
+    fn apply_profile(&mut self, profile: &ValidationProfile) {
+        self.protobuf = self.protobuf.take().or_else(|| profile.protobuf.clone());
+        // A version only makes sense for the descriptor it was given with.
+        if self.descriptor.is_none() {
+            self.descriptor = profile.descriptor.clone();
+            self.version = self.version.or(profile.version);
+        }
+        self.rules = profile
+            .rules
+            .iter()
+            .cloned()
+            .chain(self.rules.drain(..))
+            .collect();
+        self.bytes_encoding = self.bytes_encoding.or(profile.bytes_encoding);
+        self.unknown_enum_policy = self.unknown_enum_policy.or(profile.unknown_enum_policy);
+        self.field_naming = self.field_naming.or(profile.field_naming);
+        self.collect_errors = self.collect_errors.or(profile.collect_errors);
+    }
+}
+
+#[derive(Deserialize)]
+pub struct VersionQuery {
+    pub version: Option<u32>,
 }
 
 pub async fn load_descriptor_handler(
     State(state): State<AppState>,
-    Json(payload): Json<LoadDescriptorRequest>,
-) -> impl IntoResponse {
+    request: Request,
+) -> Result<Response, AppError> {
     trace!("Entering load_descriptor_handler function");
 
-    let permit = match state.semaphore.acquire().await {
+    let _permit = match state.semaphore.acquire().await {
         Ok(permit) => permit,
         Err(_) => {
             warn!("Too many concurrent requests, service unavailable.");
-            return (
-                StatusCode::SERVICE_UNAVAILABLE,
-                "Too many concurrent requests, please try again later".to_string(),
-            )
-                .into_response();
+            return Err(AppError::ServiceUnavailable);
         }
     };
 
     let span = span!(Level::INFO, "load_descriptor_handler");
     let _enter = span.enter();
 
-    let file_name = payload.file_name.clone();
-    let file_content_base64 = payload.file_content.clone();
-
-    trace!(
-        "Attempting to decode base64 content for file: {}",
-        file_name
-    );
-    let file_content = match base64::decode(&file_content_base64) {
-        Ok(decoded) => decoded,
-        Err(err) => {
-            error!("Failed to decode base64 content for {}: {}", file_name, err);
-            return (
-                StatusCode::BAD_REQUEST,
-                format!("Failed to decode file content: {}", err),
+    let is_multipart = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("multipart/form-data"));
+    if is_multipart {
+        let multipart = Multipart::from_request(request, &state)
+            .await
+            .map_err(|rejection| AppError::InvalidRequest(rejection.body_text()))?;
+        return load_descriptor_files(&state, multipart)
+            .await
+            .map(IntoResponse::into_response);
+    }
+
+    let AppJson(payload) = AppJson::<LoadDescriptorRequest>::from_request(request, &state).await?;
+    let file_name = descriptor_file_name(&payload.file_name);
+
+    let file_content = match (payload.file_content, payload.proto_source) {
+        (Some(file_content_base64), None) => {
+            trace!(
+                "Attempting to decode base64 content for file: {}",
+                file_name
+            );
+            match BASE64_STANDARD.decode(&file_content_base64) {
+                Ok(decoded) => decoded,
+                Err(err) => {
+                    error!("Failed to decode base64 content for {}: {}", file_name, err);
+                    return Err(AppError::DescriptorError(format!(
+                        "Failed to decode file content of {}: {}",
+                        file_name, err
+                    )));
+                }
+            }
+        }
+        (None, Some(proto_source)) => {
+            let validation_pool = state.descriptor_pool.load_full();
+            compile_proto_sources(
+                vec![(file_name.clone(), proto_source)],
+                &validation_pool.descriptor_pool,
+            )?
+            .into_iter()
+            .next()
+            .map(|(_, file_content)| file_content)
+            .ok_or_else(|| AppError::DescriptorError(format!("{} was not compiled", file_name)))?
+        }
+        _ => {
+            return Err(AppError::InvalidRequest(
+                "exactly one of `file_content` and `proto_source` must be set".to_string(),
+            ))
+        }
+    };
+
+    let compatibility = payload.compatibility.unwrap_or(state.default_compatibility);
+    let message = match register_descriptor(&state, &file_name, file_content, compatibility).await?
+    {
+        RegisterOutcome::Unchanged(version) => {
+            format!(
+                "Descriptor {} is unchanged (version {}).",
+                file_name, version
             )
-                .into_response();
         }
+        RegisterOutcome::Loaded(version) => format!(
+            "Descriptor {} loaded successfully as version {}.",
+            file_name, version
+        ),
     };
+    trace!("Exiting load_descriptor_handler function");
 
-    let mut descriptor_map = state.descriptor_map.write().await;
-    descriptor_map.insert(file_name.clone(), file_content.clone());
+    Ok((StatusCode::OK, message).into_response())
+}
 
-    info!("Descriptor {} loaded successfully.", file_name);
-    trace!("Exiting load_descriptor_handler function");
+// Every part of a multipart upload is a file: `.pb` parts are compiled descriptor sets and
+// `.proto` parts are sources that are compiled together. An optional `compatibility` text part
+// applies to all of them.
+async fn load_descriptor_files(
+    state: &AppState,
+    mut multipart: Multipart,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let mut compatibility = state.default_compatibility;
+    let mut descriptor_files = Vec::new();
+    let mut proto_sources = Vec::new();
 
-    (
-        StatusCode::OK,
-        format!("Descriptor {} loaded successfully.", file_name),
-    )
-        .into_response()
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|err| AppError::InvalidRequest(err.body_text()))?
+    {
+        if field.name() == Some("compatibility") {
+            let mode = field
+                .text()
+                .await
+                .map_err(|err| AppError::InvalidRequest(err.body_text()))?;
+            compatibility = CompatibilityMode::from_str(mode.trim(), true).map_err(|_| {
+                AppError::InvalidRequest(format!("Unknown compatibility mode {}", mode))
+            })?;
+            continue;
+        }
+
+        let Some(file_name) = field.file_name().map(str::to_string) else {
+            return Err(AppError::InvalidRequest(format!(
+                "Multipart field {} has no file name",
+                field.name().unwrap_or_default()
+            )));
+        };
+        let file_content = field
+            .bytes()
+            .await
+            .map_err(|err| AppError::InvalidRequest(err.body_text()))?;
+        trace!("Received {} ({} bytes)", file_name, file_content.len());
+
+        if file_name.ends_with(".proto") {
+            let proto_source = String::from_utf8(file_content.to_vec()).map_err(|_| {
+                AppError::InvalidRequest(format!("{} is not valid UTF-8", file_name))
+            })?;
+            proto_sources.push((file_name, proto_source));
+        } else if file_name.ends_with(".pb") {
+            descriptor_files.push((descriptor_file_name(&file_name), file_content.to_vec()));
+        } else {
+            return Err(AppError::InvalidRequest(format!(
+                "{} is neither a .pb descriptor set nor a .proto source",
+                file_name
+            )));
+        }
+    }
+
+    if !proto_sources.is_empty() {
+        let validation_pool = state.descriptor_pool.load_full();
+        descriptor_files.extend(compile_proto_sources(
+            proto_sources,
+            &validation_pool.descriptor_pool,
+        )?);
+    }
+    if descriptor_files.is_empty() {
+        return Err(AppError::InvalidRequest(
+            "No files were uploaded".to_string(),
+        ));
+    }
+
+    let outcomes = register_descriptors(state, descriptor_files, compatibility).await?;
+    let descriptors: Vec<serde_json::Value> = outcomes
+        .into_iter()
+        .map(|(file_name, outcome)| match outcome {
+            RegisterOutcome::Unchanged(version) => {
+                json!({ "file_name": file_name, "version": version, "status": "unchanged" })
+            }
+            RegisterOutcome::Loaded(version) => {
+                json!({ "file_name": file_name, "version": version, "status": "loaded" })
+            }
+        })
+        .collect();
+
+    Ok(Json(json!({ "descriptors": descriptors })))
 }
 
 pub async fn validate_json_handler(
     State(state): State<AppState>,
-    Json(payload): Json<ValidationRequest>,
-) -> Result<impl IntoResponse, StatusCode> {
+    AppJson(mut payload): AppJson<ValidationRequest>,
+) -> Result<impl IntoResponse, AppError> {
     trace!("Entering validate_json_handler function");
 
-    let permit = match state.semaphore.acquire().await {
+    let _permit = match state.semaphore.acquire().await {
         Ok(permit) => permit,
         Err(_) => {
             warn!("Too many concurrent requests, service unavailable.");
-            return Err(StatusCode::SERVICE_UNAVAILABLE);
+            return Err(AppError::ServiceUnavailable);
         }
     };
 
     let span = span!(Level::INFO, "validate_json_handler");
     let _enter = span.enter();
 
+    if let Some(profile_name) = payload.profile.clone() {
+        let profiles = state.profiles.read().await;
+        let profile = profiles
+            .get(&profile_name)
+            .ok_or(AppError::ProfileNotFound(profile_name))?;
+        payload.apply_profile(profile);
+    }
+
     let proto_name = payload.protobuf.clone();
     let json_escaped = payload.json_escaped.unwrap_or(true);
 
@@ -103,44 +286,405 @@ pub async fn validate_json_handler(
             Ok(unescaped_json) => unescaped_json,
             Err(e) => {
                 error!("Failed to unescape JSON: {}", e);
-                return Err(StatusCode::BAD_REQUEST);
+                return Err(e);
             }
         }
     } else {
         payload.json.to_string()
     };
 
-    trace!("Rebuilding descriptor pool.");
-    let descriptor_pool = {
-        let descriptor_map = state.descriptor_map.read().await;
-        match rebuild_descriptor_pool(&descriptor_map) {
-            Ok(pool) => pool,
-            Err(err) => {
-                error!("Failed to rebuild descriptor pool: {}", err);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
+    // A `descriptor` without a `version` selects its latest version, which is in the cached pool.
+    let validation_pool = match (payload.descriptor.as_deref(), payload.version) {
+        (Some(file_name), version) => {
+            let descriptor_map = state.descriptor_map.read().await;
+            let file_name = descriptor_file_name(file_name);
+            select_version(&state, &descriptor_map, &file_name, version)?.1
+        }
+        (None, Some(_)) => {
+            return Err(AppError::InvalidRequest(
+                "`version` requires `descriptor` to be set".to_string(),
+            ))
         }
+        (None, None) => state.descriptor_pool.load_full(),
     };
 
     let enable_metrics = state.enable_metrics;
 
+    let populate_options = PopulateOptions::new()
+        .bytes_encoding(payload.bytes_encoding.unwrap_or_default())
+        .unknown_enum_policy(payload.unknown_enum_policy.unwrap_or_default())
+        .field_naming(payload.field_naming.unwrap_or_default());
+
     trace!("Attempting to validate JSON message.");
-    match validate_json(
-        Some(&descriptor_pool),
-        &json_message,
-        proto_name.as_deref(),
-        payload.field_check,
-        payload.field_name,
-        payload.field_value_check,
-        enable_metrics,
-    ) {
-        Ok(_) => {
+    let input = ValidationInput {
+        validation_pool: Some(&validation_pool),
+        json_message: &json_message,
+        definition_name: proto_name.as_deref(),
+        field_check: payload.field_check,
+        field_name: payload.field_name,
+        field_value_check: payload.field_value_check,
+        rules: &payload.rules,
+        populate_options,
+        collect_errors: payload.collect_errors.unwrap_or(false),
+    };
+    match validate_json(input, enable_metrics) {
+        Ok(rule_results) if rule_results.is_empty() => {
             info!("JSON validation succeeded.");
             Ok((StatusCode::OK, Json(json!({ "message": "Valid JSON" }))))
         }
+        Ok(rule_results) => {
+            info!("JSON validation succeeded.");
+            Ok((
+                StatusCode::OK,
+                Json(json!({ "message": "Valid JSON", "rules": rule_results })),
+            ))
+        }
         Err(e) => {
             error!("JSON validation failed: {}", e);
-            Err(StatusCode::BAD_REQUEST)
+            Err(e)
         }
     }
 }
+
+pub async fn list_profiles_handler(State(state): State<AppState>) -> Json<Vec<ValidationProfile>> {
+    trace!("Entering list_profiles_handler function");
+
+    let profiles = state.profiles.read().await;
+    let mut profiles: Vec<ValidationProfile> = profiles.values().cloned().collect();
+    profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    Json(profiles)
+}
+
+pub async fn get_profile_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<ValidationProfile>, AppError> {
+    trace!("Entering get_profile_handler function");
+
+    let profiles = state.profiles.read().await;
+    profiles
+        .get(&name)
+        .cloned()
+        .map(Json)
+        .ok_or(AppError::ProfileNotFound(name))
+}
+
+pub async fn create_profile_handler(
+    State(state): State<AppState>,
+    AppJson(profile): AppJson<ValidationProfile>,
+) -> Result<impl IntoResponse, AppError> {
+    trace!("Entering create_profile_handler function");
+
+    save_profile(&state, profile.clone(), true).await?;
+    Ok((StatusCode::CREATED, Json(profile)))
+}
+
+/// Creates or replaces the profile named in the path. The body may leave out `name`.
+pub async fn put_profile_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    AppJson(mut profile): AppJson<ValidationProfile>,
+) -> Result<impl IntoResponse, AppError> {
+    trace!("Entering put_profile_handler function");
+
+    if profile.name.is_empty() {
+        profile.name = name;
+    } else if profile.name != name {
+        return Err(AppError::InvalidRequest(format!(
+            "Profile name '{}' in the body does not match '{}' in the path",
+            profile.name, name
+        )));
+    }
+
+    let status_code = match save_profile(&state, profile.clone(), false).await? {
+        SaveOutcome::Created => StatusCode::CREATED,
+        SaveOutcome::Replaced => StatusCode::OK,
+    };
+    Ok((status_code, Json(profile)))
+}
+
+pub async fn delete_profile_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    trace!("Entering delete_profile_handler function");
+
+    delete_profile(&state, &name).await?;
+
+    Ok((
+        StatusCode::OK,
+        format!("Validation profile {} deleted successfully.", name),
+    ))
+}
+
+pub async fn list_descriptors_handler(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<DescriptorSummary>>, AppError> {
+    trace!("Entering list_descriptors_handler function");
+
+    let descriptor_map = state.descriptor_map.read().await;
+    let validation_pool = state.descriptor_pool.load();
+
+    let mut summaries = descriptor_map
+        .iter()
+        .filter_map(|(file_name, versions)| Some((file_name, versions, versions.last()?)))
+        .map(|(file_name, _, entry)| {
+            describe_descriptor(
+                &validation_pool.descriptor_pool,
+                &descriptor_map,
+                file_name,
+                entry,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(AppError::DescriptorError)?;
+    summaries.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+    Ok(Json(summaries))
+}
+
+pub async fn list_descriptor_versions_handler(
+    State(state): State<AppState>,
+    Path(file_name): Path<String>,
+) -> Result<Json<Vec<DescriptorSummary>>, AppError> {
+    trace!("Entering list_descriptor_versions_handler function");
+
+    let file_name = descriptor_file_name(&file_name);
+    let descriptor_map = state.descriptor_map.read().await;
+    let versions = descriptor_map
+        .get(&file_name)
+        .ok_or_else(|| AppError::DescriptorNotFound(file_name.clone()))?;
+
+    let mut summaries = Vec::with_capacity(versions.len());
+    for entry in versions {
+        let (_, validation_pool) =
+            select_version(&state, &descriptor_map, &file_name, Some(entry.version))?;
+        summaries.push(
+            describe_descriptor(
+                &validation_pool.descriptor_pool,
+                &descriptor_map,
+                &file_name,
+                entry,
+            )
+            .map_err(AppError::DescriptorError)?,
+        );
+    }
+
+    Ok(Json(summaries))
+}
+
+pub async fn get_descriptor_handler(
+    State(state): State<AppState>,
+    Path(file_name): Path<String>,
+    Query(query): Query<VersionQuery>,
+) -> Result<Json<DescriptorSummary>, AppError> {
+    trace!("Entering get_descriptor_handler function");
+
+    let file_name = descriptor_file_name(&file_name);
+    let descriptor_map = state.descriptor_map.read().await;
+    let (entry, validation_pool) =
+        select_version(&state, &descriptor_map, &file_name, query.version)?;
+
+    describe_descriptor(
+        &validation_pool.descriptor_pool,
+        &descriptor_map,
+        &file_name,
+        entry,
+    )
+    .map(Json)
+    .map_err(AppError::DescriptorError)
+}
+
+pub async fn get_descriptor_binary_handler(
+    State(state): State<AppState>,
+    Path(file_name): Path<String>,
+    Query(query): Query<VersionQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    trace!("Entering get_descriptor_binary_handler function");
+
+    let file_name = descriptor_file_name(&file_name);
+    let descriptor_map = state.descriptor_map.read().await;
+    let entry = find_version(&descriptor_map, &file_name, query.version)?;
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/octet-stream")],
+        entry.file_content.clone(),
+    ))
+}
+
+pub async fn get_descriptor_proto_handler(
+    State(state): State<AppState>,
+    Path(file_name): Path<String>,
+    Query(query): Query<VersionQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    trace!("Entering get_descriptor_proto_handler function");
+
+    let file_name = descriptor_file_name(&file_name);
+    let descriptor_map = state.descriptor_map.read().await;
+    let (entry, validation_pool) =
+        select_version(&state, &descriptor_map, &file_name, query.version)?;
+
+    let proto_text = proto_file_names(entry)
+        .map_err(AppError::DescriptorError)?
+        .iter()
+        .filter_map(|proto_file| validation_pool.descriptor_pool.get_file_by_name(proto_file))
+        .map(|file| format!("// {}\n{}", file.name(), file_to_proto_text(&file)))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        proto_text,
+    ))
+}
+
+fn find_version<'a>(
+    descriptor_map: &'a DescriptorVersions,
+    file_name: &str,
+    version: Option<u32>,
+) -> Result<&'a DescriptorEntry, AppError> {
+    let versions = descriptor_map
+        .get(file_name)
+        .ok_or_else(|| AppError::DescriptorNotFound(file_name.to_string()))?;
+
+    match version {
+        Some(version) => versions
+            .iter()
+            .find(|entry| entry.version == version)
+            .ok_or_else(|| {
+                AppError::DescriptorNotFound(format!("{} version {}", file_name, version))
+            }),
+        None => versions
+            .last()
+            .ok_or_else(|| AppError::DescriptorNotFound(file_name.to_string())),
+    }
+}
+
+// The cached pool only holds the latest versions; older versions get a pool of their own, which
+// is cached in `version_pools` until the descriptors change.
+fn select_version<'a>(
+    state: &AppState,
+    descriptor_map: &'a DescriptorVersions,
+    file_name: &str,
+    version: Option<u32>,
+) -> Result<(&'a DescriptorEntry, Arc<ValidationPool>), AppError> {
+    let entry = find_version(descriptor_map, file_name, version)?;
+    let is_latest = descriptor_map[file_name]
+        .last()
+        .is_some_and(|latest| latest.version == entry.version);
+
+    if is_latest {
+        Ok((entry, state.descriptor_pool.load_full()))
+    } else {
+        let pool = state
+            .version_pools
+            .get_or_build(descriptor_map, file_name, entry.version)
+            .map_err(AppError::DescriptorLoadFailed)?;
+        Ok((entry, pool))
+    }
+}
+
+pub async fn delete_descriptor_handler(
+    State(state): State<AppState>,
+    Path(file_name): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    trace!("Entering delete_descriptor_handler function");
+
+    let file_name = descriptor_file_name(&file_name);
+    unregister_descriptor(&state, &file_name).await?;
+
+    Ok((
+        StatusCode::OK,
+        format!("Descriptor {} deleted successfully.", file_name),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(request: serde_json::Value) -> ValidationRequest {
+        serde_json::from_value(request).unwrap()
+    }
+
+    fn profile(profile: serde_json::Value) -> ValidationProfile {
+        serde_json::from_value(profile).unwrap()
+    }
+
+    fn rule(field: &str) -> FieldRule {
+        serde_json::from_value(json!({ "field": field, "operator": "not_null" })).unwrap()
+    }
+
+    fn orders_profile() -> ValidationProfile {
+        profile(json!({
+            "name": "orders",
+            "protobuf": "Order",
+            "descriptor": "orders.proto",
+            "version": 2,
+            "rules": [{ "field": "id", "operator": "not_null" }],
+            "bytes_encoding": "raw_string",
+            "field_naming": "proto_name",
+            "collect_errors": true,
+        }))
+    }
+
+    #[test]
+    fn test_profile_fills_in_missing_fields() {
+        let mut payload = request(json!({ "json": {} }));
+        payload.apply_profile(&orders_profile());
+
+        assert_eq!(payload.protobuf.as_deref(), Some("Order"));
+        assert_eq!(payload.descriptor.as_deref(), Some("orders.proto"));
+        assert_eq!(payload.version, Some(2));
+        assert_eq!(payload.rules, vec![rule("id")]);
+        assert_eq!(payload.bytes_encoding, Some(BytesEncoding::RawString));
+        assert_eq!(payload.unknown_enum_policy, None);
+        assert_eq!(payload.field_naming, Some(FieldNaming::ProtoName));
+        assert_eq!(payload.collect_errors, Some(true));
+    }
+
+    #[test]
+    fn test_request_fields_override_profile() {
+        let mut payload = request(json!({
+            "json": {},
+            "protobuf": "Item",
+            "rules": [{ "field": "sku", "operator": "not_null" }],
+            "bytes_encoding": "base64",
+            "unknown_enum_policy": "warn",
+            "collect_errors": false,
+        }));
+        payload.apply_profile(&orders_profile());
+
+        assert_eq!(payload.protobuf.as_deref(), Some("Item"));
+        // Profile rules run before the rules of the request.
+        assert_eq!(payload.rules, vec![rule("id"), rule("sku")]);
+        assert_eq!(payload.bytes_encoding, Some(BytesEncoding::Base64));
+        assert_eq!(payload.unknown_enum_policy, Some(UnknownEnumPolicy::Warn));
+        assert_eq!(payload.field_naming, Some(FieldNaming::ProtoName));
+        assert_eq!(payload.collect_errors, Some(false));
+    }
+
+    #[test]
+    fn test_profile_version_only_comes_with_its_descriptor() {
+        // The request's descriptor wins, so the profile's version must not be applied to it.
+        let mut payload = request(json!({ "json": {}, "descriptor": "items.proto" }));
+        payload.apply_profile(&orders_profile());
+        assert_eq!(payload.descriptor.as_deref(), Some("items.proto"));
+        assert_eq!(payload.version, None);
+
+        let mut payload = request(json!({
+            "json": {},
+            "descriptor": "items.proto",
+            "version": 1,
+        }));
+        payload.apply_profile(&orders_profile());
+        assert_eq!(payload.descriptor.as_deref(), Some("items.proto"));
+        assert_eq!(payload.version, Some(1));
+
+        // A version without a descriptor refers to the profile's descriptor.
+        let mut payload = request(json!({ "json": {}, "version": 1 }));
+        payload.apply_profile(&orders_profile());
+        assert_eq!(payload.descriptor.as_deref(), Some("orders.proto"));
+        assert_eq!(payload.version, Some(1));
+    }
+}
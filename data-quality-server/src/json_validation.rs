@@ -1,30 +1,55 @@
 /* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
 
-use anyhow::Result;
-use dynamic_message::{populate_dynamic_message, serialize_dynamic_message};
+use dynamic_message::{
+    populate_dynamic_message_all_errors_with_constraints,
+    populate_dynamic_message_with_constraints, serialize_dynamic_message, PopulateOptions,
+};
 use opentelemetry::{global, KeyValue};
-use prost_reflect::{DescriptorPool, DynamicMessage};
+use prost_reflect::DynamicMessage;
 use serde_json::Value as JsonValue;
 use std::time::Instant;
-use tracing::{debug, error, info, span, Level, trace, warn};
+use tracing::{debug, error, info, trace};
 
 use crate::app_error::AppError;
+use crate::field_path::{FieldPath, Lookup};
 use crate::metrics::create_metrics;
+use crate::protobuf_descriptors::ValidationPool;
+use crate::rule_engine::{evaluate_rules, FieldRule, RuleResult};
 
-
+/// A JSON message and what to validate it against. Without a `definition_name` the message is
+/// only parsed before the field check and rules are applied.
+#[derive(Debug, Default)]
+pub struct ValidationInput<'a> {
+    pub validation_pool: Option<&'a ValidationPool>,
+    pub json_message: &'a str,
+    pub definition_name: Option<&'a str>,
+    pub field_check: Option<bool>,
+    pub field_name: Option<String>,
+    pub field_value_check: Option<JsonValue>,
+    pub rules: &'a [FieldRule],
+    pub populate_options: PopulateOptions,
+    pub collect_errors: bool,
+}
 
 #[tracing::instrument]
 pub fn validate_json(
-    descriptor_pool: Option<&DescriptorPool>,
-    json_message: &str,
-    definition_name: Option<&str>,
-    field_check: Option<bool>,
-    field_name: Option<String>,
-    field_value_check: Option<JsonValue>,
+    input: ValidationInput<'_>,
     enable_metrics: bool,
-) -> Result<(), anyhow::Error> {
+) -> Result<Vec<RuleResult>, AppError> {
     info!("Starting JSON validation process.");
 
+    let ValidationInput {
+        validation_pool,
+        json_message,
+        definition_name,
+        field_check,
+        field_name,
+        field_value_check,
+        rules,
+        populate_options,
+        collect_errors,
+    } = input;
+
     let meter = if enable_metrics {
         Some(global::meter("json-validation-service"))
     } else {
@@ -34,9 +59,8 @@ pub fn validate_json(
     let start_time = meter.as_ref().map(|_| Instant::now());
 
     let json_value: JsonValue = serde_json::from_str(json_message).map_err(|e| {
-        let error_msg = format!("Failed to parse JSON: {:?}", e);
-        error!("{}", error_msg);
-        anyhow::anyhow!(error_msg)
+        error!("Failed to parse JSON: {:?}", e);
+        AppError::JsonParseError(e)
     })?;
 
     let message_name = definition_name.unwrap_or("only_json").to_string();
@@ -85,43 +109,58 @@ pub fn validate_json(
     if let Some(definition_name) = definition_name {
         info!("Starting JSON validation for proto: {}", definition_name);
 
-        let message_descriptor = descriptor_pool
-            .ok_or_else(|| {
-                let error_msg = "Descriptor pool is None".to_string();
-                error!("{}", error_msg);
-                anyhow::anyhow!(error_msg)
-            })?
+        let validation_pool = validation_pool.ok_or_else(|| {
+            let error_msg = "Descriptor pool is None".to_string();
+            error!("{}", error_msg);
+            AppError::DescriptorError(error_msg)
+        })?;
+        let message_descriptor = validation_pool
+            .descriptor_pool
             .get_message_by_name(definition_name)
             .ok_or_else(|| {
-                let error_msg = format!("Message '{}' not found in pool", definition_name);
-                error!("{}", error_msg);
-                anyhow::anyhow!(error_msg)
+                error!("Message '{}' not found in pool", definition_name);
+                AppError::MessageNotFound(definition_name.to_string())
             })?;
 
         info!("Found message descriptor: {:?}", message_descriptor);
 
         let mut dynamic_message = DynamicMessage::new(message_descriptor.clone());
-        populate_dynamic_message(&mut dynamic_message, &message_descriptor, &json_value).map_err(
-            |e| {
-                let error_msg = format!("Failed to populate dynamic message: {}", e);
-                error!("{}", error_msg);
-                anyhow::anyhow!(error_msg)
-            },
-        )?;
+        let populated = if collect_errors {
+            populate_dynamic_message_all_errors_with_constraints(
+                &mut dynamic_message,
+                &message_descriptor,
+                &json_value,
+                &populate_options,
+                &validation_pool.constraints,
+            )
+        } else {
+            populate_dynamic_message_with_constraints(
+                &mut dynamic_message,
+                &message_descriptor,
+                &json_value,
+                &populate_options,
+                &validation_pool.constraints,
+            )
+            .map_err(|e| vec![e])
+        };
+        populated.map_err(|violations| {
+            for violation in &violations {
+                error!("Failed to populate dynamic message: {}", violation);
+            }
+            AppError::PopulationError(violations)
+        })?;
 
         serialize_dynamic_message(&mut dynamic_message).map_err(|e| {
-            let error_msg = format!("Failed to serialize dynamic message: {}", e);
-            error!("{}", error_msg);
-            anyhow::anyhow!(error_msg)
+            error!("Failed to serialize dynamic message: {}", e);
+            AppError::SerializationError(e)
         })?;
 
         if field_check.unwrap_or(false) {
             debug!("Performing field check validation.");
             validate_json_message_content(&json_value, field_name, field_value_check).map_err(
                 |e| {
-                    let error_msg = format!("Failed to validate message content: {}", e);
-                    error!("{}", error_msg);
-                    anyhow::anyhow!(error_msg)
+                    error!("Failed to validate message content: {}", e);
+                    AppError::FieldCheckError(e)
                 },
             )?;
         }
@@ -134,9 +173,8 @@ pub fn validate_json(
             debug!("Performing field check validation on parsed JSON.");
             validate_json_message_content(&json_value, field_name, field_value_check).map_err(
                 |e| {
-                    let error_msg = format!("Failed to validate message content: {}", e);
-                    error!("{}", error_msg);
-                    anyhow::anyhow!(error_msg)
+                    error!("Failed to validate message content: {}", e);
+                    AppError::FieldCheckError(e)
                 },
             )?;
         }
@@ -144,13 +182,19 @@ pub fn validate_json(
         record_duration("only_json", field_check.unwrap_or(false));
     }
 
+    let rule_results = evaluate_rules(&json_value, rules);
+    if rule_results.iter().any(|result| !result.passed) {
+        error!("Failed to validate message rules.");
+        return Err(AppError::RuleCheckFailed(rule_results));
+    }
+
     info!("JSON validation completed.");
-    Ok(())
+    Ok(rule_results)
 }
 
 pub fn unescape_json(json_string: &str) -> Result<String, AppError> {
     trace!("Attempting to unescape JSON string.");
-    
+
     if json_string.starts_with('"') && json_string.ends_with('"') {
         let unescaped = serde_json::from_str::<String>(json_string)
             .map_err(|e| AppError::JsonUnescapeError(format!("Failed to unescape JSON: {}", e)))?;
@@ -170,25 +214,52 @@ pub fn validate_json_message_content(
     trace!("Starting field content validation.");
 
     if let (Some(field), Some(expected_value)) = (field_name, field_value_check) {
-        if let Some(actual_value) = json_value.get(&field) {
-            if actual_value != &expected_value {
-                let error_msg = format!(
-                    "Field '{}' value mismatch: expected {:?}, found {:?}",
-                    field, expected_value, actual_value
-                );
-                error!("{}", error_msg);
-                return Err(error_msg);
+        // Unlike the `eq` rule, the legacy check compares values strictly, so `"5"` does not
+        // match `5` and `1.0` does not match `1`.
+        let field_matches = FieldPath::key(&field).resolve(json_value);
+        let error_msg = match field_matches.first().map(|field_match| &field_match.lookup) {
+            Some(Lookup::Found(actual_value)) if *actual_value == &expected_value => {
+                info!("Field '{}' value matched expected value.", field);
+                return Ok(());
             }
-            info!("Field '{}' value matched expected value.", field);
-            Ok(())
-        } else {
-            let error_msg = format!("Field '{}' not found in the JSON", field);
-            error!("{}", error_msg);
-            Err(error_msg)
-        }
+            Some(Lookup::Found(actual_value)) => format!(
+                "Field '{}' value mismatch: expected {}, found {}",
+                field, expected_value, actual_value
+            ),
+            _ => format!("Field '{}' not found in the JSON", field),
+        };
+        error!("{}", error_msg);
+        Err(error_msg)
     } else {
         let error_msg = "Field name and value must be provided for validation".to_string();
         error!("{}", error_msg);
         Err(error_msg)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn field_check(json_value: JsonValue, expected_value: JsonValue) -> Result<(), String> {
+        validate_json_message_content(&json_value, Some("id".to_string()), Some(expected_value))
+    }
+
+    #[test]
+    fn test_field_check_compares_values_strictly() {
+        assert!(field_check(json!({ "id": 5 }), json!(5)).is_ok());
+        assert!(field_check(json!({ "id": "5" }), json!("5")).is_ok());
+        assert!(field_check(json!({ "id": { "a": [1] } }), json!({ "a": [1] })).is_ok());
+
+        assert_eq!(
+            field_check(json!({ "id": "5" }), json!(5)),
+            Err("Field 'id' value mismatch: expected 5, found \"5\"".to_string())
+        );
+        assert!(field_check(json!({ "id": 1.0 }), json!(1)).is_err());
+        assert_eq!(
+            field_check(json!({ "other": 5 }), json!(5)),
+            Err("Field 'id' not found in the JSON".to_string())
+        );
+    }
+}
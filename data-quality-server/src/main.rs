@@ -1,37 +1,67 @@
 /* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
 
-use crate::handlers::{load_descriptor_handler, validate_json_handler};
+use crate::handlers::{
+    create_profile_handler, delete_descriptor_handler, delete_profile_handler,
+    get_descriptor_binary_handler, get_descriptor_handler, get_descriptor_proto_handler,
+    get_profile_handler, list_descriptor_versions_handler, list_descriptors_handler,
+    list_profiles_handler, load_descriptor_handler, put_profile_handler, validate_json_handler,
+};
 use anyhow::{anyhow, Context, Result};
-use axum::{routing::post, Router};
+use arc_swap::ArcSwap;
+use axum::{
+    routing::{get, post},
+    Router,
+};
 use clap::Parser;
-use json_validation::validate_json;
+use descriptor_dir::{load_descriptor_dir, watch_descriptor_dir};
+use descriptor_store::{open_store, DescriptorStore, ProfileStore, StoreKind};
+use json_validation::{validate_json, ValidationInput};
 use metrics::init_meter_provider;
-use std::collections::HashMap;
-use std::{
-    env,
-    net::SocketAddr,
-    sync::{Arc, Mutex},
+use protobuf_descriptors::{
+    rebuild_descriptor_pool, DescriptorVersions, ValidationPool, VersionPools,
 };
+use schema_compatibility::CompatibilityMode;
+use std::{env, net::SocketAddr, path::PathBuf, sync::Arc};
 use tokio::net::TcpListener;
 use tokio::runtime::Builder;
 use tokio::sync::{RwLock, Semaphore};
+use validation_profiles::ValidationProfiles;
 
 use data_quality_settings::{load_env_variables, load_logging_config, parse_log_level};
-use tracing::{debug, error, info, span, trace, warn, Level};
+use tracing::{error, info, warn};
 
 pub mod app_error;
+pub mod descriptor_dir;
+pub mod descriptor_registry;
+pub mod descriptor_store;
+pub mod field_path;
 pub mod handlers;
 pub mod json_validation;
 pub mod metrics;
+pub mod proto_compiler;
+pub mod proto_text;
 pub mod protobuf_descriptors;
+pub mod rule_engine;
+pub mod schema_compatibility;
+#[cfg(test)]
+mod test_support;
+pub mod validation_profiles;
 
-type DescriptorMap = Arc<RwLock<HashMap<String, Vec<u8>>>>;
+type DescriptorMap = Arc<RwLock<DescriptorVersions>>;
 
 #[derive(Clone)]
 pub struct AppState {
     descriptor_map: DescriptorMap,
+    // Rebuilt whenever `descriptor_map` changes so `/validate` never has to decode descriptors.
+    descriptor_pool: Arc<ArcSwap<ValidationPool>>,
+    // Pools with an older version of one file, cleared whenever `descriptor_pool` is replaced.
+    version_pools: Arc<VersionPools>,
     enable_metrics: bool,
     semaphore: Arc<Semaphore>,
+    default_compatibility: CompatibilityMode,
+    descriptor_store: Arc<dyn DescriptorStore>,
+    profile_store: Arc<dyn ProfileStore>,
+    profiles: Arc<RwLock<ValidationProfiles>>,
 }
 
 #[derive(Parser, Debug)]
@@ -49,6 +79,26 @@ struct Args {
     #[clap(long, default_value_t = 2)]
     worker_threads: usize,
 
+    /// Compatibility mode for descriptor uploads that do not choose one
+    #[clap(long, value_enum, default_value_t = CompatibilityMode::Backward)]
+    compatibility: CompatibilityMode,
+
+    /// Where uploaded descriptors are persisted
+    #[clap(long, value_enum, default_value_t = StoreKind::Directory)]
+    store: StoreKind,
+
+    /// Directory or database file of the descriptor store
+    #[clap(long)]
+    store_path: Option<PathBuf>,
+
+    /// Directory with compiled .pb descriptors to load at startup
+    #[clap(long)]
+    descriptor_dir: Option<PathBuf>,
+
+    /// Reload the descriptor directory when its contents change
+    #[arg(long, action(clap::ArgAction::SetTrue))]
+    watch_descriptor_dir: bool,
+
     /// Logging level
     #[clap(short, long, default_value = "info")]
     log_level: String,
@@ -66,15 +116,11 @@ fn main() -> Result<(), anyhow::Error> {
     runtime.block_on(async {
         if let Some(json_string) = cli_args.json {
             info!("Received JSON to validate: {}", json_string);
-            match validate_json(
-                None,
-                &json_string,
-                None,
-                Some(false),
-                None,
-                None,
-                cli_args.enable_metrics,
-            ) {
+            let input = ValidationInput {
+                json_message: &json_string,
+                ..ValidationInput::default()
+            };
+            match validate_json(input, cli_args.enable_metrics) {
                 Ok(_) => {
                     info!("JSON validation passed.");
                     println!("JSON OK");
@@ -106,18 +152,109 @@ fn main() -> Result<(), anyhow::Error> {
 
         let semaphore = Arc::new(Semaphore::new(110));
 
+        let store_path = cli_args
+            .store_path
+            .clone()
+            .unwrap_or_else(|| default_store_path(cli_args.store));
+        let stores = open_store(cli_args.store, &store_path)?;
+        if cli_args.store == StoreKind::Memory {
+            warn!(
+                "The memory store keeps descriptors and validation profiles only until the \
+                 server stops; use --store directory or --store redb to persist them."
+            );
+        }
+        let mut descriptor_map = stores
+            .descriptors
+            .load_all()
+            .context("Failed to load descriptors from the descriptor store")?;
+        let (descriptor_pool, report) = rebuild_descriptor_pool(&descriptor_map);
+        // A stored descriptor that no longer loads is skipped rather than taking the others down.
+        for failed in &report.failed {
+            error!(
+                "Skipping stored descriptor {}: {}",
+                failed.file_name, failed.reason
+            );
+            descriptor_map.remove(&failed.file_name);
+        }
+        info!(
+            "Restored {} descriptor files from the descriptor store.",
+            descriptor_map.len()
+        );
+        let profiles = stores
+            .profiles
+            .load_profiles()
+            .context("Failed to load validation profiles from the profile store")?;
+        info!(
+            "Restored {} validation profiles from the profile store.",
+            profiles.len()
+        );
+
         let app_state = AppState {
-            descriptor_map: Arc::new(RwLock::new(HashMap::new())),
+            descriptor_map: Arc::new(RwLock::new(descriptor_map)),
+            descriptor_pool: Arc::new(ArcSwap::from_pointee(descriptor_pool)),
+            version_pools: Arc::new(VersionPools::default()),
             enable_metrics: cli_args.enable_metrics,
             semaphore,
+            default_compatibility: cli_args.compatibility,
+            descriptor_store: stores.descriptors,
+            profile_store: stores.profiles,
+            profiles: Arc::new(RwLock::new(profiles)),
+        };
+
+        let descriptor_dir = cli_args.descriptor_dir.clone().or_else(|| {
+            env::var("DATA_QUALITY_DESCRIPTOR_DIR")
+                .ok()
+                .map(PathBuf::from)
+        });
+        let _descriptor_dir_watcher = match descriptor_dir {
+            Some(descriptor_dir) => {
+                let loaded_files = load_descriptor_dir(&app_state, &descriptor_dir).await?;
+                if cli_args.watch_descriptor_dir {
+                    Some(watch_descriptor_dir(
+                        app_state.clone(),
+                        descriptor_dir,
+                        loaded_files,
+                    )?)
+                } else {
+                    None
+                }
+            }
+            None => None,
         };
 
         let app = Router::new()
             .route("/load_descriptor", post(load_descriptor_handler))
             .route("/validate", post(validate_json_handler))
+            .route(
+                "/profiles",
+                get(list_profiles_handler).post(create_profile_handler),
+            )
+            .route(
+                "/profiles/{name}",
+                get(get_profile_handler)
+                    .put(put_profile_handler)
+                    .delete(delete_profile_handler),
+            )
+            .route("/descriptors", get(list_descriptors_handler))
+            .route(
+                "/descriptors/{file_name}",
+                get(get_descriptor_handler).delete(delete_descriptor_handler),
+            )
+            .route(
+                "/descriptors/{file_name}/binary",
+                get(get_descriptor_binary_handler),
+            )
+            .route(
+                "/descriptors/{file_name}/versions",
+                get(list_descriptor_versions_handler),
+            )
+            .route(
+                "/descriptors/{file_name}/proto",
+                get(get_descriptor_proto_handler),
+            )
             .with_state(app_state);
 
-        let tcp_listener_address: SocketAddr = format!("{}", server_address)
+        let tcp_listener_address: SocketAddr = server_address
             .parse::<SocketAddr>()
             .map_err(|e| anyhow::anyhow!("Failed to parse SocketAddr: {}", e))?;
 
@@ -137,3 +274,10 @@ fn main() -> Result<(), anyhow::Error> {
         Ok(())
     })
 }
+
+fn default_store_path(store: StoreKind) -> PathBuf {
+    match store {
+        StoreKind::Redb => PathBuf::from("data/descriptor_store.redb"),
+        StoreKind::Memory | StoreKind::Directory => PathBuf::from("data/descriptor_store"),
+    }
+}
@@ -6,7 +6,7 @@ use opentelemetry_sdk::{
     runtime::Tokio,
     Resource,
 };
-use tracing::{debug, error, info, instrument, trace};
+use tracing::{debug, info, instrument, trace};
 
 pub fn create_metrics(
     meter: &Meter,
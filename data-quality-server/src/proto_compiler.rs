@@ -0,0 +1,91 @@
+/* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
+
+use miette::Diagnostic;
+use prost_reflect::DescriptorPool;
+use protox::file::{File, FileResolver};
+use protox::Compiler;
+use std::collections::HashMap;
+use tracing::{debug, error, info};
+
+use crate::app_error::AppError;
+
+/// Resolves imports from the uploaded sources first and then from the descriptors that are
+/// already loaded, so uploaded `.proto` files can import each other and any registered file.
+struct UploadFileResolver {
+    sources: HashMap<String, String>,
+    descriptor_pool: DescriptorPool,
+}
+
+impl FileResolver for UploadFileResolver {
+    fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+        if let Some(source) = self.sources.get(name) {
+            return File::from_source(name, source);
+        }
+        match self.descriptor_pool.get_file_by_name(name) {
+            Some(file) => File::decode_file_descriptor_proto(file.encode_to_vec().as_slice())
+                .map_err(protox::Error::new),
+            None => Err(protox::Error::file_not_found(name)),
+        }
+    }
+}
+
+/// Compiles `.proto` sources in-process and returns one encoded `FileDescriptorSet` per source,
+/// named after the source file. Imports are not included in the sets; they are resolved against
+/// the other sources and `descriptor_pool` when the descriptors are registered.
+pub fn compile_proto_sources(
+    sources: Vec<(String, String)>,
+    descriptor_pool: &DescriptorPool,
+) -> Result<Vec<(String, Vec<u8>)>, AppError> {
+    info!("Compiling {} .proto source(s)", sources.len());
+    let file_names: Vec<String> = sources.iter().map(|(name, _)| name.clone()).collect();
+
+    let mut compiler = Compiler::with_file_resolver(UploadFileResolver {
+        sources: sources.into_iter().collect(),
+        descriptor_pool: descriptor_pool.clone(),
+    });
+    compiler
+        .open_files(&file_names)
+        .map_err(|err| compile_error(&err))?;
+
+    let compiled_pool = compiler.descriptor_pool();
+    let mut compiled_files = Vec::with_capacity(file_names.len());
+    for file_name in file_names {
+        let Some(file) = compiled_pool.get_file_by_name(&file_name) else {
+            return Err(AppError::DescriptorError(format!(
+                "{} was not compiled",
+                file_name
+            )));
+        };
+        // Encoding the file descriptor directly keeps custom options, which `prost_types` drops.
+        let mut file_descriptor_set = Vec::new();
+        prost::encoding::bytes::encode(1, &file.encode_to_vec(), &mut file_descriptor_set);
+        debug!(
+            "Compiled {} ({} bytes)",
+            file_name,
+            file_descriptor_set.len()
+        );
+        compiled_files.push((file_name, file_descriptor_set));
+    }
+
+    Ok(compiled_files)
+}
+
+fn compile_error(err: &protox::Error) -> AppError {
+    let mut location = None;
+    if let (Some(source_code), Some(mut labels)) = (err.source_code(), err.labels()) {
+        if let Some(contents) = labels
+            .next()
+            .and_then(|label| source_code.read_span(label.inner(), 0, 0).ok())
+        {
+            location = Some((contents.line() + 1, contents.column() + 1));
+        }
+    }
+    error!("Failed to compile .proto source: {:?}", err);
+
+    AppError::ProtoCompileError {
+        file: err.file().map(str::to_string),
+        line: location.map(|(line, _)| line),
+        column: location.map(|(_, column)| column),
+        message: err.to_string(),
+    }
+}
@@ -0,0 +1,651 @@
+/* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
+
+use prost_reflect::{
+    Cardinality, DynamicMessage, EnumDescriptor, ExtensionDescriptor, FieldDescriptor,
+    FileDescriptor, Kind, MessageDescriptor, OneofDescriptor, ServiceDescriptor, Syntax, Value,
+};
+use std::fmt::Write;
+
+const INDENT: &str = "  ";
+const MAX_FIELD_NUMBER: i64 = 536_870_911;
+
+// Reconstructs `.proto` source from a compiled file descriptor. Comments are not part of the
+// descriptor and the options of services and methods are left out, so the output is equivalent
+// to, but not identical with, the original. Options of files, messages, fields, oneofs, enums and
+// enum values are written, including custom options such as `(dq.rules)`.
+pub fn file_to_proto_text(file: &FileDescriptor) -> String {
+    let mut out = String::new();
+    let syntax = file.syntax();
+
+    match syntax {
+        Syntax::Proto2 => out.push_str("syntax = \"proto2\";\n"),
+        Syntax::Proto3 => out.push_str("syntax = \"proto3\";\n"),
+    }
+    if !file.package_name().is_empty() {
+        let _ = writeln!(out, "\npackage {};", file.package_name());
+    }
+
+    let public_dependencies: Vec<String> = file
+        .public_dependencies()
+        .map(|dependency| dependency.name().to_string())
+        .collect();
+    let dependencies: Vec<FileDescriptor> = file.dependencies().collect();
+    if !dependencies.is_empty() {
+        out.push('\n');
+    }
+    for dependency in dependencies {
+        if public_dependencies
+            .iter()
+            .any(|name| name == dependency.name())
+        {
+            let _ = writeln!(out, "import public \"{}\";", dependency.name());
+        } else {
+            let _ = writeln!(out, "import \"{}\";", dependency.name());
+        }
+    }
+
+    let file_options = format_options(&file.options());
+    if !file_options.is_empty() {
+        out.push('\n');
+    }
+    write_option_statements(&mut out, &file_options, 0);
+
+    for enum_descriptor in file.enums() {
+        out.push('\n');
+        write_enum(&mut out, &enum_descriptor, 0);
+    }
+    for message_descriptor in file.messages() {
+        out.push('\n');
+        write_message(&mut out, &message_descriptor, syntax, 0);
+    }
+    write_extensions(&mut out, file.extensions().collect(), syntax, 0);
+    for service_descriptor in file.services() {
+        out.push('\n');
+        write_service(&mut out, &service_descriptor);
+    }
+
+    out
+}
+
+fn write_message(out: &mut String, message: &MessageDescriptor, syntax: Syntax, depth: usize) {
+    let indent = INDENT.repeat(depth);
+    let _ = writeln!(out, "{}message {} {{", indent, message.name());
+    write_option_statements(out, &format_options(&message.options()), depth + 1);
+
+    for enum_descriptor in message.child_enums() {
+        write_enum(out, &enum_descriptor, depth + 1);
+    }
+    for child_message in message.child_messages() {
+        if !child_message.is_map_entry() {
+            write_message(out, &child_message, syntax, depth + 1);
+        }
+    }
+
+    let mut written_oneofs: Vec<String> = Vec::new();
+    for field in message.fields() {
+        match field.containing_oneof() {
+            Some(oneof) if !is_synthetic_oneof(&oneof) => {
+                if !written_oneofs.iter().any(|name| name == oneof.name()) {
+                    write_oneof(out, &oneof, depth + 1);
+                    written_oneofs.push(oneof.name().to_string());
+                }
+            }
+            _ => write_field(out, &field, syntax, depth + 1),
+        }
+    }
+
+    let reserved_ranges: Vec<String> = message
+        .reserved_ranges()
+        .map(|range| format_range(range.start as i64, range.end as i64 - 1, MAX_FIELD_NUMBER))
+        .collect();
+    write_reserved(out, reserved_ranges, message.reserved_names(), depth + 1);
+
+    write_extensions(out, message.child_extensions().collect(), syntax, depth + 1);
+
+    let _ = writeln!(out, "{}}}", indent);
+}
+
+fn write_field(out: &mut String, field: &FieldDescriptor, syntax: Syntax, depth: usize) {
+    let indent = INDENT.repeat(depth);
+    let field_proto = field.field_descriptor_proto();
+
+    let label = if field.is_map() {
+        ""
+    } else {
+        match (field.cardinality(), syntax) {
+            (Cardinality::Repeated, _) => "repeated ",
+            (Cardinality::Required, _) => "required ",
+            (Cardinality::Optional, Syntax::Proto2) => "optional ",
+            (Cardinality::Optional, Syntax::Proto3) if field_proto.proto3_optional() => "optional ",
+            (Cardinality::Optional, Syntax::Proto3) => "",
+        }
+    };
+
+    let options = field_options(field);
+    let _ = writeln!(
+        out,
+        "{}{}{} {} = {}{};",
+        indent,
+        label,
+        field_type_name(field),
+        field.name(),
+        field.number(),
+        format_option_list(&options)
+    );
+}
+
+// The options written in brackets after a field: its default value, a non default `json_name`
+// and the field options.
+fn field_options(field: &FieldDescriptor) -> Vec<String> {
+    let field_proto = field.field_descriptor_proto();
+    let mut options = Vec::new();
+    if let Some(default_value) = &field_proto.default_value {
+        match field.kind() {
+            Kind::String => options.push(format!("default = \"{}\"", escape_string(default_value))),
+            // Descriptors hold bytes defaults C-escaped already.
+            Kind::Bytes => options.push(format!("default = \"{}\"", default_value)),
+            _ => options.push(format!("default = {}", default_value)),
+        }
+    }
+    if field.json_name() != default_json_name(field.name()) {
+        options.push(format!(
+            "json_name = \"{}\"",
+            escape_string(field.json_name())
+        ));
+    }
+    options.extend(format_options(&field.options()));
+    options
+}
+
+fn write_oneof(out: &mut String, oneof: &OneofDescriptor, depth: usize) {
+    let indent = INDENT.repeat(depth);
+    let _ = writeln!(out, "{}oneof {} {{", indent, oneof.name());
+    write_option_statements(out, &format_options(&oneof.options()), depth + 1);
+    for field in oneof.fields() {
+        let _ = writeln!(
+            out,
+            "{}{}{} {} = {}{};",
+            indent,
+            INDENT,
+            field_type_name(&field),
+            field.name(),
+            field.number(),
+            format_option_list(&field_options(&field))
+        );
+    }
+    let _ = writeln!(out, "{}}}", indent);
+}
+
+fn write_enum(out: &mut String, enum_descriptor: &EnumDescriptor, depth: usize) {
+    let indent = INDENT.repeat(depth);
+    let _ = writeln!(out, "{}enum {} {{", indent, enum_descriptor.name());
+    write_option_statements(out, &format_options(&enum_descriptor.options()), depth + 1);
+
+    // `values()` is ordered by number; aliases are written in the order they were declared.
+    for value in enum_descriptor
+        .enum_descriptor_proto()
+        .value
+        .iter()
+        .filter_map(|value| enum_descriptor.get_value_by_name(value.name()))
+    {
+        let _ = writeln!(
+            out,
+            "{}{}{} = {}{};",
+            indent,
+            INDENT,
+            value.name(),
+            value.number(),
+            format_option_list(&format_options(&value.options()))
+        );
+    }
+
+    let reserved_ranges: Vec<String> = enum_descriptor
+        .reserved_ranges()
+        .map(|range| format_range(*range.start() as i64, *range.end() as i64, i32::MAX as i64))
+        .collect();
+    write_reserved(
+        out,
+        reserved_ranges,
+        enum_descriptor.reserved_names(),
+        depth + 1,
+    );
+
+    let _ = writeln!(out, "{}}}", indent);
+}
+
+fn write_extensions(
+    out: &mut String,
+    extensions: Vec<ExtensionDescriptor>,
+    syntax: Syntax,
+    depth: usize,
+) {
+    let indent = INDENT.repeat(depth);
+    let mut extendees: Vec<String> = Vec::new();
+    for extension in &extensions {
+        let extendee = extension.containing_message().full_name().to_string();
+        if !extendees.contains(&extendee) {
+            extendees.push(extendee);
+        }
+    }
+
+    for extendee in extendees {
+        let _ = writeln!(out, "\n{}extend .{} {{", indent, extendee);
+        for extension in extensions
+            .iter()
+            .filter(|extension| extension.containing_message().full_name() == extendee)
+        {
+            let label = match (extension.cardinality(), syntax) {
+                (Cardinality::Repeated, _) => "repeated ",
+                (_, Syntax::Proto2) => "optional ",
+                (_, Syntax::Proto3) => "",
+            };
+            let _ = writeln!(
+                out,
+                "{}{}{}{} {} = {}{};",
+                indent,
+                INDENT,
+                label,
+                kind_type_name(&extension.kind()),
+                extension.name(),
+                extension.number(),
+                format_option_list(&format_options(&extension.options()))
+            );
+        }
+        let _ = writeln!(out, "{}}}", indent);
+    }
+}
+
+fn write_service(out: &mut String, service: &ServiceDescriptor) {
+    let _ = writeln!(out, "service {} {{", service.name());
+    for method in service.methods() {
+        let _ = writeln!(
+            out,
+            "{}rpc {}({}.{}) returns ({}.{});",
+            INDENT,
+            method.name(),
+            if method.is_client_streaming() {
+                "stream "
+            } else {
+                ""
+            },
+            method.input().full_name(),
+            if method.is_server_streaming() {
+                "stream "
+            } else {
+                ""
+            },
+            method.output().full_name()
+        );
+    }
+    out.push_str("}\n");
+}
+
+fn write_reserved<'a>(
+    out: &mut String,
+    reserved_ranges: Vec<String>,
+    reserved_names: impl Iterator<Item = &'a str>,
+    depth: usize,
+) {
+    let indent = INDENT.repeat(depth);
+    if !reserved_ranges.is_empty() {
+        let _ = writeln!(out, "{}reserved {};", indent, reserved_ranges.join(", "));
+    }
+    let reserved_names: Vec<String> = reserved_names
+        .map(|name| format!("\"{}\"", escape_string(name)))
+        .collect();
+    if !reserved_names.is_empty() {
+        let _ = writeln!(out, "{}reserved {};", indent, reserved_names.join(", "));
+    }
+}
+
+fn field_type_name(field: &FieldDescriptor) -> String {
+    match field.kind() {
+        Kind::Message(entry) if field.is_map() => format!(
+            "map<{}, {}>",
+            kind_type_name(&entry.map_entry_key_field().kind()),
+            kind_type_name(&entry.map_entry_value_field().kind())
+        ),
+        kind => kind_type_name(&kind),
+    }
+}
+
+fn kind_type_name(kind: &Kind) -> String {
+    match kind {
+        Kind::Double => "double".to_string(),
+        Kind::Float => "float".to_string(),
+        Kind::Int32 => "int32".to_string(),
+        Kind::Int64 => "int64".to_string(),
+        Kind::Uint32 => "uint32".to_string(),
+        Kind::Uint64 => "uint64".to_string(),
+        Kind::Sint32 => "sint32".to_string(),
+        Kind::Sint64 => "sint64".to_string(),
+        Kind::Fixed32 => "fixed32".to_string(),
+        Kind::Fixed64 => "fixed64".to_string(),
+        Kind::Sfixed32 => "sfixed32".to_string(),
+        Kind::Sfixed64 => "sfixed64".to_string(),
+        Kind::Bool => "bool".to_string(),
+        Kind::String => "string".to_string(),
+        Kind::Bytes => "bytes".to_string(),
+        Kind::Message(message_descriptor) => format!(".{}", message_descriptor.full_name()),
+        Kind::Enum(enum_descriptor) => format!(".{}", enum_descriptor.full_name()),
+    }
+}
+
+// proto3 `optional` fields are compiled into a oneof of their own that is not part of the source.
+fn is_synthetic_oneof(oneof: &OneofDescriptor) -> bool {
+    oneof
+        .fields()
+        .all(|field| field.field_descriptor_proto().proto3_optional())
+}
+
+fn default_json_name(field_name: &str) -> String {
+    let mut json_name = String::with_capacity(field_name.len());
+    let mut capitalize_next = false;
+    for character in field_name.chars() {
+        if character == '_' {
+            capitalize_next = true;
+        } else if capitalize_next {
+            json_name.extend(character.to_uppercase());
+            capitalize_next = false;
+        } else {
+            json_name.push(character);
+        }
+    }
+    json_name
+}
+
+fn format_range(start: i64, end: i64, max: i64) -> String {
+    if start == end {
+        start.to_string()
+    } else if end >= max {
+        format!("{} to max", start)
+    } else {
+        format!("{} to {}", start, end)
+    }
+}
+
+fn format_option_list(options: &[String]) -> String {
+    if options.is_empty() {
+        String::new()
+    } else {
+        format!(" [{}]", options.join(", "))
+    }
+}
+
+fn write_option_statements(out: &mut String, options: &[String], depth: usize) {
+    let indent = INDENT.repeat(depth);
+    for option in options {
+        let _ = writeln!(out, "{}option {};", indent, option);
+    }
+}
+
+// Formats every option set in `options`, one of the `google.protobuf.*Options` messages, as
+// `name = value`. Custom options are named by their extension, e.g. `(dq.rules) = {...}`, and
+// repeated options get one assignment per value.
+fn format_options(options: &DynamicMessage) -> Vec<String> {
+    let standard_options = options
+        .fields()
+        .map(|(field, value)| (field.name().to_string(), field.kind(), value));
+    let custom_options = options.extensions().map(|(extension, value)| {
+        (
+            format!("({})", extension.full_name()),
+            extension.kind(),
+            value,
+        )
+    });
+
+    let mut formatted = Vec::new();
+    for (name, kind, value) in standard_options.chain(custom_options) {
+        match value {
+            Value::List(values) => formatted.extend(
+                values
+                    .iter()
+                    .map(|value| format!("{} = {}", name, format_value(&kind, value))),
+            ),
+            value => formatted.push(format!("{} = {}", name, format_value(&kind, value))),
+        }
+    }
+    formatted
+}
+
+fn format_value(kind: &Kind, value: &Value) -> String {
+    match value {
+        Value::Bool(value) => value.to_string(),
+        Value::I32(value) => value.to_string(),
+        Value::I64(value) => value.to_string(),
+        Value::U32(value) => value.to_string(),
+        Value::U64(value) => value.to_string(),
+        Value::F32(value) => format_float(*value as f64),
+        Value::F64(value) => format_float(*value),
+        Value::String(value) => format!("\"{}\"", escape_string(value)),
+        Value::Bytes(value) => format!("\"{}\"", escape_bytes(value)),
+        Value::EnumNumber(number) => match kind {
+            Kind::Enum(enum_descriptor) => enum_descriptor
+                .get_value(*number)
+                .map(|value| value.name().to_string())
+                .unwrap_or_else(|| number.to_string()),
+            _ => number.to_string(),
+        },
+        Value::Message(message) => format!("{{{}}}", message.to_text_format()),
+        Value::List(_) | Value::Map(_) => String::new(),
+    }
+}
+
+fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        "nan".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+// Escapes a string literal the way the protobuf text format reads it back.
+fn escape_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\'' => escaped.push_str("\\'"),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            character if character.is_control() => {
+                for byte in character.to_string().bytes() {
+                    let _ = write!(escaped, "\\{:03o}", byte);
+                }
+            }
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+fn escape_bytes(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\'' => escaped.push_str("\\'"),
+            b'\\' => escaped.push_str("\\\\"),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => {
+                let _ = write!(escaped, "\\{:03o}", byte);
+            }
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto_compiler::compile_proto_sources;
+    use prost_reflect::DescriptorPool;
+
+    const EXAMPLE_PROTO: &str = include_str!("../../data/input/proto/example.proto");
+    const RULES_PROTO: &str = include_str!("../../data/input/proto/dq/rules.proto");
+
+    const ESCAPES_PROTO: &str = r#"
+        syntax = "proto2";
+        package escapes;
+        import "dq/rules.proto";
+
+        option java_package = "com.example.escapes";
+
+        message Defaults {
+            option deprecated = true;
+
+            optional string quoted = 1 [default = "say \"hi\" \\ 'bye'\n\ttab"];
+            optional bytes raw = 2 [default = "a\"b\\c\001\377"];
+            optional double ratio = 3 [default = inf];
+            optional Level level = 4 [default = HIGH];
+            optional string renamed = 5 [json_name = "other\"Name"];
+            repeated int32 packed_numbers = 6 [packed = true];
+            optional string sku = 7 [(dq.rules) = {required: true, string: {min_len: 1, pattern: "^[A-Z]\"+$", in: ["A", "B\\C"]}}];
+            optional int32 count = 8 [deprecated = true, (dq.rules).int32 = {gte: 0, lt: 150}];
+            repeated string tags = 9 [(dq.rules).repeated = {max_items: 2, items: {string: {min_len: 2}}}];
+            map<string, int64> totals = 10;
+
+            oneof choice {
+                string left = 11 [(dq.rules).string.max_len = 3];
+                int32 right = 12;
+            }
+
+            reserved 20 to 25, 100 to max;
+            reserved "old_name";
+        }
+
+        enum Level {
+            option allow_alias = true;
+            LOW = 0;
+            HIGH = 1;
+            TOP = 1 [deprecated = true];
+        }
+    "#;
+
+    // Compiles `sources` into one pool, resolving imports against each other.
+    fn compile(sources: &[(&str, String)]) -> DescriptorPool {
+        let sources = sources
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.clone()))
+            .collect();
+        let mut pool = DescriptorPool::global();
+        for (_, file_descriptor_set) in compile_proto_sources(sources, &pool).unwrap() {
+            pool.decode_file_descriptor_set(file_descriptor_set.as_slice())
+                .unwrap();
+        }
+        pool
+    }
+
+    // Renders every file of `sources`, compiles the rendered text and checks that it describes
+    // the same schema, options included.
+    fn assert_round_trip(sources: &[(&str, String)]) {
+        let original = compile(sources);
+        let rendered: Vec<(&str, String)> = sources
+            .iter()
+            .map(|(name, _)| {
+                let file = original.get_file_by_name(name).unwrap();
+                (*name, file_to_proto_text(&file))
+            })
+            .collect();
+        let recompiled = compile(&rendered);
+
+        for (name, proto_text) in &rendered {
+            let original_file = original.get_file_by_name(name).unwrap();
+            let recompiled_file = recompiled.get_file_by_name(name).unwrap();
+
+            let mut original_proto = original_file.file_descriptor_proto().clone();
+            let mut recompiled_proto = recompiled_file.file_descriptor_proto().clone();
+            original_proto.source_code_info = None;
+            recompiled_proto.source_code_info = None;
+            assert_eq!(
+                original_proto, recompiled_proto,
+                "{}:\n{}",
+                name, proto_text
+            );
+
+            // `prost_types` drops custom options, so those are compared separately. Options of
+            // different pools never compare equal, so their text format is compared.
+            assert_eq!(
+                original_file.options().to_text_format(),
+                recompiled_file.options().to_text_format(),
+                "{}",
+                name
+            );
+            for original_message in original_file.messages() {
+                let recompiled_message = recompiled
+                    .get_message_by_name(original_message.full_name())
+                    .unwrap();
+                assert_eq!(
+                    original_message.options().to_text_format(),
+                    recompiled_message.options().to_text_format(),
+                    "{}",
+                    original_message.full_name()
+                );
+                for original_field in original_message.fields() {
+                    let recompiled_field = recompiled_message
+                        .get_field_by_name(original_field.name())
+                        .unwrap();
+                    assert_eq!(
+                        original_field.options().to_text_format(),
+                        recompiled_field.options().to_text_format(),
+                        "{}\n{}",
+                        original_field.full_name(),
+                        proto_text
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_input_protos_round_trip() {
+        assert_round_trip(&[
+            ("dq/rules.proto", RULES_PROTO.to_string()),
+            ("example.proto", EXAMPLE_PROTO.to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_escapes_and_options_round_trip() {
+        assert_round_trip(&[
+            ("dq/rules.proto", RULES_PROTO.to_string()),
+            ("escapes.proto", ESCAPES_PROTO.to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_custom_options_are_rendered() {
+        let pool = compile(&[
+            ("dq/rules.proto", RULES_PROTO.to_string()),
+            ("escapes.proto", ESCAPES_PROTO.to_string()),
+        ]);
+        let proto_text = file_to_proto_text(&pool.get_file_by_name("escapes.proto").unwrap());
+
+        assert!(
+            proto_text.contains(
+                "optional int32 count = 8 [deprecated = true, (dq.rules) = {int32{lt:150,gte:0}}];"
+            ),
+            "{}",
+            proto_text
+        );
+        assert!(
+            proto_text.contains(
+                r#"optional string quoted = 1 [default = "say \"hi\" \\ \'bye\'\n\ttab"];"#
+            ),
+            "{}",
+            proto_text
+        );
+    }
+
+    #[test]
+    fn test_escape_string() {
+        assert_eq!(escape_string(r#"a"b\c"#), r#"a\"b\\c"#);
+        assert_eq!(escape_string("line\nnext\u{1}"), "line\\nnext\\001");
+        assert_eq!(escape_string("héllo"), "héllo");
+        assert_eq!(escape_bytes(b"a\"\\\xff"), "a\\\"\\\\\\377");
+    }
+}
@@ -1,36 +1,217 @@
 /* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
 
 use anyhow::Result;
-use axum::{
-    extract::{Json, State},
-    http::StatusCode,
-    response::IntoResponse,
-};
-use base64;
-use prost_reflect::DescriptorPool;
-use prost_types::FileDescriptorSet;
-use serde::Deserialize;
-use std::collections::HashMap;
+use dynamic_message::Constraints;
+use prost::Message;
+use prost_reflect::{DescriptorPool, MessageDescriptor};
+use prost_types::{FileDescriptorProto, FileDescriptorSet};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use tracing::{debug, error, info, span, Level};
+use tracing::{debug, error, info};
 
-use crate::AppState;
-
-type DescriptorMap = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+use crate::schema_compatibility::CompatibilityMode;
 
 #[derive(Deserialize)]
 pub struct LoadDescriptorRequest {
     pub file_name: String,
-    pub file_content: String,
+    /// Base64 encoded `FileDescriptorSet`.
+    pub file_content: Option<String>,
+    /// `.proto` source, compiled by the server.
+    pub proto_source: Option<String>,
+    pub compatibility: Option<CompatibilityMode>,
+}
+
+/// The name a descriptor is registered under: the `.proto` file it was compiled from. A compiled
+/// `foo.pb` is registered as `foo.proto`, whether it is uploaded as JSON, as part of a multipart
+/// upload or found in the descriptor directory.
+pub fn descriptor_file_name(file_name: &str) -> String {
+    match file_name.strip_suffix(".pb") {
+        Some(file_stem) => format!("{}.proto", file_stem),
+        None => file_name.to_string(),
+    }
+}
+
+// Every upload of a file name is kept as a new version, oldest first.
+pub type DescriptorVersions = HashMap<String, Vec<DescriptorEntry>>;
+
+#[derive(Debug, Clone)]
+pub struct DescriptorEntry {
+    pub version: u32,
+    pub file_content: Vec<u8>,
+    pub uploaded_at: u64,
+    pub sha256: String,
+}
+
+impl DescriptorEntry {
+    pub fn new(version: u32, file_content: Vec<u8>) -> Self {
+        let uploaded_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        DescriptorEntry::restore(version, file_content, uploaded_at)
+    }
+
+    pub fn restore(version: u32, file_content: Vec<u8>, uploaded_at: u64) -> Self {
+        let sha256 = format!("{:x}", Sha256::digest(&file_content));
+
+        DescriptorEntry {
+            version,
+            file_content,
+            uploaded_at,
+            sha256,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DescriptorSummary {
+    pub file_name: String,
+    pub version: u32,
+    pub versions: Vec<u32>,
+    pub uploaded_at: u64,
+    pub sha256: String,
+    pub size: usize,
+    pub proto_files: Vec<String>,
+    pub message_types: Vec<String>,
+    pub enum_types: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedDescriptor {
+    pub file_name: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnresolvedImport {
+    pub file_name: String,
+    pub proto_file: String,
+    pub import: String,
+}
+
+/// Outcome of loading a batch of descriptor files into a pool. Files that failed, including the
+/// ones with unresolved imports, are listed in `failed` and left out of the pool.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LoadReport {
+    pub loaded: Vec<String>,
+    pub failed: Vec<FailedDescriptor>,
+    pub unresolved_imports: Vec<UnresolvedImport>,
+}
+
+impl LoadReport {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    pub fn failed_file_names(&self) -> Vec<String> {
+        self.failed
+            .iter()
+            .map(|failed| failed.file_name.clone())
+            .collect()
+    }
+}
+
+/// A descriptor pool with the `(dq.rules)` constraints of its fields, parsed when the pool is
+/// built so validations do not have to read them from the field options.
+#[derive(Debug, Default)]
+pub struct ValidationPool {
+    pub descriptor_pool: DescriptorPool,
+    pub constraints: Constraints,
+}
+
+impl ValidationPool {
+    pub fn new(descriptor_pool: DescriptorPool) -> Self {
+        let constraints = Constraints::from_pool(&descriptor_pool);
+        ValidationPool {
+            descriptor_pool,
+            constraints,
+        }
+    }
+}
+
+/// Pools in which one descriptor file is taken at an older version, built on first use and kept
+/// until the descriptors change. An older pool also holds the latest version of every other file,
+/// so every change to the descriptor map must `clear` the cache.
+#[derive(Debug, Default)]
+pub struct VersionPools {
+    pools: Mutex<HashMap<(String, u32), Arc<ValidationPool>>>,
 }
 
-#[tracing::instrument]
+impl VersionPools {
+    pub fn get_or_build(
+        &self,
+        descriptor_map: &DescriptorVersions,
+        file_name: &str,
+        version: u32,
+    ) -> Result<Arc<ValidationPool>, LoadReport> {
+        let key = (file_name.to_string(), version);
+        if let Some(pool) = self.lock().get(&key) {
+            return Ok(pool.clone());
+        }
+
+        debug!("Building pool for {} version {}", file_name, version);
+        let (pool, report) = rebuild_descriptor_pool_at_version(descriptor_map, file_name, version);
+        if !report.is_success() {
+            return Err(report);
+        }
+        let pool = Arc::new(pool);
+        self.lock().insert(key, pool.clone());
+        Ok(pool)
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<(String, u32), Arc<ValidationPool>>> {
+        // The map holds no invariants a panic could break, so a poisoned lock is still usable.
+        self.pools
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A `FileDescriptorSet` whose files are kept encoded, so their custom options are not lost.
+#[derive(Clone, PartialEq, Message)]
+struct EncodedFileDescriptorSet {
+    #[prost(bytes = "vec", repeated, tag = "1")]
+    file: Vec<Vec<u8>>,
+}
+
+#[derive(Debug)]
+pub enum LoadFailure {
+    Invalid(String),
+    UnresolvedImports(Vec<UnresolvedImport>),
+}
+
+impl fmt::Display for LoadFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadFailure::Invalid(reason) => write!(f, "{}", reason),
+            LoadFailure::UnresolvedImports(imports) => write!(
+                f,
+                "Unresolved imports: {}",
+                imports
+                    .iter()
+                    .map(|import| format!("{} (imported by {})", import.import, import.proto_file))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+#[tracing::instrument(skip(descriptor_pool, proto_content))]
 pub fn load_descriptor(
     descriptor_pool: &mut DescriptorPool,
     filename: &str,
     proto_content: &[u8],
-) -> Result<(), String> {
+) -> Result<(), LoadFailure> {
     info!("load_descriptor: {}", filename);
 
     let file_descriptor_set: FileDescriptorSet =
@@ -39,83 +220,581 @@ pub fn load_descriptor(
                 "Failed to parse .proto definition for {}: {:?}",
                 filename, e
             );
-            format!(
+            LoadFailure::Invalid(format!(
                 "Failed to parse .proto definition for {}: {:?}",
                 filename, e
-            )
+            ))
         })?;
 
-    descriptor_pool
-        .add_file_descriptor_set(file_descriptor_set)
+    let unresolved_imports =
+        find_unresolved_imports(descriptor_pool, filename, &file_descriptor_set);
+    if !unresolved_imports.is_empty() {
+        debug!(
+            "{} has {} unresolved imports",
+            filename,
+            unresolved_imports.len()
+        );
+        return Err(LoadFailure::UnresolvedImports(unresolved_imports));
+    }
+
+    // The pool skips files it already has, so a different copy of a loaded file would silently
+    // lose to whichever copy happened to be loaded first.
+    if let Some(conflicting_file) = file_descriptor_set
+        .file
+        .iter()
+        .find(|file| conflicts_with_pool(descriptor_pool, file))
+    {
+        error!(
+            "{} contains a version of {} that differs from the loaded one",
+            filename,
+            conflicting_file.name()
+        );
+        return Err(LoadFailure::Invalid(format!(
+            "{} conflicts with the loaded descriptors: it contains a different version of {}",
+            filename,
+            conflicting_file.name()
+        )));
+    }
+
+    // Decode the raw bytes again rather than adding `file_descriptor_set`: prost_types drops
+    // custom options such as `(dq.rules)` that the constraint checks rely on.
+    let mut candidate_pool = descriptor_pool.clone();
+    candidate_pool
+        .decode_file_descriptor_set(proto_content)
         .map_err(|e| {
             error!(
                 "Failed to add file descriptor to pool ({}): {:?}",
                 filename, e
             );
-            format!(
-                "Failed to add file descriptor to pool ({}): {:?}",
+            LoadFailure::Invalid(format!(
+                "Failed to add file descriptor to pool ({}): {}",
                 filename, e
-            )
+            ))
         })?;
 
+    // Constraints are parsed once per pool, so rules that cannot be used, such as an invalid
+    // `string.pattern`, reject the descriptor instead of being skipped on every validation.
+    Constraints::from_files(
+        file_descriptor_set
+            .file
+            .iter()
+            .filter_map(|file| candidate_pool.get_file_by_name(file.name())),
+    )
+    .map_err(|err| {
+        error!("Invalid constraints in {}: {}", filename, err);
+        LoadFailure::Invalid(err)
+    })?;
+    *descriptor_pool = candidate_pool;
+
     info!("Successfully loaded descriptor from file: {}", filename);
     Ok(())
 }
 
-#[tracing::instrument]
+fn conflicts_with_pool(descriptor_pool: &DescriptorPool, file: &FileDescriptorProto) -> bool {
+    // The well-known types of the global pool may be built from a different protobuf release
+    // than the copies compilers embed; either copy will do.
+    if DescriptorPool::global()
+        .get_file_by_name(file.name())
+        .is_some()
+    {
+        return false;
+    }
+    let Some(loaded_file) = descriptor_pool.get_file_by_name(file.name()) else {
+        return false;
+    };
+
+    let mut loaded_file = loaded_file.file_descriptor_proto().clone();
+    let mut file = file.clone();
+    loaded_file.source_code_info = None;
+    file.source_code_info = None;
+    loaded_file != file
+}
+
+/// Loads every file it can and reports the rest. Files are retried while other files keep
+/// loading, so a file may import a `.proto` that is only defined by a later file in `files`.
+///
+/// Descriptor sets usually embed the files they import. A `.proto` file that is also one of
+/// `files` is always taken from its own entry, so a new version of a shared file is not shadowed
+/// by an older copy embedded in a file importing it.
+#[tracing::instrument(skip(descriptor_pool, files))]
 pub fn load_descriptors(
     descriptor_pool: &mut DescriptorPool,
     files: Vec<(String, Vec<u8>)>,
-) -> Result<(), String> {
+) -> LoadReport {
     info!("load_descriptors");
 
-    let mut failed_files = Vec::new();
+    let registered_files: HashSet<String> =
+        files.iter().map(|(filename, _)| filename.clone()).collect();
+    let mut report = LoadReport::default();
+    let mut pending: Vec<(String, Vec<u8>)> = files
+        .into_iter()
+        .map(|(filename, proto_content)| {
+            let proto_content = strip_registered_files(&filename, proto_content, &registered_files);
+            (filename, proto_content)
+        })
+        .collect();
+    // Load in a fixed order, so a conflict is always reported for the same file.
+    pending.sort_by(|a, b| a.0.cmp(&b.0));
 
-    for (filename, proto_content) in files {
-        debug!("Processing file: {}", filename);
+    loop {
+        let loaded_before = report.loaded.len();
+        let mut deferred = Vec::new();
 
-        if let Err(err) = load_descriptor(descriptor_pool, &filename, &proto_content) {
-            error!("Error loading file {}: {}", filename, err);
-            failed_files.push(filename);
-        } else {
-            debug!("Successfully processed file: {}", filename);
+        for (filename, proto_content) in pending {
+            debug!("Processing file: {}", filename);
+
+            match load_descriptor(descriptor_pool, &filename, &proto_content) {
+                Ok(()) => {
+                    debug!("Successfully processed file: {}", filename);
+                    report.loaded.push(filename);
+                }
+                Err(LoadFailure::UnresolvedImports(imports)) => {
+                    deferred.push((filename, proto_content, imports))
+                }
+                Err(err) => {
+                    error!("Error loading file {}: {}", filename, err);
+                    report.failed.push(FailedDescriptor {
+                        file_name: filename,
+                        reason: err.to_string(),
+                    });
+                }
+            }
         }
+
+        if deferred.is_empty() || report.loaded.len() == loaded_before {
+            for (filename, _, imports) in deferred {
+                let err = LoadFailure::UnresolvedImports(imports);
+                error!("Error loading file {}: {}", filename, err);
+                report.failed.push(FailedDescriptor {
+                    file_name: filename,
+                    reason: err.to_string(),
+                });
+                if let LoadFailure::UnresolvedImports(imports) = err {
+                    report.unresolved_imports.extend(imports);
+                }
+            }
+            break;
+        }
+        pending = deferred
+            .into_iter()
+            .map(|(filename, proto_content, _)| (filename, proto_content))
+            .collect();
     }
 
-    if !failed_files.is_empty() {
-        let failed_files_list = failed_files.join(", ");
+    if !report.is_success() {
         error!(
             "Failed to load descriptors for the following files: {}",
-            failed_files_list
+            report.failed_file_names().join(", ")
         );
     } else {
         info!("All files successfully loaded into the descriptor pool.");
     }
 
-    Ok(())
+    report
+}
+
+// Removes the embedded copies of files in `registered_files` from the descriptor set of
+// `filename`. Content that cannot be decoded is returned as is for `load_descriptor` to report.
+fn strip_registered_files(
+    filename: &str,
+    proto_content: Vec<u8>,
+    registered_files: &HashSet<String>,
+) -> Vec<u8> {
+    let Ok(encoded_set) = EncodedFileDescriptorSet::decode(proto_content.as_slice()) else {
+        return proto_content;
+    };
+
+    let mut kept_files = Vec::with_capacity(encoded_set.file.len());
+    for encoded_file in encoded_set.file {
+        let Ok(file) = FileDescriptorProto::decode(encoded_file.as_slice()) else {
+            return proto_content;
+        };
+        if file.name() != filename && registered_files.contains(file.name()) {
+            debug!(
+                "Using the registered {} instead of the copy in {}",
+                file.name(),
+                filename
+            );
+            continue;
+        }
+        kept_files.push(encoded_file);
+    }
+
+    EncodedFileDescriptorSet { file: kept_files }.encode_to_vec()
 }
 
+fn find_unresolved_imports(
+    descriptor_pool: &DescriptorPool,
+    filename: &str,
+    file_descriptor_set: &FileDescriptorSet,
+) -> Vec<UnresolvedImport> {
+    let mut unresolved_imports = Vec::new();
+
+    for file in &file_descriptor_set.file {
+        for import in &file.dependency {
+            let in_set = file_descriptor_set
+                .file
+                .iter()
+                .any(|other| other.name() == import);
+            if !in_set && descriptor_pool.get_file_by_name(import).is_none() {
+                unresolved_imports.push(UnresolvedImport {
+                    file_name: filename.to_string(),
+                    proto_file: file.name().to_string(),
+                    import: import.clone(),
+                });
+            }
+        }
+    }
+
+    unresolved_imports
+}
+
+/// Builds the pool from the latest version of every descriptor file. Files that fail to load are
+/// left out of the pool and listed in the report.
 pub fn rebuild_descriptor_pool(
-    descriptor_map: &HashMap<String, Vec<u8>>,
-) -> Result<DescriptorPool, String> {
-    let mut descriptor_pool = DescriptorPool::default();
-
-    for (file_name, file_content) in descriptor_map {
-        let file_descriptor_set: FileDescriptorSet =
-            prost::Message::decode(file_content.as_slice()).map_err(|e| {
-                let error_msg = format!("Failed to parse descriptor {}: {:?}", file_name, e);
-                error!("{}", error_msg);
-                error_msg
-            })?;
+    descriptor_map: &DescriptorVersions,
+) -> (ValidationPool, LoadReport) {
+    build_descriptor_pool(
+        descriptor_map
+            .iter()
+            .filter_map(|(file_name, versions)| Some((file_name, versions.last()?))),
+    )
+}
+
+/// Builds the pool from the latest version of every descriptor file, except for `file_name`,
+/// which is taken at `version`.
+pub fn rebuild_descriptor_pool_at_version(
+    descriptor_map: &DescriptorVersions,
+    file_name: &str,
+    version: u32,
+) -> (ValidationPool, LoadReport) {
+    build_descriptor_pool(descriptor_map.iter().filter_map(|(name, versions)| {
+        if name == file_name {
+            Some((
+                name,
+                versions.iter().find(|entry| entry.version == version)?,
+            ))
+        } else {
+            Some((name, versions.last()?))
+        }
+    }))
+}
 
+fn build_descriptor_pool<'a>(
+    entries: impl Iterator<Item = (&'a String, &'a DescriptorEntry)>,
+) -> (ValidationPool, LoadReport) {
+    // The global pool already contains the google.protobuf well-known types, so descriptor sets
+    // compiled without `--include_imports` can still reference Timestamp, Any, etc.
+    let mut descriptor_pool = DescriptorPool::global();
+
+    let files = entries
+        .map(|(file_name, entry)| (file_name.clone(), entry.file_content.clone()))
+        .collect();
+    let report = load_descriptors(&mut descriptor_pool, files);
+
+    (ValidationPool::new(descriptor_pool), report)
+}
+
+pub fn proto_file_names(entry: &DescriptorEntry) -> Result<Vec<String>, String> {
+    let file_descriptor_set: FileDescriptorSet =
+        prost::Message::decode(entry.file_content.as_slice())
+            .map_err(|e| format!("Failed to parse descriptor: {:?}", e))?;
+
+    Ok(file_descriptor_set
+        .file
+        .into_iter()
+        .map(|file| file.name().to_string())
+        .collect())
+}
+
+/// The `.proto` files of `proto_files`, the contents of the descriptor `file_name`, that belong to
+/// that descriptor and not to the imports it embeds: an embedded file belongs to the descriptor
+/// registered under its name, and the well-known types to none.
+pub fn own_proto_files(
+    descriptor_map: &DescriptorVersions,
+    file_name: &str,
+    proto_files: &[String],
+) -> Vec<String> {
+    let well_known_types = DescriptorPool::global();
+
+    proto_files
+        .iter()
+        .filter(|proto_file| {
+            proto_file.as_str() == file_name
+                || (!descriptor_map.contains_key(proto_file.as_str())
+                    && well_known_types.get_file_by_name(proto_file).is_none())
+        })
+        .cloned()
+        .collect()
+}
+
+/// Summarizes `entry`, a version of `file_name`. Only the types of the descriptor's own `.proto`
+/// files are listed, see `own_proto_files`.
+pub fn describe_descriptor(
+    descriptor_pool: &DescriptorPool,
+    descriptor_map: &DescriptorVersions,
+    file_name: &str,
+    entry: &DescriptorEntry,
+) -> Result<DescriptorSummary, String> {
+    let proto_files = proto_file_names(entry)?;
+    let mut message_types = Vec::new();
+    let mut enum_types = Vec::new();
+
+    for file in own_proto_files(descriptor_map, file_name, &proto_files)
+        .iter()
+        .filter_map(|proto_file| descriptor_pool.get_file_by_name(proto_file))
+    {
+        for message_descriptor in file.messages() {
+            collect_types(&message_descriptor, &mut message_types, &mut enum_types);
+        }
+        enum_types.extend(
+            file.enums()
+                .map(|enum_descriptor| enum_descriptor.full_name().to_string()),
+        );
+    }
+
+    Ok(DescriptorSummary {
+        file_name: file_name.to_string(),
+        version: entry.version,
+        versions: descriptor_map
+            .get(file_name)
+            .map(|versions| versions.iter().map(|entry| entry.version).collect())
+            .unwrap_or_default(),
+        uploaded_at: entry.uploaded_at,
+        sha256: entry.sha256.clone(),
+        size: entry.file_content.len(),
+        proto_files,
+        message_types,
+        enum_types,
+    })
+}
+
+fn collect_types(
+    message_descriptor: &MessageDescriptor,
+    message_types: &mut Vec<String>,
+    enum_types: &mut Vec<String>,
+) {
+    if message_descriptor.is_map_entry() {
+        return;
+    }
+    message_types.push(message_descriptor.full_name().to_string());
+    enum_types.extend(
+        message_descriptor
+            .child_enums()
+            .map(|enum_descriptor| enum_descriptor.full_name().to_string()),
+    );
+    for child_message in message_descriptor.child_messages() {
+        collect_types(&child_message, message_types, enum_types);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto_compiler::compile_proto_sources;
+
+    // Compiles `source` on its own into the encoded descriptor set of `file_name`.
+    fn descriptor_set(file_name: &str, source: &str) -> Vec<u8> {
+        compile_proto_sources(
+            vec![(file_name.to_string(), source.to_string())],
+            &DescriptorPool::global(),
+        )
+        .unwrap()
+        .remove(0)
+        .1
+    }
+
+    // Compiles `sources` together into one encoded descriptor set per file, without the imports.
+    fn descriptor_sets(sources: &[(&str, &str)]) -> Vec<(String, Vec<u8>)> {
+        compile_proto_sources(
+            sources
+                .iter()
+                .map(|(file_name, source)| (file_name.to_string(), source.to_string()))
+                .collect(),
+            &DescriptorPool::global(),
+        )
+        .unwrap()
+    }
+
+    fn loaded_file(descriptor_pool: &DescriptorPool, file_name: &str) -> FileDescriptorProto {
         descriptor_pool
-            .add_file_descriptor_set(file_descriptor_set)
-            .map_err(|e| {
-                let error_msg = format!("Failed to add descriptor {}: {:?}", file_name, e);
-                error!("{}", error_msg);
-                error_msg
-            })?;
+            .get_file_by_name(file_name)
+            .unwrap()
+            .file_descriptor_proto()
+            .clone()
     }
 
-    Ok(descriptor_pool)
+    #[test]
+    fn test_corrupt_descriptor_is_reported_as_failed() {
+        let mut descriptor_pool = DescriptorPool::new();
+        let report = load_descriptors(
+            &mut descriptor_pool,
+            vec![
+                // A length-delimited field that claims more bytes than there are.
+                ("corrupt.proto".to_string(), b"\x0a\x05abc".to_vec()),
+                (
+                    "item.proto".to_string(),
+                    descriptor_set("item.proto", "syntax = \"proto3\"; message Item {}"),
+                ),
+            ],
+        );
+
+        assert_eq!(report.loaded, vec!["item.proto"]);
+        assert_eq!(report.failed_file_names(), vec!["corrupt.proto"]);
+        assert!(report.failed[0]
+            .reason
+            .starts_with("Failed to parse .proto definition for corrupt.proto"));
+        assert!(report.unresolved_imports.is_empty());
+        assert!(descriptor_pool.get_file_by_name("corrupt.proto").is_none());
+    }
+
+    #[test]
+    fn test_missing_import_is_reported_as_unresolved() {
+        let files = descriptor_sets(&[
+            (
+                "order.proto",
+                "syntax = \"proto3\"; import \"missing.proto\"; message Order { Missing m = 1; }",
+            ),
+            ("missing.proto", "syntax = \"proto3\"; message Missing {}"),
+        ]);
+        let mut descriptor_pool = DescriptorPool::new();
+        let report = load_descriptors(&mut descriptor_pool, files[..1].to_vec());
+
+        assert!(report.loaded.is_empty());
+        assert_eq!(report.failed_file_names(), vec!["order.proto"]);
+        assert_eq!(report.unresolved_imports.len(), 1);
+        let unresolved_import = &report.unresolved_imports[0];
+        assert_eq!(unresolved_import.file_name, "order.proto");
+        assert_eq!(unresolved_import.proto_file, "order.proto");
+        assert_eq!(unresolved_import.import, "missing.proto");
+    }
+
+    #[test]
+    fn test_dependent_files_load_in_any_order() {
+        let files = descriptor_sets(&[
+            (
+                "a.proto",
+                "syntax = \"proto3\"; import \"b.proto\"; message A { B b = 1; }",
+            ),
+            (
+                "b.proto",
+                "syntax = \"proto3\"; import \"c.proto\"; message B { C c = 1; }",
+            ),
+            ("c.proto", "syntax = \"proto3\"; message C {}"),
+        ]);
+
+        for order in [[0, 1, 2], [2, 1, 0], [1, 2, 0]] {
+            let mut descriptor_pool = DescriptorPool::new();
+            let report = load_descriptors(
+                &mut descriptor_pool,
+                order.iter().map(|&index| files[index].clone()).collect(),
+            );
+
+            assert!(report.is_success(), "order {:?}: {:?}", order, report);
+            let mut loaded = report.loaded.clone();
+            loaded.sort();
+            assert_eq!(loaded, vec!["a.proto", "b.proto", "c.proto"]);
+            assert!(descriptor_pool.get_message_by_name("A").is_some());
+        }
+    }
+
+    #[test]
+    fn test_registered_file_replaces_embedded_copy() {
+        let old_files = descriptor_sets(&[
+            (
+                "order.proto",
+                "syntax = \"proto3\"; import \"item.proto\"; message Order { Item item = 1; }",
+            ),
+            ("item.proto", "syntax = \"proto3\"; message Item {}"),
+        ]);
+        let new_item = descriptor_set(
+            "item.proto",
+            "syntax = \"proto3\"; message Item { string sku = 1; }",
+        );
+        // Repeated fields concatenate, so this is `order.proto` with the old `item.proto` embedded.
+        let mut order_with_old_item = old_files[0].1.clone();
+        order_with_old_item.extend_from_slice(&old_files[1].1);
+
+        let mut descriptor_pool = DescriptorPool::new();
+        let report = load_descriptors(
+            &mut descriptor_pool,
+            vec![
+                ("order.proto".to_string(), order_with_old_item),
+                ("item.proto".to_string(), new_item),
+            ],
+        );
+
+        assert!(report.is_success(), "{:?}", report);
+        let item = descriptor_pool.get_message_by_name("Item").unwrap();
+        assert!(item.get_field_by_name("sku").is_some());
+    }
+
+    #[test]
+    fn test_conflicting_redefinition_leaves_pool_unchanged() {
+        let mut descriptor_pool = DescriptorPool::new();
+        let item = descriptor_set("item.proto", "syntax = \"proto3\"; message Item {}");
+        load_descriptor(&mut descriptor_pool, "item.proto", &item).unwrap();
+        let loaded_item = loaded_file(&descriptor_pool, "item.proto");
+
+        let changed_item = descriptor_set(
+            "item.proto",
+            "syntax = \"proto3\"; message Item { string sku = 1; }",
+        );
+        let err = load_descriptor(&mut descriptor_pool, "other.proto", &changed_item).unwrap_err();
+        assert!(
+            matches!(&err, LoadFailure::Invalid(reason) if reason.contains("conflicts with the loaded descriptors")),
+            "{}",
+            err
+        );
+
+        // Reloading the loaded copy is not a conflict.
+        load_descriptor(&mut descriptor_pool, "item.proto", &item).unwrap();
+        assert_eq!(loaded_file(&descriptor_pool, "item.proto"), loaded_item);
+        assert_eq!(descriptor_pool.files().count(), 1);
+    }
+
+    #[test]
+    fn test_version_pools_are_cached_until_cleared() {
+        let mut descriptor_map = DescriptorVersions::new();
+        descriptor_map.insert(
+            "item.proto".to_string(),
+            vec![
+                DescriptorEntry::new(
+                    1,
+                    descriptor_set(
+                        "item.proto",
+                        "syntax = \"proto3\"; message Item { string sku = 1; }",
+                    ),
+                ),
+                DescriptorEntry::new(
+                    2,
+                    descriptor_set(
+                        "item.proto",
+                        "syntax = \"proto3\"; message Item { string sku = 1; int32 qty = 2; }",
+                    ),
+                ),
+            ],
+        );
+        let version_pools = VersionPools::default();
+
+        let pool = version_pools
+            .get_or_build(&descriptor_map, "item.proto", 1)
+            .unwrap();
+        let item = pool.descriptor_pool.get_message_by_name("Item").unwrap();
+        assert!(item.get_field_by_name("qty").is_none());
+        assert!(Arc::ptr_eq(
+            &pool,
+            &version_pools
+                .get_or_build(&descriptor_map, "item.proto", 1)
+                .unwrap()
+        ));
+
+        version_pools.clear();
+        assert!(!Arc::ptr_eq(
+            &pool,
+            &version_pools
+                .get_or_build(&descriptor_map, "item.proto", 1)
+                .unwrap()
+        ));
+    }
 }
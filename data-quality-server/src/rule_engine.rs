@@ -0,0 +1,969 @@
+/* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Number, Value as JsonValue};
+use std::cmp::Ordering;
+use std::fmt;
+use tracing::{debug, trace};
+
+use crate::field_path::{FieldMatch, FieldPath, Lookup};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "operator", content = "value", rename_all = "snake_case")]
+pub enum RuleOperator {
+    /// The field equals the value.
+    Eq(JsonValue),
+    /// The field does not equal the value.
+    Ne(JsonValue),
+    Lt(Number),
+    Lte(Number),
+    Gt(Number),
+    Gte(Number),
+    /// The field is a number within the inclusive bounds.
+    Range(Bounds<Number>),
+    /// The field equals one of the values.
+    In(Vec<JsonValue>),
+    /// The field equals none of the values.
+    NotIn(Vec<JsonValue>),
+    /// The field is a string matching the regular expression.
+    Regex(Pattern),
+    /// The field is a string whose length in characters is within the inclusive bounds.
+    Length(Bounds<usize>),
+    Null,
+    NotNull,
+    /// The field is set, to any value including `null`.
+    Present,
+    Absent,
+}
+
+impl RuleOperator {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RuleOperator::Eq(_) => "eq",
+            RuleOperator::Ne(_) => "ne",
+            RuleOperator::Lt(_) => "lt",
+            RuleOperator::Lte(_) => "lte",
+            RuleOperator::Gt(_) => "gt",
+            RuleOperator::Gte(_) => "gte",
+            RuleOperator::Range(_) => "range",
+            RuleOperator::In(_) => "in",
+            RuleOperator::NotIn(_) => "not_in",
+            RuleOperator::Regex(_) => "regex",
+            RuleOperator::Length(_) => "length",
+            RuleOperator::Null => "null",
+            RuleOperator::NotNull => "not_null",
+            RuleOperator::Present => "present",
+            RuleOperator::Absent => "absent",
+        }
+    }
+}
+
+/// Inclusive bounds, checked when the rule is read so a `min` above `max` is rejected up front.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    try_from = "UncheckedBounds<T>",
+    bound(deserialize = "T: Deserialize<'de> + BoundValue")
+)]
+pub struct Bounds<T> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<T>,
+}
+
+#[derive(Deserialize)]
+struct UncheckedBounds<T> {
+    min: Option<T>,
+    max: Option<T>,
+}
+
+/// A value that can bound a `range` or `length` rule.
+pub trait BoundValue: fmt::Display {
+    fn compare(&self, other: &Self) -> Option<Ordering>;
+}
+
+impl BoundValue for Number {
+    fn compare(&self, other: &Self) -> Option<Ordering> {
+        compare_numbers(self, other)
+    }
+}
+
+impl BoundValue for usize {
+    fn compare(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: BoundValue> TryFrom<UncheckedBounds<T>> for Bounds<T> {
+    type Error = String;
+
+    fn try_from(bounds: UncheckedBounds<T>) -> Result<Self, Self::Error> {
+        if let (Some(min), Some(max)) = (&bounds.min, &bounds.max) {
+            if min.compare(max).is_some_and(Ordering::is_gt) {
+                return Err(format!("min {} is greater than max {}", min, max));
+            }
+        }
+        Ok(Bounds {
+            min: bounds.min,
+            max: bounds.max,
+        })
+    }
+}
+
+/// A regular expression, compiled when the rule is read so invalid patterns are rejected
+/// up front.
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern)
+            .map(Pattern)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A check on one field of the validated JSON, e.g.
+/// `{ "field": "status", "operator": "eq", "value": "ACTIVE" }`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldRule {
+    pub field: FieldPath,
+    #[serde(flatten)]
+    pub operator: RuleOperator,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleFailureKind {
+    FieldNotFound,
+    /// The rule does not apply to the type of the field, e.g. `lt` on a string.
+    TypeMismatch,
+    CheckFailed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleResult {
+    #[serde(flatten)]
+    pub rule: FieldRule,
+    pub passed: bool,
+    #[serde(rename = "code", skip_serializing_if = "Option::is_none")]
+    pub failure: Option<RuleFailureKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actual: Option<JsonValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+struct RuleFailure {
+    kind: RuleFailureKind,
+    message: String,
+}
+
+impl RuleFailure {
+    fn check_failed(message: String) -> Self {
+        RuleFailure {
+            kind: RuleFailureKind::CheckFailed,
+            message,
+        }
+    }
+
+    fn type_mismatch(
+        operator: &RuleOperator,
+        field: &str,
+        expected: &str,
+        actual: &JsonValue,
+    ) -> Self {
+        RuleFailure {
+            kind: RuleFailureKind::TypeMismatch,
+            message: format!(
+                "Operator '{}' expects a {} but field '{}' is {}",
+                operator.name(),
+                expected,
+                field,
+                json_type_name(actual)
+            ),
+        }
+    }
+}
+
+/// Evaluates every rule against `json_value` and returns one result per rule, in order.
+pub fn evaluate_rules(json_value: &JsonValue, rules: &[FieldRule]) -> Vec<RuleResult> {
+    trace!("Evaluating {} rule(s).", rules.len());
+
+    rules
+        .iter()
+        .map(|rule| evaluate_rule(json_value, rule))
+        .collect()
+}
+
+// A rule with a wildcard passes when it passes for every element, and reports the elements it
+// failed for in its message.
+fn evaluate_rule(json_value: &JsonValue, rule: &FieldRule) -> RuleResult {
+    let field_matches = rule.field.resolve(json_value);
+    let failures: Vec<RuleFailure> = field_matches
+        .iter()
+        .filter_map(|field_match| {
+            let actual = match field_match.lookup {
+                Lookup::Found(actual) => Some(actual),
+                Lookup::Missing => None,
+                Lookup::NotAnArray(actual) => {
+                    return Some(RuleFailure {
+                        kind: RuleFailureKind::TypeMismatch,
+                        message: format!(
+                            "Field '{}' is {}, expected an array",
+                            field_match.path,
+                            json_type_name(actual)
+                        ),
+                    })
+                }
+            };
+            check_value(&rule.operator, &field_match.path, actual).err()
+        })
+        .collect();
+    debug!(
+        "Rule '{}' on '{}' {}",
+        rule.operator.name(),
+        rule.field,
+        if failures.is_empty() {
+            "passed"
+        } else {
+            "failed"
+        }
+    );
+
+    let actual = match field_matches.as_slice() {
+        [FieldMatch {
+            lookup: Lookup::Found(actual),
+            ..
+        }] if !rule.field.has_wildcard() => Some((*actual).clone()),
+        _ => None,
+    };
+    RuleResult {
+        rule: rule.clone(),
+        passed: failures.is_empty(),
+        failure: failures.first().map(|failure| failure.kind),
+        actual,
+        message: (!failures.is_empty()).then(|| {
+            failures
+                .into_iter()
+                .map(|failure| failure.message)
+                .collect::<Vec<_>>()
+                .join("; ")
+        }),
+    }
+}
+
+fn check_value(
+    operator: &RuleOperator,
+    field: &str,
+    actual: Option<&JsonValue>,
+) -> Result<(), RuleFailure> {
+    let actual = match (operator, actual) {
+        (RuleOperator::Present, Some(_)) | (RuleOperator::Absent, None) => return Ok(()),
+        (RuleOperator::Absent, Some(_)) => {
+            return Err(RuleFailure::check_failed(format!(
+                "Field '{}' is present but must be absent",
+                field
+            )))
+        }
+        (_, Some(actual)) => actual,
+        (_, None) => {
+            return Err(RuleFailure {
+                kind: RuleFailureKind::FieldNotFound,
+                message: format!("Field '{}' not found in the JSON", field),
+            })
+        }
+    };
+
+    match operator {
+        RuleOperator::Eq(expected) if !values_equal(actual, expected) => {
+            Err(RuleFailure::check_failed(format!(
+                "Field '{}' value mismatch: expected {}, found {}",
+                field, expected, actual
+            )))
+        }
+        RuleOperator::Ne(unexpected) if values_equal(actual, unexpected) => Err(
+            RuleFailure::check_failed(format!("Field '{}' must not be {}", field, unexpected)),
+        ),
+        RuleOperator::Lt(bound) => {
+            check_ordering(operator, field, actual, bound, "less than", Ordering::is_lt)
+        }
+        RuleOperator::Lte(bound) => {
+            check_ordering(operator, field, actual, bound, "at most", Ordering::is_le)
+        }
+        RuleOperator::Gt(bound) => check_ordering(
+            operator,
+            field,
+            actual,
+            bound,
+            "greater than",
+            Ordering::is_gt,
+        ),
+        RuleOperator::Gte(bound) => {
+            check_ordering(operator, field, actual, bound, "at least", Ordering::is_ge)
+        }
+        RuleOperator::Range(bounds) => {
+            if let Some(min) = &bounds.min {
+                check_ordering(operator, field, actual, min, "at least", Ordering::is_ge)?;
+            }
+            if let Some(max) = &bounds.max {
+                check_ordering(operator, field, actual, max, "at most", Ordering::is_le)?;
+            }
+            Ok(())
+        }
+        RuleOperator::In(values) if !values.iter().any(|value| values_equal(actual, value)) => {
+            Err(RuleFailure::check_failed(format!(
+                "Field '{}' is {}, which is not one of the allowed values",
+                field, actual
+            )))
+        }
+        RuleOperator::NotIn(values) if values.iter().any(|value| values_equal(actual, value)) => {
+            Err(RuleFailure::check_failed(format!(
+                "Field '{}' is {}, which is one of the rejected values",
+                field, actual
+            )))
+        }
+        RuleOperator::Regex(pattern) => {
+            let Some(text) = actual.as_str() else {
+                return Err(RuleFailure::type_mismatch(
+                    operator, field, "string", actual,
+                ));
+            };
+            if pattern.0.is_match(text) {
+                Ok(())
+            } else {
+                Err(RuleFailure::check_failed(format!(
+                    "Field '{}' does not match /{}/",
+                    field,
+                    pattern.0.as_str()
+                )))
+            }
+        }
+        RuleOperator::Length(bounds) => {
+            let Some(text) = actual.as_str() else {
+                return Err(RuleFailure::type_mismatch(
+                    operator, field, "string", actual,
+                ));
+            };
+            let length = text.chars().count();
+            if bounds.min.is_some_and(|min| length < min)
+                || bounds.max.is_some_and(|max| length > max)
+            {
+                Err(RuleFailure::check_failed(format!(
+                    "Field '{}' has length {}, expected {}",
+                    field,
+                    length,
+                    describe_bounds(bounds)
+                )))
+            } else {
+                Ok(())
+            }
+        }
+        RuleOperator::Null if !actual.is_null() => Err(RuleFailure::check_failed(format!(
+            "Field '{}' is {}, expected null",
+            field, actual
+        ))),
+        RuleOperator::NotNull if actual.is_null() => Err(RuleFailure::check_failed(format!(
+            "Field '{}' is null",
+            field
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn check_ordering(
+    operator: &RuleOperator,
+    field: &str,
+    actual: &JsonValue,
+    bound: &Number,
+    relation: &str,
+    accept: impl Fn(Ordering) -> bool,
+) -> Result<(), RuleFailure> {
+    let Some(number) = as_number(actual) else {
+        return Err(RuleFailure::type_mismatch(
+            operator, field, "number", actual,
+        ));
+    };
+
+    match compare_numbers(&number, bound) {
+        Some(ordering) if accept(ordering) => Ok(()),
+        _ => Err(RuleFailure::check_failed(format!(
+            "Field '{}' is {}, expected {} {}",
+            field, number, relation, bound
+        ))),
+    }
+}
+
+// Numbers are compared by value, so `1` equals `1.0`. Numeric strings are numbers too, whether
+// on one side or both, so a value matches the same operand whether or not it was quoted. Other
+// values are compared as they are.
+fn values_equal(actual: &JsonValue, expected: &JsonValue) -> bool {
+    match (as_number(actual), as_number(expected)) {
+        (Some(actual), Some(expected)) => {
+            compare_numbers(&actual, &expected).is_some_and(Ordering::is_eq)
+        }
+        _ => actual == expected,
+    }
+}
+
+// proto3 JSON quotes 64-bit integers, so numeric strings are read as numbers.
+fn as_number(json_value: &JsonValue) -> Option<Number> {
+    match json_value {
+        JsonValue::Number(number) => Some(number.clone()),
+        JsonValue::String(text) => text.parse::<Number>().ok(),
+        _ => None,
+    }
+}
+
+fn compare_numbers(a: &Number, b: &Number) -> Option<Ordering> {
+    if let (Some(a), Some(b)) = (a.as_i64(), b.as_i64()) {
+        return Some(a.cmp(&b));
+    }
+    if let (Some(a), Some(b)) = (a.as_u64(), b.as_u64()) {
+        return Some(a.cmp(&b));
+    }
+    a.as_f64()?.partial_cmp(&b.as_f64()?)
+}
+
+fn describe_bounds(bounds: &Bounds<usize>) -> String {
+    match (bounds.min, bounds.max) {
+        (Some(min), Some(max)) => format!("between {} and {}", min, max),
+        (Some(min), None) => format!("at least {}", min),
+        (None, Some(max)) => format!("at most {}", max),
+        (None, None) => "any length".to_string(),
+    }
+}
+
+fn json_type_name(json_value: &JsonValue) -> &'static str {
+    match json_value {
+        JsonValue::Null => "null",
+        JsonValue::Bool(_) => "a boolean",
+        JsonValue::Number(_) => "a number",
+        JsonValue::String(_) => "a string",
+        JsonValue::Array(_) => "an array",
+        JsonValue::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rules(rules: JsonValue) -> Vec<FieldRule> {
+        serde_json::from_value(rules).unwrap()
+    }
+
+    fn outcomes(results: &[RuleResult]) -> Vec<(String, bool, Option<RuleFailureKind>)> {
+        results
+            .iter()
+            .map(|result| (result.rule.field.to_string(), result.passed, result.failure))
+            .collect()
+    }
+
+    #[test]
+    fn test_passing_rule() {
+        let results = evaluate_rules(
+            &json!({ "status": "ACTIVE" }),
+            &rules(json!([{ "field": "status", "operator": "eq", "value": "ACTIVE" }])),
+        );
+
+        assert_eq!(results.len(), 1);
+        assert!(results[0].passed);
+        assert_eq!(results[0].failure, None);
+        assert_eq!(results[0].actual, Some(json!("ACTIVE")));
+        assert_eq!(results[0].message, None);
+    }
+
+    #[test]
+    fn test_failing_rule() {
+        let results = evaluate_rules(
+            &json!({ "count": 41 }),
+            &rules(json!([{ "field": "count", "operator": "eq", "value": 42 }])),
+        );
+
+        assert!(!results[0].passed);
+        assert_eq!(results[0].failure, Some(RuleFailureKind::CheckFailed));
+        assert_eq!(results[0].actual, Some(json!(41)));
+        assert_eq!(
+            results[0].message.as_deref(),
+            Some("Field 'count' value mismatch: expected 42, found 41")
+        );
+    }
+
+    #[test]
+    fn test_missing_field() {
+        let results = evaluate_rules(
+            &json!({ "status": "ACTIVE" }),
+            &rules(json!([
+                { "field": "count", "operator": "gt", "value": 0 },
+                { "field": "count", "operator": "absent" },
+                { "field": "count", "operator": "present" },
+            ])),
+        );
+
+        assert_eq!(
+            outcomes(&results),
+            vec![
+                (
+                    "count".to_string(),
+                    false,
+                    Some(RuleFailureKind::FieldNotFound)
+                ),
+                ("count".to_string(), true, None),
+                (
+                    "count".to_string(),
+                    false,
+                    Some(RuleFailureKind::FieldNotFound)
+                ),
+            ]
+        );
+        assert_eq!(results[0].actual, None);
+        assert_eq!(
+            results[0].message.as_deref(),
+            Some("Field 'count' not found in the JSON")
+        );
+    }
+
+    #[test]
+    fn test_results_follow_rule_order() {
+        let results = evaluate_rules(
+            &json!({ "name": "Ada", "age": 36, "email": null }),
+            &rules(json!([
+                { "field": "name", "operator": "length", "value": { "min": 5 } },
+                { "field": "age", "operator": "range", "value": { "min": 18, "max": 99 } },
+                { "field": "email", "operator": "not_null" },
+                { "field": "name", "operator": "regex", "value": "^[A-Z]" },
+                { "field": "phone", "operator": "present" },
+            ])),
+        );
+
+        assert_eq!(
+            outcomes(&results),
+            vec![
+                (
+                    "name".to_string(),
+                    false,
+                    Some(RuleFailureKind::CheckFailed)
+                ),
+                ("age".to_string(), true, None),
+                (
+                    "email".to_string(),
+                    false,
+                    Some(RuleFailureKind::CheckFailed)
+                ),
+                ("name".to_string(), true, None),
+                (
+                    "phone".to_string(),
+                    false,
+                    Some(RuleFailureKind::FieldNotFound)
+                ),
+            ]
+        );
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| result.rule.operator.name())
+                .collect::<Vec<_>>(),
+            vec!["length", "range", "not_null", "regex", "present"]
+        );
+    }
+
+    #[test]
+    fn test_wildcard_rule_reports_every_failing_element() {
+        let results = evaluate_rules(
+            &json!({ "items": [{ "qty": 1 }, { "qty": 0 }, { "qty": -1 }] }),
+            &rules(json!([{ "field": "items[*].qty", "operator": "gt", "value": 0 }])),
+        );
+
+        assert!(!results[0].passed);
+        assert_eq!(results[0].actual, None);
+        assert_eq!(
+            results[0].message.as_deref(),
+            Some("Field 'items[1].qty' is 0, expected greater than 0; Field 'items[2].qty' is -1, expected greater than 0")
+        );
+    }
+
+    fn check(json_value: JsonValue, mut rule: JsonValue) -> (bool, Option<RuleFailureKind>) {
+        rule["field"] = json!("value");
+        let results = evaluate_rules(&json!({ "value": json_value }), &rules(json!([rule])));
+        (results[0].passed, results[0].failure)
+    }
+
+    const PASSED: (bool, Option<RuleFailureKind>) = (true, None);
+    const CHECK_FAILED: (bool, Option<RuleFailureKind>) =
+        (false, Some(RuleFailureKind::CheckFailed));
+    const TYPE_MISMATCH: (bool, Option<RuleFailureKind>) =
+        (false, Some(RuleFailureKind::TypeMismatch));
+
+    #[test]
+    fn test_equality_operators_compare_numbers_by_value() {
+        let cases = [
+            (json!(1), json!({ "operator": "eq", "value": 1.0 }), PASSED),
+            (json!(1.0), json!({ "operator": "eq", "value": 1 }), PASSED),
+            (
+                json!("42"),
+                json!({ "operator": "eq", "value": 42 }),
+                PASSED,
+            ),
+            (
+                json!(42),
+                json!({ "operator": "eq", "value": "42" }),
+                PASSED,
+            ),
+            (
+                json!(u64::MAX),
+                json!({ "operator": "eq", "value": u64::MAX }),
+                PASSED,
+            ),
+            (
+                json!(2),
+                json!({ "operator": "eq", "value": 1 }),
+                CHECK_FAILED,
+            ),
+            (
+                json!("abc"),
+                json!({ "operator": "eq", "value": 1 }),
+                CHECK_FAILED,
+            ),
+            (
+                json!(true),
+                json!({ "operator": "eq", "value": 1 }),
+                CHECK_FAILED,
+            ),
+            (
+                json!(42),
+                json!({ "operator": "eq", "value": "42.0" }),
+                PASSED,
+            ),
+            (
+                json!("42"),
+                json!({ "operator": "eq", "value": "42.0" }),
+                PASSED,
+            ),
+            (
+                json!("abc"),
+                json!({ "operator": "eq", "value": "abc" }),
+                PASSED,
+            ),
+            (
+                json!("42"),
+                json!({ "operator": "ne", "value": "42.0" }),
+                CHECK_FAILED,
+            ),
+            (
+                json!(42),
+                json!({ "operator": "ne", "value": "42.0" }),
+                CHECK_FAILED,
+            ),
+            (
+                json!("42.0"),
+                json!({ "operator": "in", "value": ["41", "42"] }),
+                PASSED,
+            ),
+            (
+                json!(42.0),
+                json!({ "operator": "in", "value": ["41", "42"] }),
+                PASSED,
+            ),
+            (
+                json!("42"),
+                json!({ "operator": "not_in", "value": ["42.0"] }),
+                CHECK_FAILED,
+            ),
+            (
+                json!(42),
+                json!({ "operator": "not_in", "value": ["42.0"] }),
+                CHECK_FAILED,
+            ),
+            (
+                json!({ "a": [1] }),
+                json!({ "operator": "eq", "value": { "a": [1] } }),
+                PASSED,
+            ),
+            (
+                json!(1),
+                json!({ "operator": "ne", "value": 1.0 }),
+                CHECK_FAILED,
+            ),
+            (
+                json!("7"),
+                json!({ "operator": "ne", "value": 7 }),
+                CHECK_FAILED,
+            ),
+            (json!(8), json!({ "operator": "ne", "value": 7 }), PASSED),
+            (
+                json!("ACTIVE"),
+                json!({ "operator": "ne", "value": "CLOSED" }),
+                PASSED,
+            ),
+            (
+                json!(2.0),
+                json!({ "operator": "in", "value": [1, 2, 3] }),
+                PASSED,
+            ),
+            (
+                json!("3"),
+                json!({ "operator": "in", "value": [1, 2, 3] }),
+                PASSED,
+            ),
+            (
+                json!(4),
+                json!({ "operator": "in", "value": [1, 2, 3] }),
+                CHECK_FAILED,
+            ),
+            (
+                json!("b"),
+                json!({ "operator": "in", "value": ["a", "b"] }),
+                PASSED,
+            ),
+            (
+                json!(2.0),
+                json!({ "operator": "not_in", "value": [1, 2] }),
+                CHECK_FAILED,
+            ),
+            (
+                json!("1"),
+                json!({ "operator": "not_in", "value": [1, 2] }),
+                CHECK_FAILED,
+            ),
+            (
+                json!(3),
+                json!({ "operator": "not_in", "value": [1, 2] }),
+                PASSED,
+            ),
+        ];
+
+        for (json_value, rule, expected) in cases {
+            assert_eq!(
+                check(json_value.clone(), rule.clone()),
+                expected,
+                "{} {}",
+                json_value,
+                rule
+            );
+        }
+    }
+
+    #[test]
+    fn test_ordering_operators() {
+        let cases = [
+            (json!(1), json!({ "operator": "lt", "value": 2 }), PASSED),
+            (
+                json!(2),
+                json!({ "operator": "lt", "value": 2 }),
+                CHECK_FAILED,
+            ),
+            (json!(2), json!({ "operator": "lte", "value": 2.0 }), PASSED),
+            (
+                json!(2.5),
+                json!({ "operator": "lte", "value": 2 }),
+                CHECK_FAILED,
+            ),
+            (json!("3"), json!({ "operator": "gt", "value": 2 }), PASSED),
+            (
+                json!(-1),
+                json!({ "operator": "gt", "value": 0 }),
+                CHECK_FAILED,
+            ),
+            (json!(0), json!({ "operator": "gte", "value": 0 }), PASSED),
+            (
+                json!("-1"),
+                json!({ "operator": "gte", "value": 0 }),
+                CHECK_FAILED,
+            ),
+            (
+                json!(5),
+                json!({ "operator": "range", "value": { "min": 1, "max": 10 } }),
+                PASSED,
+            ),
+            (
+                json!(10),
+                json!({ "operator": "range", "value": { "max": 10 } }),
+                PASSED,
+            ),
+            (
+                json!(0),
+                json!({ "operator": "range", "value": { "min": 1 } }),
+                CHECK_FAILED,
+            ),
+            (
+                json!(11),
+                json!({ "operator": "range", "value": { "min": 1, "max": 10 } }),
+                CHECK_FAILED,
+            ),
+            (
+                json!("abc"),
+                json!({ "operator": "lt", "value": 2 }),
+                TYPE_MISMATCH,
+            ),
+            (
+                json!(true),
+                json!({ "operator": "lte", "value": 2 }),
+                TYPE_MISMATCH,
+            ),
+            (
+                json!(null),
+                json!({ "operator": "gt", "value": 2 }),
+                TYPE_MISMATCH,
+            ),
+            (
+                json!([1]),
+                json!({ "operator": "gte", "value": 2 }),
+                TYPE_MISMATCH,
+            ),
+            (
+                json!({}),
+                json!({ "operator": "range", "value": { "min": 1 } }),
+                TYPE_MISMATCH,
+            ),
+        ];
+
+        for (json_value, rule, expected) in cases {
+            assert_eq!(
+                check(json_value.clone(), rule.clone()),
+                expected,
+                "{} {}",
+                json_value,
+                rule
+            );
+        }
+    }
+
+    #[test]
+    fn test_string_operators() {
+        let cases = [
+            (
+                json!("ABC-1"),
+                json!({ "operator": "regex", "value": "^[A-Z]+-[0-9]$" }),
+                PASSED,
+            ),
+            (
+                json!("abc-1"),
+                json!({ "operator": "regex", "value": "^[A-Z]+-[0-9]$" }),
+                CHECK_FAILED,
+            ),
+            (
+                json!(1),
+                json!({ "operator": "regex", "value": "^1$" }),
+                TYPE_MISMATCH,
+            ),
+            (
+                json!("héllo"),
+                json!({ "operator": "length", "value": { "min": 5, "max": 5 } }),
+                PASSED,
+            ),
+            (
+                json!("hi"),
+                json!({ "operator": "length", "value": { "min": 3 } }),
+                CHECK_FAILED,
+            ),
+            (
+                json!("hello"),
+                json!({ "operator": "length", "value": { "max": 4 } }),
+                CHECK_FAILED,
+            ),
+            (
+                json!(["a"]),
+                json!({ "operator": "length", "value": { "max": 4 } }),
+                TYPE_MISMATCH,
+            ),
+        ];
+
+        for (json_value, rule, expected) in cases {
+            assert_eq!(
+                check(json_value.clone(), rule.clone()),
+                expected,
+                "{} {}",
+                json_value,
+                rule
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_rules_are_rejected() {
+        for value in [
+            json!({ "operator": "range", "value": { "min": 10, "max": 1 } }),
+            json!({ "operator": "range", "value": { "min": 1.5, "max": 1 } }),
+            json!({ "operator": "length", "value": { "min": 5, "max": 4 } }),
+            json!({ "operator": "regex", "value": "[" }),
+            json!({ "operator": "lt", "value": "1" }),
+        ] {
+            let mut rule = value.clone();
+            rule["field"] = json!("price");
+            assert!(
+                serde_json::from_value::<FieldRule>(rule).is_err(),
+                "{}",
+                value
+            );
+        }
+
+        // Equal bounds are fine.
+        assert_eq!(
+            check(
+                json!(1),
+                json!({ "operator": "range", "value": { "min": 1, "max": 1.0 } })
+            ),
+            PASSED
+        );
+    }
+
+    #[test]
+    fn test_presence_operators() {
+        let cases = [
+            (json!(null), json!({ "operator": "null" }), PASSED),
+            (json!(0), json!({ "operator": "null" }), CHECK_FAILED),
+            (json!(""), json!({ "operator": "not_null" }), PASSED),
+            (json!(null), json!({ "operator": "not_null" }), CHECK_FAILED),
+            (json!(null), json!({ "operator": "present" }), PASSED),
+            (json!(null), json!({ "operator": "absent" }), CHECK_FAILED),
+        ];
+
+        for (json_value, rule, expected) in cases {
+            assert_eq!(
+                check(json_value.clone(), rule.clone()),
+                expected,
+                "{} {}",
+                json_value,
+                rule
+            );
+        }
+    }
+
+    #[test]
+    fn test_type_mismatch_message() {
+        let results = evaluate_rules(
+            &json!({ "name": "Ada", "tags": "none" }),
+            &rules(json!([
+                { "field": "name", "operator": "gt", "value": 1 },
+                { "field": "tags[*]", "operator": "eq", "value": "x" },
+            ])),
+        );
+
+        assert_eq!(results[0].failure, Some(RuleFailureKind::TypeMismatch));
+        assert_eq!(
+            results[0].message.as_deref(),
+            Some("Operator 'gt' expects a number but field 'name' is a string")
+        );
+        assert_eq!(results[1].failure, Some(RuleFailureKind::TypeMismatch));
+        assert_eq!(
+            results[1].message.as_deref(),
+            Some("Field 'tags' is a string, expected an array")
+        );
+    }
+}
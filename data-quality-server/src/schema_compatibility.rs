@@ -0,0 +1,569 @@
+/* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
+
+use clap::ValueEnum;
+use dynamic_message::constraints::is_required;
+use prost_reflect::{
+    Cardinality, DescriptorPool, EnumDescriptor, FieldDescriptor, Kind, MessageDescriptor,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use tracing::debug;
+
+// Compatibility is judged from the point of view of JSON validation: BACKWARD means documents
+// valid under the previous version are still valid under the new one, FORWARD means documents
+// valid under the new version are still valid under the previous one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CompatibilityMode {
+    #[default]
+    Backward,
+    Forward,
+    Full,
+    None,
+}
+
+impl fmt::Display for CompatibilityMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompatibilityMode::Backward => write!(f, "BACKWARD"),
+            CompatibilityMode::Forward => write!(f, "FORWARD"),
+            CompatibilityMode::Full => write!(f, "FULL"),
+            CompatibilityMode::None => write!(f, "NONE"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    MessageAdded,
+    MessageRemoved,
+    FieldAdded,
+    RequiredFieldAdded,
+    FieldRemoved,
+    FieldTypeChanged,
+    FieldNumberChanged,
+    FieldCardinalityChanged,
+    FieldOneofChanged,
+    EnumAdded,
+    EnumRemoved,
+    EnumValueAdded,
+    EnumValueRemoved,
+}
+
+impl ChangeKind {
+    fn breaks_backward(self) -> bool {
+        !matches!(
+            self,
+            ChangeKind::MessageAdded
+                | ChangeKind::FieldAdded
+                | ChangeKind::EnumAdded
+                | ChangeKind::EnumValueAdded
+        )
+    }
+
+    fn breaks_forward(self) -> bool {
+        !matches!(
+            self,
+            ChangeKind::MessageAdded
+                | ChangeKind::MessageRemoved
+                | ChangeKind::FieldRemoved
+                | ChangeKind::EnumAdded
+                | ChangeKind::EnumRemoved
+                | ChangeKind::EnumValueRemoved
+        )
+    }
+
+    pub fn is_breaking(self, mode: CompatibilityMode) -> bool {
+        match mode {
+            CompatibilityMode::Backward => self.breaks_backward(),
+            CompatibilityMode::Forward => self.breaks_forward(),
+            CompatibilityMode::Full => self.breaks_backward() || self.breaks_forward(),
+            CompatibilityMode::None => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SchemaChange {
+    pub change: ChangeKind,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<String>,
+}
+
+impl SchemaChange {
+    fn new(change: ChangeKind, path: impl Into<String>) -> Self {
+        SchemaChange {
+            change,
+            path: path.into(),
+            old: None,
+            new: None,
+        }
+    }
+
+    fn values(mut self, old: impl Into<String>, new: impl Into<String>) -> Self {
+        self.old = Some(old.into());
+        self.new = Some(new.into());
+        self
+    }
+}
+
+/// Lists the changes between the types defined in `old_files` of `old_pool` and the types
+/// defined in `new_files` of `new_pool`. Types are matched by full name and fields by name.
+pub fn diff_schemas(
+    old_pool: &DescriptorPool,
+    old_files: &[String],
+    new_pool: &DescriptorPool,
+    new_files: &[String],
+) -> Vec<SchemaChange> {
+    let (old_messages, old_enums) = collect_types(old_pool, old_files);
+    let (new_messages, new_enums) = collect_types(new_pool, new_files);
+    let mut changes = Vec::new();
+
+    for (name, old_message) in &old_messages {
+        match new_messages.get(name) {
+            Some(new_message) => diff_messages(old_message, new_message, &mut changes),
+            None => changes.push(SchemaChange::new(ChangeKind::MessageRemoved, name)),
+        }
+    }
+    for name in new_messages.keys() {
+        if !old_messages.contains_key(name) {
+            changes.push(SchemaChange::new(ChangeKind::MessageAdded, name));
+        }
+    }
+
+    for (name, old_enum) in &old_enums {
+        match new_enums.get(name) {
+            Some(new_enum) => diff_enums(old_enum, new_enum, &mut changes),
+            None => changes.push(SchemaChange::new(ChangeKind::EnumRemoved, name)),
+        }
+    }
+    for name in new_enums.keys() {
+        if !old_enums.contains_key(name) {
+            changes.push(SchemaChange::new(ChangeKind::EnumAdded, name));
+        }
+    }
+
+    debug!("Found {} schema changes", changes.len());
+    changes
+}
+
+pub fn breaking_changes(changes: Vec<SchemaChange>, mode: CompatibilityMode) -> Vec<SchemaChange> {
+    changes
+        .into_iter()
+        .filter(|change| change.change.is_breaking(mode))
+        .collect()
+}
+
+fn diff_messages(
+    old_message: &MessageDescriptor,
+    new_message: &MessageDescriptor,
+    changes: &mut Vec<SchemaChange>,
+) {
+    for old_field in old_message.fields() {
+        let path = old_field.full_name().to_string();
+        let Some(new_field) = new_message.get_field_by_name(old_field.name()) else {
+            changes.push(SchemaChange::new(ChangeKind::FieldRemoved, path));
+            continue;
+        };
+
+        if old_field.number() != new_field.number() {
+            changes.push(
+                SchemaChange::new(ChangeKind::FieldNumberChanged, path.clone()).values(
+                    old_field.number().to_string(),
+                    new_field.number().to_string(),
+                ),
+            );
+        }
+        let (old_type, new_type) = (field_type(&old_field), field_type(&new_field));
+        if old_type != new_type {
+            changes.push(
+                SchemaChange::new(ChangeKind::FieldTypeChanged, path.clone())
+                    .values(old_type, new_type),
+            );
+        } else if old_field.cardinality() != new_field.cardinality() {
+            changes.push(
+                SchemaChange::new(ChangeKind::FieldCardinalityChanged, path.clone()).values(
+                    cardinality_name(old_field.cardinality()),
+                    cardinality_name(new_field.cardinality()),
+                ),
+            );
+        }
+        let (old_oneof, new_oneof) = (oneof_name(&old_field), oneof_name(&new_field));
+        if old_oneof != new_oneof {
+            changes.push(
+                SchemaChange::new(ChangeKind::FieldOneofChanged, path)
+                    .values(old_oneof.unwrap_or_default(), new_oneof.unwrap_or_default()),
+            );
+        }
+    }
+
+    for new_field in new_message.fields() {
+        if old_message.get_field_by_name(new_field.name()).is_none() {
+            let change = if is_required(&new_field) {
+                ChangeKind::RequiredFieldAdded
+            } else {
+                ChangeKind::FieldAdded
+            };
+            changes.push(SchemaChange::new(change, new_field.full_name()));
+        }
+    }
+}
+
+fn diff_enums(
+    old_enum: &EnumDescriptor,
+    new_enum: &EnumDescriptor,
+    changes: &mut Vec<SchemaChange>,
+) {
+    for old_value in old_enum.values() {
+        if new_enum.get_value_by_name(old_value.name()).is_none() {
+            changes.push(SchemaChange::new(
+                ChangeKind::EnumValueRemoved,
+                format!("{}.{}", old_enum.full_name(), old_value.name()),
+            ));
+        }
+    }
+    for new_value in new_enum.values() {
+        if old_enum.get_value_by_name(new_value.name()).is_none() {
+            changes.push(SchemaChange::new(
+                ChangeKind::EnumValueAdded,
+                format!("{}.{}", new_enum.full_name(), new_value.name()),
+            ));
+        }
+    }
+}
+
+fn collect_types(
+    pool: &DescriptorPool,
+    files: &[String],
+) -> (
+    BTreeMap<String, MessageDescriptor>,
+    BTreeMap<String, EnumDescriptor>,
+) {
+    let mut messages = BTreeMap::new();
+    let mut enums = BTreeMap::new();
+
+    for file in files.iter().filter_map(|file| pool.get_file_by_name(file)) {
+        let mut pending: Vec<MessageDescriptor> = file.messages().collect();
+        enums.extend(
+            file.enums()
+                .map(|enum_descriptor| (enum_descriptor.full_name().to_string(), enum_descriptor)),
+        );
+        while let Some(message) = pending.pop() {
+            if message.is_map_entry() {
+                continue;
+            }
+            enums.extend(
+                message.child_enums().map(|enum_descriptor| {
+                    (enum_descriptor.full_name().to_string(), enum_descriptor)
+                }),
+            );
+            pending.extend(message.child_messages());
+            messages.insert(message.full_name().to_string(), message);
+        }
+    }
+
+    (messages, enums)
+}
+
+fn field_type(field: &FieldDescriptor) -> String {
+    match field.kind() {
+        Kind::Message(entry) if field.is_map() => format!(
+            "map<{}, {}>",
+            kind_type(&entry.map_entry_key_field().kind()),
+            kind_type(&entry.map_entry_value_field().kind())
+        ),
+        kind => kind_type(&kind),
+    }
+}
+
+fn kind_type(kind: &Kind) -> String {
+    match kind {
+        Kind::Message(message_descriptor) => message_descriptor.full_name().to_string(),
+        Kind::Enum(enum_descriptor) => enum_descriptor.full_name().to_string(),
+        kind => format!("{:?}", kind).to_lowercase(),
+    }
+}
+
+fn cardinality_name(cardinality: Cardinality) -> &'static str {
+    match cardinality {
+        Cardinality::Optional => "optional",
+        Cardinality::Required => "required",
+        Cardinality::Repeated => "repeated",
+    }
+}
+
+fn oneof_name(field: &FieldDescriptor) -> Option<String> {
+    field
+        .containing_oneof()
+        .filter(|oneof| {
+            !oneof
+                .fields()
+                .all(|field| field.field_descriptor_proto().proto3_optional())
+        })
+        .map(|oneof| oneof.name().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto_compiler::compile_proto_sources;
+
+    const RULES_PROTO: &str = include_str!("../../data/input/proto/dq/rules.proto");
+
+    const SHOP_PROTO: &str = r#"
+        syntax = "proto3";
+        package shop;
+        import "dq/rules.proto";
+
+        message Item {
+            string name = 1;
+            double price = 2;
+            int32 quantity = 3;
+        }
+
+        enum Status {
+            STATUS_UNKNOWN = 0;
+            STATUS_OPEN = 1;
+            STATUS_CLOSED = 2;
+        }
+    "#;
+
+    fn compile(shop_proto: &str) -> DescriptorPool {
+        let sources = vec![
+            ("dq/rules.proto".to_string(), RULES_PROTO.to_string()),
+            ("shop.proto".to_string(), shop_proto.to_string()),
+        ];
+        let mut pool = DescriptorPool::global();
+        for (_, file_descriptor_set) in compile_proto_sources(sources, &pool).unwrap() {
+            pool.decode_file_descriptor_set(file_descriptor_set.as_slice())
+                .unwrap();
+        }
+        pool
+    }
+
+    fn diff(old_proto: &str, new_proto: &str) -> Vec<SchemaChange> {
+        let files = vec!["shop.proto".to_string()];
+        diff_schemas(&compile(old_proto), &files, &compile(new_proto), &files)
+    }
+
+    fn change_kinds(changes: &[SchemaChange]) -> Vec<(ChangeKind, &str)> {
+        changes
+            .iter()
+            .map(|change| (change.change, change.path.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_unchanged_schema_has_no_changes() {
+        assert!(diff(SHOP_PROTO, SHOP_PROTO).is_empty());
+    }
+
+    #[test]
+    fn test_removed_field() {
+        let changes = diff(SHOP_PROTO, &SHOP_PROTO.replace("int32 quantity = 3;", ""));
+        assert_eq!(
+            change_kinds(&changes),
+            vec![(ChangeKind::FieldRemoved, "shop.Item.quantity")]
+        );
+    }
+
+    #[test]
+    fn test_changed_field_type() {
+        let changes = diff(
+            SHOP_PROTO,
+            &SHOP_PROTO.replace("double price = 2;", "string price = 2;"),
+        );
+        assert_eq!(
+            changes,
+            vec![
+                SchemaChange::new(ChangeKind::FieldTypeChanged, "shop.Item.price")
+                    .values("double", "string")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_renumbered_field() {
+        let changes = diff(
+            SHOP_PROTO,
+            &SHOP_PROTO.replace("int32 quantity = 3;", "int32 quantity = 4;"),
+        );
+        assert_eq!(
+            changes,
+            vec![
+                SchemaChange::new(ChangeKind::FieldNumberChanged, "shop.Item.quantity")
+                    .values("3", "4")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_added_field() {
+        let changes = diff(
+            SHOP_PROTO,
+            &SHOP_PROTO.replace(
+                "int32 quantity = 3;",
+                "int32 quantity = 3; string note = 4;",
+            ),
+        );
+        assert_eq!(
+            change_kinds(&changes),
+            vec![(ChangeKind::FieldAdded, "shop.Item.note")]
+        );
+    }
+
+    #[test]
+    fn test_added_required_field() {
+        let changes = diff(
+            SHOP_PROTO,
+            &SHOP_PROTO.replace(
+                "int32 quantity = 3;",
+                "int32 quantity = 3; string sku = 4 [(dq.rules).required = true];",
+            ),
+        );
+        assert_eq!(
+            change_kinds(&changes),
+            vec![(ChangeKind::RequiredFieldAdded, "shop.Item.sku")]
+        );
+
+        let proto2 = r#"
+            syntax = "proto2";
+            package shop;
+            message Item { optional string name = 1; }
+        "#;
+        let changes = diff(
+            proto2,
+            &proto2.replace(
+                "optional string name = 1;",
+                "optional string name = 1; required string sku = 2;",
+            ),
+        );
+        assert_eq!(
+            change_kinds(&changes),
+            vec![(ChangeKind::RequiredFieldAdded, "shop.Item.sku")]
+        );
+    }
+
+    #[test]
+    fn test_removed_enum_value() {
+        let changes = diff(SHOP_PROTO, &SHOP_PROTO.replace("STATUS_CLOSED = 2;", ""));
+        assert_eq!(
+            change_kinds(&changes),
+            vec![(ChangeKind::EnumValueRemoved, "shop.Status.STATUS_CLOSED")]
+        );
+    }
+
+    #[test]
+    fn test_removed_message_and_enum() {
+        let new_proto = r#"
+            syntax = "proto3";
+            package shop;
+            message Order { string id = 1; }
+        "#;
+        let changes = diff(SHOP_PROTO, new_proto);
+        assert_eq!(
+            change_kinds(&changes),
+            vec![
+                (ChangeKind::MessageRemoved, "shop.Item"),
+                (ChangeKind::MessageAdded, "shop.Order"),
+                (ChangeKind::EnumRemoved, "shop.Status"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_only_listed_files_are_compared() {
+        let pool = compile(SHOP_PROTO);
+        let changes = diff_schemas(
+            &pool,
+            &["dq/rules.proto".to_string()],
+            &compile(&SHOP_PROTO.replace("int32 quantity = 3;", "")),
+            &["dq/rules.proto".to_string()],
+        );
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn test_is_breaking() {
+        use ChangeKind::*;
+        // (change, breaks BACKWARD, breaks FORWARD)
+        let expected = [
+            (MessageAdded, false, false),
+            (MessageRemoved, true, false),
+            (FieldAdded, false, true),
+            (RequiredFieldAdded, true, true),
+            (FieldRemoved, true, false),
+            (FieldTypeChanged, true, true),
+            (FieldNumberChanged, true, true),
+            (FieldCardinalityChanged, true, true),
+            (FieldOneofChanged, true, true),
+            (EnumAdded, false, false),
+            (EnumRemoved, true, false),
+            (EnumValueAdded, false, true),
+            (EnumValueRemoved, true, false),
+        ];
+
+        for (change, backward, forward) in expected {
+            assert_eq!(
+                change.is_breaking(CompatibilityMode::Backward),
+                backward,
+                "{:?} BACKWARD",
+                change
+            );
+            assert_eq!(
+                change.is_breaking(CompatibilityMode::Forward),
+                forward,
+                "{:?} FORWARD",
+                change
+            );
+            assert_eq!(
+                change.is_breaking(CompatibilityMode::Full),
+                backward || forward,
+                "{:?} FULL",
+                change
+            );
+            assert!(
+                !change.is_breaking(CompatibilityMode::None),
+                "{:?} NONE",
+                change
+            );
+        }
+    }
+
+    #[test]
+    fn test_breaking_changes_per_mode() {
+        let new_proto = SHOP_PROTO
+            .replace("int32 quantity = 3;", "string note = 4;")
+            .replace("STATUS_CLOSED = 2;", "");
+        let changes = diff(SHOP_PROTO, &new_proto);
+        let breaking = |mode| {
+            change_kinds(&breaking_changes(changes.clone(), mode))
+                .into_iter()
+                .map(|(change, _)| change)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            breaking(CompatibilityMode::Backward),
+            vec![ChangeKind::FieldRemoved, ChangeKind::EnumValueRemoved]
+        );
+        assert_eq!(
+            breaking(CompatibilityMode::Forward),
+            vec![ChangeKind::FieldAdded]
+        );
+        assert_eq!(
+            breaking(CompatibilityMode::Full),
+            vec![
+                ChangeKind::FieldRemoved,
+                ChangeKind::FieldAdded,
+                ChangeKind::EnumValueRemoved
+            ]
+        );
+        assert!(breaking(CompatibilityMode::None).is_empty());
+    }
+}
@@ -0,0 +1,52 @@
+/* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
+
+//! Helpers shared by the unit tests of several modules.
+
+use arc_swap::ArcSwap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{RwLock, Semaphore};
+
+use crate::descriptor_store::{DescriptorStore, MemoryStore};
+use crate::protobuf_descriptors::{DescriptorVersions, ValidationPool, VersionPools};
+use crate::schema_compatibility::CompatibilityMode;
+use crate::validation_profiles::ValidationProfiles;
+use crate::AppState;
+
+/// A directory under the system temp directory that is removed again when dropped.
+pub struct TestDirectory(pub PathBuf);
+
+impl TestDirectory {
+    pub fn new() -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "data-quality-server-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        TestDirectory(path)
+    }
+}
+
+impl Drop for TestDirectory {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// An `AppState` without descriptors or profiles that persists descriptors in `descriptor_store`.
+pub fn test_state(descriptor_store: Arc<dyn DescriptorStore>) -> AppState {
+    AppState {
+        descriptor_map: Arc::new(RwLock::new(DescriptorVersions::new())),
+        descriptor_pool: Arc::new(ArcSwap::from_pointee(ValidationPool::default())),
+        version_pools: Arc::new(VersionPools::default()),
+        enable_metrics: false,
+        semaphore: Arc::new(Semaphore::new(1)),
+        default_compatibility: CompatibilityMode::Backward,
+        descriptor_store,
+        profile_store: Arc::new(MemoryStore),
+        profiles: Arc::new(RwLock::new(ValidationProfiles::new())),
+    }
+}
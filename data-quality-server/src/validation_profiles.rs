@@ -0,0 +1,208 @@
+/* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
+
+use dynamic_message::{BytesEncoding, FieldNaming, UnknownEnumPolicy};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::{error, info};
+
+use crate::app_error::AppError;
+use crate::rule_engine::FieldRule;
+use crate::AppState;
+
+pub type ValidationProfiles = HashMap<String, ValidationProfile>;
+
+/// A named set of `/validate` settings stored on the server, so callers only send the profile
+/// name and the JSON. Fields of a validation request override the profile; its rules are
+/// evaluated after the profile's rules.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidationProfile {
+    #[serde(default)]
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protobuf: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub descriptor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+    #[serde(default)]
+    pub rules: Vec<FieldRule>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes_encoding: Option<BytesEncoding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unknown_enum_policy: Option<UnknownEnumPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field_naming: Option<FieldNaming>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collect_errors: Option<bool>,
+}
+
+pub enum SaveOutcome {
+    Created,
+    Replaced,
+}
+
+/// Stores `profile`, replacing a profile of the same name unless `create_only` is set. The
+/// profile is persisted before it becomes visible to `/validate`.
+pub async fn save_profile(
+    state: &AppState,
+    profile: ValidationProfile,
+    create_only: bool,
+) -> Result<SaveOutcome, AppError> {
+    if !is_valid_profile_name(&profile.name) {
+        return Err(AppError::InvalidRequest(format!(
+            "Invalid profile name '{}': use letters, digits, '-', '_' and '.'",
+            profile.name
+        )));
+    }
+    if profile.version.is_some() && profile.descriptor.is_none() {
+        return Err(AppError::InvalidRequest(
+            "`version` requires `descriptor` to be set".to_string(),
+        ));
+    }
+
+    let mut profiles = state.profiles.write().await;
+    let exists = profiles.contains_key(&profile.name);
+    if exists && create_only {
+        return Err(AppError::ProfileExists(profile.name));
+    }
+
+    if let Err(err) = state.profile_store.save_profile(&profile) {
+        error!(
+            "Failed to save profile {} to the store: {}",
+            profile.name, err
+        );
+        return Err(AppError::StorageError(err.to_string()));
+    }
+
+    info!("Validation profile {} saved.", profile.name);
+    profiles.insert(profile.name.clone(), profile);
+    Ok(if exists {
+        SaveOutcome::Replaced
+    } else {
+        SaveOutcome::Created
+    })
+}
+
+pub async fn delete_profile(state: &AppState, name: &str) -> Result<(), AppError> {
+    let mut profiles = state.profiles.write().await;
+    if !profiles.contains_key(name) {
+        return Err(AppError::ProfileNotFound(name.to_string()));
+    }
+
+    if let Err(err) = state.profile_store.delete_profile(name) {
+        error!("Failed to delete profile {} from the store: {}", name, err);
+        return Err(AppError::StorageError(err.to_string()));
+    }
+
+    profiles.remove(name);
+    info!("Validation profile {} deleted.", name);
+    Ok(())
+}
+
+fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || "-_.".contains(character))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor_store::{DirectoryStore, MemoryStore, ProfileStore};
+    use crate::test_support::{test_state, TestDirectory};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn profile(profile: serde_json::Value) -> ValidationProfile {
+        serde_json::from_value(profile).unwrap()
+    }
+
+    fn directory_state(directory: &TestDirectory) -> (AppState, Arc<DirectoryStore>) {
+        let store = Arc::new(DirectoryStore::new(&directory.0).unwrap());
+        let state = AppState {
+            profile_store: store.clone(),
+            ..test_state(Arc::new(MemoryStore))
+        };
+        (state, store)
+    }
+
+    #[tokio::test]
+    async fn test_save_profile_creates_and_replaces() {
+        let directory = TestDirectory::new();
+        let (state, store) = directory_state(&directory);
+        let orders = profile(json!({ "name": "orders", "protobuf": "Order" }));
+        let orders_v2 = profile(json!({ "name": "orders", "protobuf": "OrderV2" }));
+
+        let outcome = save_profile(&state, orders.clone(), true).await.unwrap();
+        assert!(matches!(outcome, SaveOutcome::Created));
+
+        let err = save_profile(&state, orders_v2.clone(), true)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, AppError::ProfileExists(ref name) if name == "orders"));
+        assert_eq!(state.profiles.read().await["orders"], orders);
+
+        let outcome = save_profile(&state, orders_v2.clone(), false)
+            .await
+            .unwrap();
+        assert!(matches!(outcome, SaveOutcome::Replaced));
+        assert_eq!(state.profiles.read().await["orders"], orders_v2);
+        assert_eq!(store.load_profiles().unwrap()["orders"], orders_v2);
+
+        let outcome = save_profile(
+            &state,
+            profile(json!({ "name": "items", "protobuf": "Item" })),
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(outcome, SaveOutcome::Created));
+        assert_eq!(store.load_profiles().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_save_profile_rejects_invalid_profiles() {
+        let state = test_state(Arc::new(MemoryStore));
+        let invalid_profiles = [
+            json!({ "name": "", "protobuf": "Order" }),
+            json!({ "name": "../orders", "protobuf": "Order" }),
+            json!({ "name": "orders", "version": 2 }),
+        ];
+
+        for invalid_profile in invalid_profiles {
+            let err = save_profile(&state, profile(invalid_profile.clone()), false)
+                .await
+                .err()
+                .unwrap();
+            assert!(
+                matches!(err, AppError::InvalidRequest(_)),
+                "{}: {}",
+                invalid_profile,
+                err
+            );
+        }
+        assert!(state.profiles.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delete_profile() {
+        let directory = TestDirectory::new();
+        let (state, store) = directory_state(&directory);
+        save_profile(
+            &state,
+            profile(json!({ "name": "orders", "protobuf": "Order" })),
+            true,
+        )
+        .await
+        .unwrap();
+
+        delete_profile(&state, "orders").await.unwrap();
+        assert!(state.profiles.read().await.is_empty());
+        assert!(store.load_profiles().unwrap().is_empty());
+
+        let err = delete_profile(&state, "orders").await.err().unwrap();
+        assert!(matches!(err, AppError::ProfileNotFound(ref name) if name == "orders"));
+    }
+}
@@ -8,10 +8,7 @@ use std::fs;
 use std::path::Path;
 use tracing::{debug, error, info, trace, warn, Level};
 use tracing_subscriber::FmtSubscriber;
-use tracing_subscriber::{
-    fmt::{self, format::FmtSpan},
-    EnvFilter,
-};
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
 
 #[tracing::instrument]
 fn is_docker() -> bool {
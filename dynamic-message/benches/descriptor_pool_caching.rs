@@ -0,0 +1,88 @@
+/* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use dynamic_message::populate_dynamic_message;
+use prost_reflect::{DescriptorPool, DynamicMessage};
+use prost_types::FileDescriptorSet;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// Mirrors the server's `rebuild_descriptor_pool`, which used to run on every `/validate` request.
+fn rebuild_descriptor_pool(descriptor_map: &HashMap<String, Vec<u8>>) -> DescriptorPool {
+    let mut descriptor_pool = DescriptorPool::global();
+    for file_content in descriptor_map.values() {
+        let file_descriptor_set: FileDescriptorSet =
+            prost::Message::decode(file_content.as_slice()).expect("Failed to decode .pb file");
+        descriptor_pool
+            .add_file_descriptor_set(file_descriptor_set)
+            .expect("Failed to add descriptor");
+    }
+    descriptor_pool
+}
+
+fn descriptor_map(stored_descriptors: usize) -> HashMap<String, Vec<u8>> {
+    let example = std::fs::read("tests/example.pb").expect("Failed to read example.pb");
+    let test_types = std::fs::read("tests/test_types.pb").expect("Failed to read test_types.pb");
+
+    (0..stored_descriptors)
+        .map(|index| {
+            let file_content = if index % 2 == 0 {
+                &example
+            } else {
+                &test_types
+            };
+            (format!("descriptor_{}.pb", index), file_content.clone())
+        })
+        .collect()
+}
+
+fn validate(descriptor_pool: &DescriptorPool, json_value: &serde_json::Value) {
+    let message_descriptor = descriptor_pool
+        .get_message_by_name("MyMessage")
+        .expect("Message not found");
+    let mut dynamic_message = DynamicMessage::new(message_descriptor.clone());
+    populate_dynamic_message(&mut dynamic_message, &message_descriptor, json_value)
+        .expect("Failed to populate dynamic message");
+}
+
+fn benchmark_descriptor_pool_per_request(c: &mut Criterion) {
+    let json_value = json!({
+        "key1": "test_value",
+        "key2": 42,
+        "key3": true
+    });
+
+    let mut group = c.benchmark_group("validate_request");
+    for stored_descriptors in [1, 10, 50] {
+        let descriptor_map = descriptor_map(stored_descriptors);
+        let cached_pool = Arc::new(rebuild_descriptor_pool(&descriptor_map));
+
+        group.bench_with_input(
+            BenchmarkId::new("rebuild_pool", stored_descriptors),
+            &descriptor_map,
+            |b, descriptor_map| {
+                b.iter(|| {
+                    let descriptor_pool = rebuild_descriptor_pool(black_box(descriptor_map));
+                    validate(&descriptor_pool, black_box(&json_value));
+                });
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("cached_pool", stored_descriptors),
+            &cached_pool,
+            |b, cached_pool| {
+                b.iter(|| {
+                    let descriptor_pool = Arc::clone(black_box(cached_pool));
+                    validate(&descriptor_pool, black_box(&json_value));
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_descriptor_pool_per_request);
+
+criterion_main!(benches);
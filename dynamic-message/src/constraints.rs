@@ -0,0 +1,631 @@
+/* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
+
+//! Field constraints declared in the schema with the `(dq.rules)` field option, see
+//! `data/input/proto/dq/rules.proto`.
+
+use prost_reflect::{
+    Cardinality, DescriptorPool, DynamicMessage, ExtensionDescriptor, FieldDescriptor,
+    FileDescriptor, Kind, MessageDescriptor, Value as ProstReflectValue,
+};
+use regex::Regex;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use tracing::warn;
+
+use crate::{kind_name, FieldLocation, PopulateError, PopulateErrorKind};
+
+const RULES_EXTENSION: &str = "dq.rules";
+
+/// The bound rules of numbers, with how the value must compare to the bound.
+const BOUNDS: [(&str, &str, &[Ordering]); 4] = [
+    ("lt", "less than", &[Ordering::Less]),
+    ("lte", "at most", &[Ordering::Less, Ordering::Equal]),
+    ("gt", "greater than", &[Ordering::Greater]),
+    ("gte", "at least", &[Ordering::Greater, Ordering::Equal]),
+];
+
+/// The `(dq.rules)` extension, if any file in `pool` defines it.
+pub(crate) fn rules_extension(pool: &DescriptorPool) -> Option<ExtensionDescriptor> {
+    pool.get_extension_by_name(RULES_EXTENSION)
+}
+
+/// Whether every document must set `field_descriptor`: a proto2 `required` field or one with
+/// `(dq.rules).required = true`.
+pub fn is_required(field_descriptor: &FieldDescriptor) -> bool {
+    if field_descriptor.cardinality() == Cardinality::Required {
+        return true;
+    }
+    let Some(rules_extension) = rules_extension(field_descriptor.parent_pool()) else {
+        return false;
+    };
+    let options = field_descriptor.options();
+    options.has_extension(&rules_extension)
+        && options
+            .get_extension(&rules_extension)
+            .as_message()
+            .is_some_and(|rules| rule_bool(rules, "required"))
+}
+
+/// The `(dq.rules)` constraints of the fields of a set of messages, parsed once so validations do
+/// not have to decode field options.
+#[derive(Debug, Clone, Default)]
+pub struct Constraints {
+    fields: HashMap<String, FieldRules>,
+}
+
+impl Constraints {
+    /// Parses the constraints of the fields of every message defined in `files`. Fails on rules
+    /// that cannot be used, such as a `string.pattern` that is not a valid regular expression or
+    /// rules for another type than the field's, listing every such field.
+    pub fn from_files(files: impl IntoIterator<Item = FileDescriptor>) -> Result<Self, String> {
+        let mut fields = HashMap::new();
+        let mut errors = Vec::new();
+
+        for file in files {
+            let Some(rules_extension) = rules_extension(file.parent_pool()) else {
+                continue;
+            };
+            let mut messages: Vec<MessageDescriptor> = file.messages().collect();
+            while let Some(message) = messages.pop() {
+                messages.extend(message.child_messages());
+                for field_descriptor in message.fields() {
+                    match FieldRules::parse(&rules_extension, &field_descriptor) {
+                        Ok(Some(field_rules)) => {
+                            fields.insert(field_descriptor.full_name().to_string(), field_rules);
+                        }
+                        Ok(None) => {}
+                        Err(err) => errors.push(format!(
+                            "Invalid (dq.rules) on field '{}': {}",
+                            field_descriptor.full_name(),
+                            err
+                        )),
+                    }
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors.join("; "));
+        }
+        Ok(Constraints { fields })
+    }
+
+    /// Parses the constraints of every field in `pool`. Fields whose rules cannot be used are
+    /// left out with a warning; use `from_files` to reject them instead.
+    pub fn from_pool(pool: &DescriptorPool) -> Self {
+        let mut fields = HashMap::new();
+        let Some(rules_extension) = rules_extension(pool) else {
+            return Constraints { fields };
+        };
+
+        for field_descriptor in pool
+            .all_messages()
+            .flat_map(|message| message.fields().collect::<Vec<_>>())
+        {
+            match FieldRules::parse(&rules_extension, &field_descriptor) {
+                Ok(Some(field_rules)) => {
+                    fields.insert(field_descriptor.full_name().to_string(), field_rules);
+                }
+                Ok(None) => {}
+                Err(err) => warn!(
+                    "Ignoring invalid (dq.rules) on field '{}': {}",
+                    field_descriptor.full_name(),
+                    err
+                ),
+            }
+        }
+
+        Constraints { fields }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub(crate) fn get(&self, field_descriptor: &FieldDescriptor) -> Option<&FieldRules> {
+        self.fields.get(field_descriptor.full_name())
+    }
+}
+
+/// The `(dq.rules)` of one field.
+#[derive(Debug, Clone)]
+pub(crate) struct FieldRules {
+    required: bool,
+    type_rules: Option<TypeRules>,
+}
+
+/// The set member of the `type` oneof of a `FieldRules`, e.g. the `StringRules` of `string`.
+#[derive(Debug, Clone)]
+struct TypeRules {
+    rule_type: String,
+    rules: DynamicMessage,
+    /// `string.pattern`, compiled.
+    pattern: Option<Regex>,
+    /// `repeated.items`.
+    items: Option<Box<TypeRules>>,
+}
+
+impl FieldRules {
+    /// Reads the `(dq.rules)` option of `field_descriptor`, if it has one.
+    pub(crate) fn parse(
+        rules_extension: &ExtensionDescriptor,
+        field_descriptor: &FieldDescriptor,
+    ) -> Result<Option<Self>, String> {
+        let options = field_descriptor.options();
+        if !options.has_extension(rules_extension) {
+            return Ok(None);
+        }
+        let rules = options.get_extension(rules_extension);
+        let Some(rules) = rules.as_message() else {
+            return Ok(None);
+        };
+
+        let type_rules = TypeRules::parse(rules)?;
+        if let Some(type_rules) = &type_rules {
+            type_rules.check_applies(field_descriptor)?;
+        }
+        Ok(Some(FieldRules {
+            required: rule_bool(rules, "required"),
+            type_rules,
+        }))
+    }
+}
+
+impl TypeRules {
+    // Reads the type rules of `field_rules`, a `FieldRules` message.
+    fn parse(field_rules: &DynamicMessage) -> Result<Option<Self>, String> {
+        let Some((rule_type, rules)) = type_rules(field_rules) else {
+            return Ok(None);
+        };
+
+        let pattern = match rule_value(&rules, "pattern").filter(|_| rule_type == "string") {
+            Some(pattern) => {
+                let pattern = pattern.as_str().unwrap_or_default();
+                let regex = Regex::new(pattern).map_err(|err| {
+                    format!(
+                        "string.pattern {:?} is not a valid regular expression: {}",
+                        pattern, err
+                    )
+                })?;
+                Some(regex)
+            }
+            None => None,
+        };
+        let items = match rules
+            .get_field_by_name("items")
+            .filter(|_| rule_type == "repeated")
+        {
+            Some(items) => items
+                .as_message()
+                .map(TypeRules::parse)
+                .transpose()
+                .map_err(|err| format!("repeated.items.{}", err))?
+                .flatten()
+                .map(Box::new),
+            None => None,
+        };
+
+        Ok(Some(TypeRules {
+            rule_type,
+            rules,
+            pattern,
+            items,
+        }))
+    }
+
+    // Rules for another type than the field's are rejected when they are read, so checking a
+    // value never has to skip them.
+    fn check_applies(&self, field_descriptor: &FieldDescriptor) -> Result<(), String> {
+        let kind = field_descriptor.kind();
+        if field_descriptor.is_map() {
+            return Err(format!(
+                "{} rules are not supported on map fields",
+                self.rule_type
+            ));
+        }
+        if self.rule_type != "repeated" {
+            if field_descriptor.is_list() {
+                return Err(format!(
+                    "{} rules do not apply to a repeated field, use repeated.items",
+                    self.rule_type
+                ));
+            }
+            if !applies_to(&self.rule_type, &kind) {
+                return Err(format!(
+                    "{} rules do not apply to a field of type {}",
+                    self.rule_type,
+                    kind_name(&kind)
+                ));
+            }
+            return Ok(());
+        }
+
+        if !field_descriptor.is_list() {
+            return Err("repeated rules only apply to repeated fields".to_string());
+        }
+        match &self.items {
+            Some(items) if !applies_to(&items.rule_type, &kind) => Err(format!(
+                "repeated.items.{} rules do not apply to elements of type {}",
+                items.rule_type,
+                kind_name(&kind)
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Whether `rule_type` rules apply to a single value of `kind`.
+fn applies_to(rule_type: &str, kind: &Kind) -> bool {
+    matches!(
+        (rule_type, kind),
+        ("string", Kind::String)
+            | ("bytes", Kind::Bytes)
+            | ("int32", Kind::Int32 | Kind::Sint32 | Kind::Sfixed32)
+            | ("int64", Kind::Int64 | Kind::Sint64 | Kind::Sfixed64)
+            | ("uint32", Kind::Uint32 | Kind::Fixed32)
+            | ("uint64", Kind::Uint64 | Kind::Fixed64)
+            | ("float", Kind::Float)
+            | ("double", Kind::Double)
+    )
+}
+
+/// Checks the constraints of `field_descriptor` against its value in `dynamic_message`. Fields
+/// with presence are only checked when set; proto3 fields without presence are checked with
+/// their default value when left out.
+pub(crate) fn check_field(
+    field_rules: &FieldRules,
+    dynamic_message: &DynamicMessage,
+    field_descriptor: &FieldDescriptor,
+    location: &FieldLocation,
+) -> Vec<PopulateError> {
+    let mut violations = Vec::new();
+    let is_set = dynamic_message.has_field(field_descriptor);
+    if !is_set && field_rules.required {
+        violations.push(violation(
+            location,
+            "required = true",
+            format!("Field '{}' is required", location.name),
+        ));
+    }
+    if !is_set && field_descriptor.supports_presence() {
+        return violations;
+    }
+
+    let Some(type_rules) = &field_rules.type_rules else {
+        return violations;
+    };
+    let value = dynamic_message.get_field(field_descriptor);
+
+    match (type_rules.rule_type.as_str(), value.as_ref()) {
+        ("repeated", ProstReflectValue::List(elements)) => {
+            check_repeated(type_rules, elements, location, &mut violations);
+        }
+        (_, value) => check_value(type_rules, value, location, &mut violations),
+    }
+    violations
+}
+
+fn check_repeated(
+    repeated_rules: &TypeRules,
+    elements: &[ProstReflectValue],
+    location: &FieldLocation,
+    violations: &mut Vec<PopulateError>,
+) {
+    let count = elements.len() as u64;
+    if let Some(min_items) = rule_u64(&repeated_rules.rules, "min_items").filter(|min| count < *min)
+    {
+        violations.push(violation(
+            location,
+            format!("repeated.min_items = {}", min_items),
+            format!(
+                "Field '{}' must have at least {} item(s), found {}",
+                location.name, min_items, count
+            ),
+        ));
+    }
+    if let Some(max_items) = rule_u64(&repeated_rules.rules, "max_items").filter(|max| count > *max)
+    {
+        violations.push(violation(
+            location,
+            format!("repeated.max_items = {}", max_items),
+            format!(
+                "Field '{}' must have at most {} item(s), found {}",
+                location.name, max_items, count
+            ),
+        ));
+    }
+
+    let Some(item_rules) = &repeated_rules.items else {
+        return;
+    };
+    for (index, element) in elements.iter().enumerate() {
+        check_value(item_rules, element, &location.index(index), violations);
+    }
+}
+
+fn check_value(
+    type_rules: &TypeRules,
+    value: &ProstReflectValue,
+    location: &FieldLocation,
+    violations: &mut Vec<PopulateError>,
+) {
+    let rule_type = type_rules.rule_type.as_str();
+    match (rule_type, value) {
+        ("string", ProstReflectValue::String(text)) => {
+            check_string(type_rules, text, location, violations)
+        }
+        ("bytes", ProstReflectValue::Bytes(bytes)) => check_length(
+            rule_type,
+            &type_rules.rules,
+            bytes.len(),
+            "byte(s)",
+            location,
+            violations,
+        ),
+        ("int32", ProstReflectValue::I32(_))
+        | ("int64", ProstReflectValue::I64(_))
+        | ("uint32", ProstReflectValue::U32(_))
+        | ("uint64", ProstReflectValue::U64(_))
+        | ("float", ProstReflectValue::F32(_))
+        | ("double", ProstReflectValue::F64(_)) => {
+            check_number(rule_type, &type_rules.rules, value, location, violations)
+        }
+        // Unreachable: `TypeRules::check_applies` rejects rules for other types.
+        _ => {}
+    }
+}
+
+fn check_string(
+    string_rules: &TypeRules,
+    text: &str,
+    location: &FieldLocation,
+    violations: &mut Vec<PopulateError>,
+) {
+    check_length(
+        "string",
+        &string_rules.rules,
+        text.chars().count(),
+        "character(s)",
+        location,
+        violations,
+    );
+
+    if let Some(pattern) = string_rules
+        .pattern
+        .as_ref()
+        .filter(|pattern| !pattern.is_match(text))
+    {
+        violations.push(violation(
+            location,
+            format!("string.pattern = {:?}", pattern.as_str()),
+            format!(
+                "Field '{}' does not match /{}/",
+                location.name,
+                pattern.as_str()
+            ),
+        ));
+    }
+
+    check_membership(
+        "string",
+        &string_rules.rules,
+        &ProstReflectValue::String(text.to_string()),
+        location,
+        violations,
+    );
+}
+
+fn check_length(
+    rule_type: &str,
+    type_rules: &DynamicMessage,
+    length: usize,
+    unit: &str,
+    location: &FieldLocation,
+    violations: &mut Vec<PopulateError>,
+) {
+    let length = length as u64;
+    if let Some(min_len) = rule_u64(type_rules, "min_len").filter(|min| length < *min) {
+        violations.push(violation(
+            location,
+            format!("{}.min_len = {}", rule_type, min_len),
+            format!(
+                "Field '{}' must be at least {} {} long, found {}",
+                location.name, min_len, unit, length
+            ),
+        ));
+    }
+    if let Some(max_len) = rule_u64(type_rules, "max_len").filter(|max| length > *max) {
+        violations.push(violation(
+            location,
+            format!("{}.max_len = {}", rule_type, max_len),
+            format!(
+                "Field '{}' must be at most {} {} long, found {}",
+                location.name, max_len, unit, length
+            ),
+        ));
+    }
+}
+
+fn check_number(
+    rule_type: &str,
+    number_rules: &DynamicMessage,
+    value: &ProstReflectValue,
+    location: &FieldLocation,
+    violations: &mut Vec<PopulateError>,
+) {
+    for (rule_name, relation, accepted) in BOUNDS {
+        let Some(bound) = rule_value(number_rules, rule_name) else {
+            continue;
+        };
+        if !compare_numbers(value, &bound).is_some_and(|ordering| accepted.contains(&ordering)) {
+            violations.push(violation(
+                location,
+                format!("{}.{} = {}", rule_type, rule_name, describe_value(&bound)),
+                format!(
+                    "Field '{}' is {}, expected {} {}",
+                    location.name,
+                    describe_value(value),
+                    relation,
+                    describe_value(&bound)
+                ),
+            ));
+        }
+    }
+
+    check_membership(rule_type, number_rules, value, location, violations);
+}
+
+/// Checks the `const`, `in` and `not_in` rules shared by strings and numbers.
+fn check_membership(
+    rule_type: &str,
+    type_rules: &DynamicMessage,
+    value: &ProstReflectValue,
+    location: &FieldLocation,
+    violations: &mut Vec<PopulateError>,
+) {
+    if let Some(expected) = rule_value(type_rules, "const") {
+        if !values_equal(value, &expected) {
+            violations.push(violation(
+                location,
+                format!("{}.const = {}", rule_type, describe_value(&expected)),
+                format!(
+                    "Field '{}' is {}, expected {}",
+                    location.name,
+                    describe_value(value),
+                    describe_value(&expected)
+                ),
+            ));
+        }
+    }
+
+    let allowed = rule_list(type_rules, "in");
+    if !allowed.is_empty() && !allowed.iter().any(|allowed| values_equal(value, allowed)) {
+        violations.push(violation(
+            location,
+            format!("{}.in = {}", rule_type, describe_list(&allowed)),
+            format!(
+                "Field '{}' is {}, expected one of {}",
+                location.name,
+                describe_value(value),
+                describe_list(&allowed)
+            ),
+        ));
+    }
+
+    let rejected = rule_list(type_rules, "not_in");
+    if rejected
+        .iter()
+        .any(|rejected| values_equal(value, rejected))
+    {
+        violations.push(violation(
+            location,
+            format!("{}.not_in = {}", rule_type, describe_list(&rejected)),
+            format!(
+                "Field '{}' is {}, which is not allowed",
+                location.name,
+                describe_value(value)
+            ),
+        ));
+    }
+}
+
+// Returns the name of the set member of the `type` oneof and its rules.
+fn type_rules(rules: &DynamicMessage) -> Option<(String, DynamicMessage)> {
+    rules.fields().find_map(|(field_descriptor, value)| {
+        field_descriptor.containing_oneof()?;
+        Some((
+            field_descriptor.name().to_string(),
+            value.as_message()?.clone(),
+        ))
+    })
+}
+
+// Returns the value of an `optional` rule, or `None` when the rule is not set.
+fn rule_value<'a>(rules: &'a DynamicMessage, name: &str) -> Option<Cow<'a, ProstReflectValue>> {
+    if !rules.has_field_by_name(name) {
+        return None;
+    }
+    rules.get_field_by_name(name)
+}
+
+fn rule_bool(rules: &DynamicMessage, name: &str) -> bool {
+    rules
+        .get_field_by_name(name)
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false)
+}
+
+fn rule_u64(rules: &DynamicMessage, name: &str) -> Option<u64> {
+    rule_value(rules, name)?.as_u64()
+}
+
+fn rule_list(rules: &DynamicMessage, name: &str) -> Vec<ProstReflectValue> {
+    rules
+        .get_field_by_name(name)
+        .and_then(|value| value.as_list().map(<[_]>::to_vec))
+        .unwrap_or_default()
+}
+
+enum Number {
+    Integer(i128),
+    Float(f64),
+}
+
+fn as_number(value: &ProstReflectValue) -> Option<Number> {
+    match value {
+        ProstReflectValue::I32(number) => Some(Number::Integer((*number).into())),
+        ProstReflectValue::I64(number) => Some(Number::Integer((*number).into())),
+        ProstReflectValue::U32(number) => Some(Number::Integer((*number).into())),
+        ProstReflectValue::U64(number) => Some(Number::Integer((*number).into())),
+        ProstReflectValue::F32(number) => Some(Number::Float((*number).into())),
+        ProstReflectValue::F64(number) => Some(Number::Float(*number)),
+        _ => None,
+    }
+}
+
+fn compare_numbers(a: &ProstReflectValue, b: &ProstReflectValue) -> Option<Ordering> {
+    match (as_number(a)?, as_number(b)?) {
+        (Number::Integer(a), Number::Integer(b)) => Some(a.cmp(&b)),
+        (Number::Integer(a), Number::Float(b)) => (a as f64).partial_cmp(&b),
+        (Number::Float(a), Number::Integer(b)) => a.partial_cmp(&(b as f64)),
+        (Number::Float(a), Number::Float(b)) => a.partial_cmp(&b),
+    }
+}
+
+fn values_equal(a: &ProstReflectValue, b: &ProstReflectValue) -> bool {
+    match compare_numbers(a, b) {
+        Some(ordering) => ordering.is_eq(),
+        None => a == b,
+    }
+}
+
+fn describe_value(value: &ProstReflectValue) -> String {
+    match value {
+        ProstReflectValue::String(text) => format!("{:?}", text),
+        ProstReflectValue::Bool(flag) => flag.to_string(),
+        ProstReflectValue::I32(number) => number.to_string(),
+        ProstReflectValue::I64(number) => number.to_string(),
+        ProstReflectValue::U32(number) => number.to_string(),
+        ProstReflectValue::U64(number) => number.to_string(),
+        ProstReflectValue::F32(number) => number.to_string(),
+        ProstReflectValue::F64(number) => number.to_string(),
+        ProstReflectValue::List(values) => describe_list(values),
+        value => format!("{:?}", value),
+    }
+}
+
+fn describe_list(values: &[ProstReflectValue]) -> String {
+    let values: Vec<String> = values.iter().map(describe_value).collect();
+    format!("[{}]", values.join(", "))
+}
+
+fn violation(
+    location: &FieldLocation,
+    constraint: impl Into<String>,
+    message: String,
+) -> PopulateError {
+    PopulateError::new(PopulateErrorKind::ConstraintViolation, message)
+        .at(location)
+        .expected(constraint)
+}
@@ -1,260 +1,1149 @@
 /* Licensed under the AGPL-3.0 License: https://www.gnu.org/licenses/agpl-3.0.html */
 
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
 use prost_reflect::{
-    DynamicMessage, Kind, MessageDescriptor, SerializeOptions, Value as ProstReflectValue,
+    DynamicMessage, EnumDescriptor, FieldDescriptor, Kind, MapKey, MessageDescriptor,
+    SerializeOptions, Value as ProstReflectValue,
 };
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use tracing::{debug, error, info};
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+use tracing::{debug, error, info, warn};
+
+pub mod constraints;
+
+pub use constraints::Constraints;
+
+const WELL_KNOWN_TYPES: &[&str] = &[
+    "google.protobuf.Any",
+    "google.protobuf.BoolValue",
+    "google.protobuf.BytesValue",
+    "google.protobuf.DoubleValue",
+    "google.protobuf.Duration",
+    "google.protobuf.FieldMask",
+    "google.protobuf.FloatValue",
+    "google.protobuf.Int32Value",
+    "google.protobuf.Int64Value",
+    "google.protobuf.ListValue",
+    "google.protobuf.StringValue",
+    "google.protobuf.Struct",
+    "google.protobuf.Timestamp",
+    "google.protobuf.UInt32Value",
+    "google.protobuf.UInt64Value",
+    "google.protobuf.Value",
+];
+
+const BASE64_STANDARD_INDIFFERENT: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+const BASE64_URL_SAFE_INDIFFERENT: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// How JSON strings for `bytes` fields are turned into bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BytesEncoding {
+    /// Canonical proto3 JSON: standard or URL-safe base64, with or without padding.
+    #[default]
+    Base64,
+    /// The UTF-8 bytes of the string itself, for legacy producers that do not encode bytes.
+    RawString,
+}
+
+/// What to do with enum numbers that are not defined in the enum. Names are always checked,
+/// but proto3 enums are open, so producers upgraded before the schema may send new numbers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownEnumPolicy {
+    #[default]
+    Reject,
+    Accept,
+    /// Accept the number but log a warning.
+    Warn,
+}
+
+/// Which field names a JSON document may use. The proto3 JSON mapping accepts both the original
+/// proto name (`user_id`) and the `json_name` (`userId`); the strict modes enforce one of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldNaming {
+    #[default]
+    Any,
+    ProtoName,
+    JsonName,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PopulateOptions {
+    pub bytes_encoding: BytesEncoding,
+    pub unknown_enum_policy: UnknownEnumPolicy,
+    pub field_naming: FieldNaming,
+}
+
+impl PopulateOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bytes_encoding(mut self, bytes_encoding: BytesEncoding) -> Self {
+        self.bytes_encoding = bytes_encoding;
+        self
+    }
+
+    pub fn unknown_enum_policy(mut self, unknown_enum_policy: UnknownEnumPolicy) -> Self {
+        self.unknown_enum_policy = unknown_enum_policy;
+        self
+    }
+
+    pub fn field_naming(mut self, field_naming: FieldNaming) -> Self {
+        self.field_naming = field_naming;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PopulateErrorKind {
+    NotAnObject,
+    UnknownField,
+    FieldNaming,
+    DuplicateField,
+    TypeMismatch,
+    Overflow,
+    InvalidEnumValue,
+    InvalidMapKey,
+    InvalidBase64,
+    OneofConflict,
+    InvalidWellKnownType,
+    /// A value broke a `(dq.rules)` constraint declared on its field.
+    ConstraintViolation,
+}
+
+/// A single violation found while populating a message. `path` is a JSON Pointer (RFC 6901)
+/// into the validated document, `expected` the protobuf type, or the broken constraint, and `actual`
+/// the JSON type found.
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[error("{message}")]
+pub struct PopulateError {
+    #[serde(rename = "code")]
+    pub kind: PopulateErrorKind,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actual: Option<String>,
+    pub message: String,
+}
+
+impl PopulateError {
+    pub fn new(kind: PopulateErrorKind, message: impl Into<String>) -> Self {
+        PopulateError {
+            kind,
+            path: String::new(),
+            expected: None,
+            actual: None,
+            message: message.into(),
+        }
+    }
+
+    fn at(mut self, location: &FieldLocation) -> Self {
+        self.path = location.pointer.clone();
+        self
+    }
+
+    fn expected(mut self, expected: impl Into<String>) -> Self {
+        self.expected = Some(expected.into());
+        self
+    }
+
+    fn actual(mut self, actual: &JsonValue) -> Self {
+        self.actual = Some(json_type_name(actual).to_string());
+        self
+    }
+}
+
+/// Where a value sits in the document: a readable name for messages (`items[1].price`) and a
+/// JSON Pointer for machine consumption (`/items/1/price`).
+#[derive(Debug, Clone, Default)]
+struct FieldLocation {
+    name: String,
+    pointer: String,
+}
+
+impl FieldLocation {
+    fn field(&self, field_name: &str) -> Self {
+        FieldLocation {
+            name: if self.name.is_empty() {
+                field_name.to_string()
+            } else {
+                format!("{}.{}", self.name, field_name)
+            },
+            pointer: format!("{}/{}", self.pointer, escape_json_pointer(field_name)),
+        }
+    }
+
+    fn index(&self, index: usize) -> Self {
+        FieldLocation {
+            name: format!("{}[{}]", self.name, index),
+            pointer: format!("{}/{}", self.pointer, index),
+        }
+    }
+
+    fn key(&self, key: &str) -> Self {
+        FieldLocation {
+            name: format!("{}[\"{}\"]", self.name, key),
+            pointer: format!("{}/{}", self.pointer, escape_json_pointer(key)),
+        }
+    }
+}
+
+struct PopulateContext<'a> {
+    options: &'a PopulateOptions,
+    collect_all_errors: bool,
+    errors: Vec<PopulateError>,
+    constraints: &'a Constraints,
+}
+
+impl<'a> PopulateContext<'a> {
+    fn new(
+        options: &'a PopulateOptions,
+        collect_all_errors: bool,
+        constraints: &'a Constraints,
+    ) -> Self {
+        PopulateContext {
+            options,
+            collect_all_errors,
+            errors: Vec::new(),
+            constraints,
+        }
+    }
+
+    /// Records a violation. When failing fast the error is handed back to be propagated with `?`,
+    /// otherwise it is stored and population continues with the next value.
+    fn report(&mut self, error: PopulateError) -> Result<(), PopulateError> {
+        if self.collect_all_errors {
+            self.errors.push(error);
+            Ok(())
+        } else {
+            Err(error)
+        }
+    }
+}
 
 #[tracing::instrument]
 pub fn populate_dynamic_message(
     dynamic_message: &mut DynamicMessage,
     message_descriptor: &MessageDescriptor,
     json_value: &JsonValue,
-) -> Result<(), String> {
+) -> Result<(), PopulateError> {
+    populate_dynamic_message_with_options(
+        dynamic_message,
+        message_descriptor,
+        json_value,
+        &PopulateOptions::default(),
+    )
+}
+
+/// Parses the `(dq.rules)` constraints of the pool of `message_descriptor` on every call. Callers
+/// that validate many documents should parse them once with `Constraints::from_pool` and use
+/// `populate_dynamic_message_with_constraints`.
+#[tracing::instrument]
+pub fn populate_dynamic_message_with_options(
+    dynamic_message: &mut DynamicMessage,
+    message_descriptor: &MessageDescriptor,
+    json_value: &JsonValue,
+    options: &PopulateOptions,
+) -> Result<(), PopulateError> {
+    populate_fail_fast(
+        dynamic_message,
+        message_descriptor,
+        json_value,
+        options,
+        &Constraints::from_pool(message_descriptor.parent_pool()),
+    )
+}
+
+/// Like `populate_dynamic_message_with_options`, with the `(dq.rules)` constraints taken from
+/// `constraints` instead of being parsed from the pool of `message_descriptor` on every call.
+#[tracing::instrument(skip(constraints))]
+pub fn populate_dynamic_message_with_constraints(
+    dynamic_message: &mut DynamicMessage,
+    message_descriptor: &MessageDescriptor,
+    json_value: &JsonValue,
+    options: &PopulateOptions,
+    constraints: &Constraints,
+) -> Result<(), PopulateError> {
+    populate_fail_fast(
+        dynamic_message,
+        message_descriptor,
+        json_value,
+        options,
+        constraints,
+    )
+}
+
+fn populate_fail_fast(
+    dynamic_message: &mut DynamicMessage,
+    message_descriptor: &MessageDescriptor,
+    json_value: &JsonValue,
+    options: &PopulateOptions,
+    constraints: &Constraints,
+) -> Result<(), PopulateError> {
     info!("populate_dynamic_message");
 
-    if let JsonValue::Object(map) = json_value {
-        for (field_name, field_value) in map {
-            if let Some(field_descriptor) = message_descriptor.get_field_by_name(field_name) {
-                match field_descriptor.kind() {
-                    Kind::Double | Kind::Float => {
-                        if let Some(float_value) = field_value.as_f64() {
-                            let value = ProstReflectValue::F64(float_value);
-                            if value.is_valid_for_field(&field_descriptor) {
-                                dynamic_message.set_field_by_name(field_name, value);
-                                debug!(
-                                    "Field '{}' set to F64 with value {}",
-                                    field_name, float_value
-                                );
-                            } else {
-                                return Err(format!(
-                                    "Field '{}' expects a float or double value",
-                                    field_name
-                                ));
-                            }
-                        } else {
-                            return Err(format!(
-                                "Field '{}' expects a float or double value",
-                                field_name
-                            ));
-                        }
-                    }
-                    Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => {
-                        if let Some(int_value) = field_value.as_i64() {
-                            let value = ProstReflectValue::I32(int_value as i32);
-                            if value.is_valid_for_field(&field_descriptor) {
-                                dynamic_message.set_field_by_name(field_name, value);
-                                debug!(
-                                    "Field '{}' set to I32 with value {}",
-                                    field_name, int_value
-                                );
-                            } else {
-                                return Err(format!(
-                                    "Field '{}' expects an integer value",
-                                    field_name
-                                ));
-                            }
-                        } else {
-                            return Err(format!("Field '{}' expects an integer value", field_name));
-                        }
-                    }
-                    Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => {
-                        if let Some(int_value) = field_value.as_i64() {
-                            let value = ProstReflectValue::I64(int_value);
-                            if value.is_valid_for_field(&field_descriptor) {
-                                dynamic_message.set_field_by_name(field_name, value);
-                                debug!(
-                                    "Field '{}' set to I64 with value {}",
-                                    field_name, int_value
-                                );
-                            } else {
-                                return Err(format!(
-                                    "Field '{}' expects a 64-bit integer value",
-                                    field_name
-                                ));
-                            }
-                        } else {
-                            return Err(format!(
-                                "Field '{}' expects a 64-bit integer value",
-                                field_name
-                            ));
-                        }
-                    }
-                    Kind::Uint32 | Kind::Fixed32 => {
-                        if let Some(int_value) = field_value.as_u64() {
-                            let value = ProstReflectValue::U32(int_value as u32);
-                            if value.is_valid_for_field(&field_descriptor) {
-                                dynamic_message.set_field_by_name(field_name, value);
-                                debug!(
-                                    "Field '{}' set to U32 with value {}",
-                                    field_name, int_value
-                                );
-                            } else {
-                                return Err(format!(
-                                    "Field '{}' expects an unsigned integer value",
-                                    field_name
-                                ));
-                            }
-                        } else {
-                            return Err(format!(
-                                "Field '{}' expects an unsigned integer value",
-                                field_name
-                            ));
-                        }
-                    }
-                    Kind::Uint64 | Kind::Fixed64 => {
-                        if let Some(int_value) = field_value.as_u64() {
-                            let value = ProstReflectValue::U64(int_value);
-                            if value.is_valid_for_field(&field_descriptor) {
-                                dynamic_message.set_field_by_name(field_name, value);
-                                debug!(
-                                    "Field '{}' set to U64 with value {}",
-                                    field_name, int_value
-                                );
-                            } else {
-                                return Err(format!(
-                                    "Field '{}' expects an unsigned 64-bit integer value",
-                                    field_name
-                                ));
-                            }
-                        } else {
-                            return Err(format!(
-                                "Field '{}' expects an unsigned 64-bit integer value",
-                                field_name
-                            ));
-                        }
-                    }
-                    Kind::Bool => {
-                        if let Some(bool_value) = field_value.as_bool() {
-                            let value = ProstReflectValue::Bool(bool_value);
-                            if value.is_valid_for_field(&field_descriptor) {
-                                dynamic_message.set_field_by_name(field_name, value);
-                                debug!(
-                                    "Field '{}' set to Bool with value {}",
-                                    field_name, bool_value
-                                );
-                            } else {
-                                return Err(format!(
-                                    "Field '{}' expects a boolean value",
-                                    field_name
-                                ));
-                            }
-                        } else {
-                            return Err(format!("Field '{}' expects a boolean value", field_name));
-                        }
-                    }
-                    Kind::String => {
-                        if let Some(string_value) = field_value.as_str() {
-                            let value = ProstReflectValue::String(string_value.to_string());
-                            if value.is_valid_for_field(&field_descriptor) {
-                                dynamic_message.set_field_by_name(field_name, value);
-                                debug!(
-                                    "Field '{}' set to String with value {}",
-                                    field_name, string_value
-                                );
-                            } else {
-                                return Err(format!(
-                                    "Field '{}' expects a string value",
-                                    field_name
-                                ));
-                            }
-                        } else {
-                            return Err(format!("Field '{}' expects a string value", field_name));
-                        }
-                    }
-                    Kind::Bytes => {
-                        if let Some(string_value) = field_value.as_str() {
-                            let bytes = string_value.as_bytes().to_vec();
-                            let bytes_for_dyn_message = bytes.clone();
-                            let value = ProstReflectValue::Bytes(bytes.into());
-                            if value.is_valid_for_field(&field_descriptor) {
-                                dynamic_message.set_field_by_name(field_name, value);
-                                debug!(
-                                    "Field '{}' set to Bytes with value {:?}",
-                                    field_name, bytes_for_dyn_message
-                                );
-                            } else {
-                                return Err(format!(
-                                    "Field '{}' expects a byte array value",
-                                    field_name
-                                ));
-                            }
-                        } else {
-                            return Err(format!(
-                                "Field '{}' expects a byte array value",
-                                field_name
-                            ));
-                        }
-                    }
-                    Kind::Enum(enum_descriptor) => {
-                        if let Some(enum_value) = field_value.as_str() {
-                            if let Some(enum_value) = enum_descriptor.get_value_by_name(enum_value)
-                            {
-                                let value = ProstReflectValue::EnumNumber(enum_value.number());
-                                if value.is_valid_for_field(&field_descriptor) {
-                                    dynamic_message.set_field_by_name(field_name, value);
-                                    debug!(
-                                        "Field '{}' set to EnumNumber with value {}",
-                                        field_name,
-                                        enum_value.number()
-                                    );
-                                } else {
-                                    return Err(format!(
-                                        "Field '{}' expects a valid enum value",
-                                        field_name
-                                    ));
-                                }
-                            } else {
-                                return Err(format!(
-                                    "Invalid enum value '{}' for field '{}'",
-                                    enum_value, field_name
-                                ));
-                            }
-                        } else {
-                            return Err(format!(
-                                "Field '{}' expects a valid enum value as a string",
-                                field_name
-                            ));
-                        }
+    let mut context = PopulateContext::new(options, false, constraints);
+    populate_message(
+        &mut context,
+        dynamic_message,
+        message_descriptor,
+        json_value,
+        &FieldLocation::default(),
+    )
+}
+
+/// Walks the whole document instead of stopping at the first problem and returns every violation.
+/// Like `populate_dynamic_message_with_options`, it parses the `(dq.rules)` constraints on every
+/// call; see `populate_dynamic_message_all_errors_with_constraints`.
+#[tracing::instrument]
+pub fn populate_dynamic_message_all_errors(
+    dynamic_message: &mut DynamicMessage,
+    message_descriptor: &MessageDescriptor,
+    json_value: &JsonValue,
+    options: &PopulateOptions,
+) -> Result<(), Vec<PopulateError>> {
+    populate_all_errors(
+        dynamic_message,
+        message_descriptor,
+        json_value,
+        options,
+        &Constraints::from_pool(message_descriptor.parent_pool()),
+    )
+}
+
+/// Like `populate_dynamic_message_all_errors`, with the `(dq.rules)` constraints taken from
+/// `constraints` instead of being parsed from the pool of `message_descriptor` on every call.
+#[tracing::instrument(skip(constraints))]
+pub fn populate_dynamic_message_all_errors_with_constraints(
+    dynamic_message: &mut DynamicMessage,
+    message_descriptor: &MessageDescriptor,
+    json_value: &JsonValue,
+    options: &PopulateOptions,
+    constraints: &Constraints,
+) -> Result<(), Vec<PopulateError>> {
+    populate_all_errors(
+        dynamic_message,
+        message_descriptor,
+        json_value,
+        options,
+        constraints,
+    )
+}
+
+fn populate_all_errors(
+    dynamic_message: &mut DynamicMessage,
+    message_descriptor: &MessageDescriptor,
+    json_value: &JsonValue,
+    options: &PopulateOptions,
+    constraints: &Constraints,
+) -> Result<(), Vec<PopulateError>> {
+    info!("populate_dynamic_message_all_errors");
+
+    let mut context = PopulateContext::new(options, true, constraints);
+    if let Err(error) = populate_message(
+        &mut context,
+        dynamic_message,
+        message_descriptor,
+        json_value,
+        &FieldLocation::default(),
+    ) {
+        context.errors.push(error);
+    }
+
+    if context.errors.is_empty() {
+        Ok(())
+    } else {
+        debug!("Found {} violations", context.errors.len());
+        Err(context.errors)
+    }
+}
+
+fn populate_message(
+    context: &mut PopulateContext,
+    dynamic_message: &mut DynamicMessage,
+    message_descriptor: &MessageDescriptor,
+    json_value: &JsonValue,
+    location: &FieldLocation,
+) -> Result<(), PopulateError> {
+    if is_well_known_type(message_descriptor) {
+        *dynamic_message = populate_well_known_type(message_descriptor, location, json_value)?;
+        return Ok(());
+    }
+
+    let map = match json_value {
+        JsonValue::Object(map) => map,
+        _ => {
+            return Err(PopulateError::new(
+                PopulateErrorKind::NotAnObject,
+                "Expected a JSON object to populate DynamicMessage",
+            )
+            .at(location)
+            .expected(message_descriptor.full_name())
+            .actual(json_value));
+        }
+    };
+
+    let mut set_oneof_members: HashMap<String, String> = HashMap::new();
+    let mut set_fields: HashMap<u32, String> = HashMap::new();
+    // Fields whose value was rejected; their constraints are not checked.
+    let mut invalid_fields: HashSet<u32> = HashSet::new();
+
+    for (field_name, field_value) in map {
+        let field_location = location.field(field_name);
+
+        let field_descriptor = match find_field(
+            message_descriptor,
+            field_name,
+            context.options.field_naming,
+            &field_location,
+        ) {
+            Ok(Some(field_descriptor)) => field_descriptor,
+            Ok(None) => {
+                context.report(
+                    PopulateError::new(
+                        PopulateErrorKind::UnknownField,
+                        format!("Field '{}' not found in descriptor", field_location.name),
+                    )
+                    .at(&field_location)
+                    .actual(field_value),
+                )?;
+                continue;
+            }
+            Err(error) => {
+                context.report(error)?;
+                continue;
+            }
+        };
+
+        if field_value.is_null() && !accepts_json_null(&field_descriptor.kind()) {
+            debug!("Field '{}' is null, leaving it unset", field_location.name);
+            continue;
+        }
+
+        if let Some(previous_name) =
+            set_fields.insert(field_descriptor.number(), field_name.clone())
+        {
+            context.report(
+                PopulateError::new(
+                    PopulateErrorKind::DuplicateField,
+                    format!(
+                        "Field '{}' is set twice, as '{}' and as '{}'",
+                        field_descriptor.name(),
+                        previous_name,
+                        field_name
+                    ),
+                )
+                .at(&field_location),
+            )?;
+            invalid_fields.insert(field_descriptor.number());
+            continue;
+        }
+
+        if let Some(oneof_descriptor) = field_descriptor.containing_oneof() {
+            if let Some(previous_member) =
+                set_oneof_members.insert(oneof_descriptor.name().to_string(), field_name.clone())
+            {
+                context.report(
+                    PopulateError::new(
+                        PopulateErrorKind::OneofConflict,
+                        format!(
+                            "More than one member of oneof '{}' set: '{}' and '{}'",
+                            oneof_descriptor.name(),
+                            previous_member,
+                            field_name
+                        ),
+                    )
+                    .at(&field_location),
+                )?;
+                invalid_fields.insert(field_descriptor.number());
+                continue;
+            }
+        }
+
+        let kind = field_descriptor.kind();
+        // Errors in elements or entries are reported without failing the whole value.
+        let errors_before = context.errors.len();
+        let value = if field_descriptor.is_map() {
+            json_to_map_value(context, &kind, field_value, &field_location)
+        } else if field_descriptor.is_list() {
+            json_to_list_value(context, &kind, field_value, &field_location)
+        } else {
+            json_to_value(context, &kind, field_value, &field_location)
+        };
+
+        match value {
+            Ok(value) if value.is_valid_for_field(&field_descriptor) => {
+                dynamic_message.set_field(&field_descriptor, value);
+                if context.errors.len() > errors_before {
+                    invalid_fields.insert(field_descriptor.number());
+                }
+            }
+            Ok(_) => {
+                context.report(type_mismatch(&field_location, &kind, field_value))?;
+                invalid_fields.insert(field_descriptor.number());
+            }
+            Err(error) => {
+                context.report(error)?;
+                invalid_fields.insert(field_descriptor.number());
+            }
+        }
+    }
+
+    if !context.constraints.is_empty() {
+        for field_descriptor in message_descriptor.fields() {
+            if invalid_fields.contains(&field_descriptor.number()) {
+                continue;
+            }
+            let Some(field_rules) = context.constraints.get(&field_descriptor) else {
+                continue;
+            };
+            // Report the field under the name the document used, or would have used.
+            let field_name = match set_fields.get(&field_descriptor.number()) {
+                Some(field_name) => field_name.clone(),
+                None if context.options.field_naming == FieldNaming::JsonName => {
+                    field_descriptor.json_name().to_string()
+                }
+                None => field_descriptor.name().to_string(),
+            };
+            for violation in constraints::check_field(
+                field_rules,
+                dynamic_message,
+                &field_descriptor,
+                &location.field(&field_name),
+            ) {
+                context.report(violation)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn find_field(
+    message_descriptor: &MessageDescriptor,
+    field_name: &str,
+    field_naming: FieldNaming,
+    location: &FieldLocation,
+) -> Result<Option<FieldDescriptor>, PopulateError> {
+    let by_proto_name = message_descriptor.get_field_by_name(field_name);
+    let by_json_name = message_descriptor.get_field_by_json_name(field_name);
+
+    match (field_naming, by_proto_name, by_json_name) {
+        (FieldNaming::Any, by_proto_name, by_json_name) => Ok(by_proto_name.or(by_json_name)),
+        (FieldNaming::ProtoName, None, Some(field_descriptor)) => Err(PopulateError::new(
+            PopulateErrorKind::FieldNaming,
+            format!(
+                "Field '{}' must use the proto field name '{}'",
+                location.name,
+                field_descriptor.name()
+            ),
+        )
+        .at(location)),
+        (FieldNaming::ProtoName, by_proto_name, _) => Ok(by_proto_name),
+        (FieldNaming::JsonName, Some(field_descriptor), None) => Err(PopulateError::new(
+            PopulateErrorKind::FieldNaming,
+            format!(
+                "Field '{}' must use the JSON field name '{}'",
+                location.name,
+                field_descriptor.json_name()
+            ),
+        )
+        .at(location)),
+        (FieldNaming::JsonName, _, by_json_name) => Ok(by_json_name),
+    }
+}
+
+/// Errors of individual elements are reported to the context; the returned list only contains
+/// the elements that could be converted.
+fn json_to_list_value(
+    context: &mut PopulateContext,
+    kind: &Kind,
+    field_value: &JsonValue,
+    location: &FieldLocation,
+) -> Result<ProstReflectValue, PopulateError> {
+    if let Some(elements) = field_value.as_array() {
+        let mut values = Vec::with_capacity(elements.len());
+        for (index, element) in elements.iter().enumerate() {
+            match json_to_value(context, kind, element, &location.index(index)) {
+                Ok(value) => values.push(value),
+                Err(error) => context.report(error)?,
+            }
+        }
+        debug!(
+            "Field '{}' set to List with {} elements",
+            location.name,
+            values.len()
+        );
+        Ok(ProstReflectValue::List(values))
+    } else {
+        Err(PopulateError::new(
+            PopulateErrorKind::TypeMismatch,
+            format!(
+                "Field '{}' expects a list of {}",
+                location.name,
+                expected_value_description(kind)
+            ),
+        )
+        .at(location)
+        .expected(format!("repeated {}", kind_name(kind)))
+        .actual(field_value))
+    }
+}
+
+fn json_to_map_value(
+    context: &mut PopulateContext,
+    kind: &Kind,
+    field_value: &JsonValue,
+    location: &FieldLocation,
+) -> Result<ProstReflectValue, PopulateError> {
+    let entry_descriptor = match kind {
+        Kind::Message(entry_descriptor) if entry_descriptor.is_map_entry() => entry_descriptor,
+        _ => return Err(type_mismatch(location, kind, field_value)),
+    };
+    let key_kind = entry_descriptor.map_entry_key_field().kind();
+    let value_kind = entry_descriptor.map_entry_value_field().kind();
+
+    if let Some(entries) = field_value.as_object() {
+        let mut values = HashMap::with_capacity(entries.len());
+        for (key, value) in entries {
+            let entry_location = location.key(key);
+            let entry = json_key_to_map_key(&key_kind, key, location, &entry_location).and_then(
+                |map_key| {
+                    json_to_value(context, &value_kind, value, &entry_location)
+                        .map(|value| (map_key, value))
+                },
+            );
+            match entry {
+                Ok((map_key, value)) => {
+                    values.insert(map_key, value);
+                }
+                Err(error) => context.report(error)?,
+            }
+        }
+        debug!(
+            "Field '{}' set to Map with {} entries",
+            location.name,
+            values.len()
+        );
+        Ok(ProstReflectValue::Map(values))
+    } else {
+        Err(PopulateError::new(
+            PopulateErrorKind::TypeMismatch,
+            format!(
+                "Field '{}' expects a map object with {} values",
+                location.name,
+                expected_value_description(&value_kind)
+            ),
+        )
+        .at(location)
+        .expected(format!(
+            "map<{}, {}>",
+            kind_name(&key_kind),
+            kind_name(&value_kind)
+        ))
+        .actual(field_value))
+    }
+}
+
+/// JSON object keys are always strings, so map keys are parsed from their string form as the
+/// proto3 JSON mapping prescribes.
+fn json_key_to_map_key(
+    key_kind: &Kind,
+    key: &str,
+    location: &FieldLocation,
+    entry_location: &FieldLocation,
+) -> Result<MapKey, PopulateError> {
+    let key_value = JsonValue::String(key.to_string());
+    let invalid_key = || {
+        PopulateError::new(
+            PopulateErrorKind::InvalidMapKey,
+            format!(
+                "Field '{}' has invalid map key '{}': expects {}",
+                location.name,
+                key,
+                expected_value_description(key_kind)
+            ),
+        )
+        .at(entry_location)
+        .expected(kind_name(key_kind))
+        .actual(&key_value)
+    };
+
+    match key_kind {
+        Kind::String => Ok(MapKey::String(key.to_string())),
+        Kind::Bool => match key {
+            "true" => Ok(MapKey::Bool(true)),
+            "false" => Ok(MapKey::Bool(false)),
+            _ => Err(invalid_key()),
+        },
+        Kind::Int32
+        | Kind::Sint32
+        | Kind::Sfixed32
+        | Kind::Int64
+        | Kind::Sint64
+        | Kind::Sfixed64
+        | Kind::Uint32
+        | Kind::Fixed32
+        | Kind::Uint64
+        | Kind::Fixed64 => {
+            let int_value = key.parse::<i128>().map_err(|_| invalid_key())?;
+            match integer_to_value(key_kind, int_value, &key_value, entry_location)? {
+                ProstReflectValue::I32(value) => Ok(MapKey::I32(value)),
+                ProstReflectValue::I64(value) => Ok(MapKey::I64(value)),
+                ProstReflectValue::U32(value) => Ok(MapKey::U32(value)),
+                ProstReflectValue::U64(value) => Ok(MapKey::U64(value)),
+                _ => Err(invalid_key()),
+            }
+        }
+        _ => Err(PopulateError::new(
+            PopulateErrorKind::InvalidMapKey,
+            format!(
+                "Field '{}' has a map key type that is not allowed in protobuf",
+                location.name
+            ),
+        )
+        .at(entry_location)),
+    }
+}
+
+fn json_to_value(
+    context: &mut PopulateContext,
+    kind: &Kind,
+    field_value: &JsonValue,
+    location: &FieldLocation,
+) -> Result<ProstReflectValue, PopulateError> {
+    let expects = || type_mismatch(location, kind, field_value);
+
+    match kind {
+        Kind::Double => {
+            let float_value = json_to_f64(kind, field_value, location)?;
+            debug!(
+                "Field '{}' set to F64 with value {}",
+                location.name, float_value
+            );
+            Ok(ProstReflectValue::F64(float_value))
+        }
+        Kind::Float => {
+            let float_value = json_to_f64(kind, field_value, location)?;
+            if float_value.is_finite() && float_value.abs() > f32::MAX as f64 {
+                return Err(overflow(kind, float_value, field_value, location));
+            }
+            debug!(
+                "Field '{}' set to F32 with value {}",
+                location.name, float_value
+            );
+            Ok(ProstReflectValue::F32(float_value as f32))
+        }
+        Kind::Int32
+        | Kind::Sint32
+        | Kind::Sfixed32
+        | Kind::Int64
+        | Kind::Sint64
+        | Kind::Sfixed64
+        | Kind::Uint32
+        | Kind::Fixed32
+        | Kind::Uint64
+        | Kind::Fixed64 => {
+            let int_value = json_to_i128(kind, field_value, location)?;
+            let value = integer_to_value(kind, int_value, field_value, location)?;
+            debug!("Field '{}' set to {:?}", location.name, value);
+            Ok(value)
+        }
+        Kind::Bool => {
+            let bool_value = field_value.as_bool().ok_or_else(expects)?;
+            debug!(
+                "Field '{}' set to Bool with value {}",
+                location.name, bool_value
+            );
+            Ok(ProstReflectValue::Bool(bool_value))
+        }
+        Kind::String => {
+            let string_value = field_value.as_str().ok_or_else(expects)?;
+            debug!(
+                "Field '{}' set to String with value {}",
+                location.name, string_value
+            );
+            Ok(ProstReflectValue::String(string_value.to_string()))
+        }
+        Kind::Bytes => {
+            let string_value = field_value.as_str().ok_or_else(expects)?;
+            let bytes = match context.options.bytes_encoding {
+                BytesEncoding::Base64 => decode_base64(string_value, field_value, location)?,
+                BytesEncoding::RawString => string_value.as_bytes().to_vec(),
+            };
+            debug!(
+                "Field '{}' set to Bytes with value {:?}",
+                location.name, bytes
+            );
+            Ok(ProstReflectValue::Bytes(bytes.into()))
+        }
+        Kind::Enum(_) if accepts_json_null(kind) && field_value.is_null() => {
+            debug!("Field '{}' set to NULL_VALUE", location.name);
+            Ok(ProstReflectValue::EnumNumber(0))
+        }
+        Kind::Enum(enum_descriptor) => {
+            let enum_number = match field_value {
+                JsonValue::String(enum_name) => enum_descriptor
+                    .get_value_by_name(enum_name)
+                    .map(|enum_value| enum_value.number())
+                    .ok_or_else(|| {
+                        PopulateError::new(
+                            PopulateErrorKind::InvalidEnumValue,
+                            format!(
+                                "Invalid enum value '{}' for field '{}'",
+                                enum_name, location.name
+                            ),
+                        )
+                        .at(location)
+                        .expected(enum_descriptor.full_name())
+                        .actual(field_value)
+                    })?,
+                JsonValue::Number(_) => {
+                    let int_value = json_to_i128(kind, field_value, location)?;
+                    let enum_number = i32::try_from(int_value)
+                        .map_err(|_| overflow(kind, int_value, field_value, location))?;
+                    if enum_descriptor.get_value(enum_number).is_none() {
+                        check_unknown_enum_number(
+                            enum_descriptor,
+                            enum_number,
+                            context.options.unknown_enum_policy,
+                            field_value,
+                            location,
+                        )?;
                     }
-                    Kind::Message(sub_message_descriptor) => {
-                        if let Some(nested_value) = field_value.as_object() {
-                            let mut nested_message =
-                                DynamicMessage::new(sub_message_descriptor.clone());
-                            populate_dynamic_message(
-                                &mut nested_message,
-                                &sub_message_descriptor,
-                                field_value,
-                            )?;
-                            let value = ProstReflectValue::Message(nested_message);
-                            if value.is_valid_for_field(&field_descriptor) {
-                                dynamic_message.set_field_by_name(field_name, value);
-                                debug!("Field '{}' set to nested message", field_name);
-                            } else {
-                                return Err(format!(
-                                    "Field '{}' expects a nested message object",
-                                    field_name
-                                ));
-                            }
+                    enum_number
+                }
+                _ => return Err(expects()),
+            };
+            debug!(
+                "Field '{}' set to EnumNumber with value {}",
+                location.name, enum_number
+            );
+            Ok(ProstReflectValue::EnumNumber(enum_number))
+        }
+        Kind::Message(sub_message_descriptor) if is_well_known_type(sub_message_descriptor) => {
+            let well_known_message =
+                populate_well_known_type(sub_message_descriptor, location, field_value)?;
+            debug!(
+                "Field '{}' set to {}",
+                location.name,
+                sub_message_descriptor.full_name()
+            );
+            Ok(ProstReflectValue::Message(well_known_message))
+        }
+        Kind::Message(sub_message_descriptor) => {
+            if !field_value.is_object() {
+                return Err(expects());
+            }
+            let mut nested_message = DynamicMessage::new(sub_message_descriptor.clone());
+            populate_message(
+                context,
+                &mut nested_message,
+                sub_message_descriptor,
+                field_value,
+                location,
+            )?;
+            debug!("Field '{}' set to nested message", location.name);
+            Ok(ProstReflectValue::Message(nested_message))
+        }
+    }
+}
+
+/// Integers may be sent as JSON numbers or, as canonical proto3 JSON does for 64-bit values,
+/// as quoted decimal strings. Numbers with an exponent are accepted when they are integral.
+fn json_to_i128(
+    kind: &Kind,
+    field_value: &JsonValue,
+    location: &FieldLocation,
+) -> Result<i128, PopulateError> {
+    match field_value {
+        JsonValue::Number(number) => {
+            if let Some(int_value) = number.as_i64() {
+                Ok(int_value as i128)
+            } else if let Some(int_value) = number.as_u64() {
+                Ok(int_value as i128)
+            } else {
+                match number.as_f64() {
+                    Some(float_value) if float_value.is_finite() && float_value.fract() == 0.0 => {
+                        if float_value.abs() < 2f64.powi(127) {
+                            Ok(float_value as i128)
                         } else {
-                            return Err(format!(
-                                "Field '{}' expects a nested message object",
-                                field_name
-                            ));
+                            Err(overflow(kind, number, field_value, location))
                         }
                     }
+                    _ => Err(type_mismatch(location, kind, field_value)),
                 }
+            }
+        }
+        JsonValue::String(string_value) => string_value.parse::<i128>().map_err(|e| {
+            if matches!(
+                e.kind(),
+                std::num::IntErrorKind::PosOverflow | std::num::IntErrorKind::NegOverflow
+            ) {
+                overflow(kind, string_value, field_value, location)
             } else {
-                return Err(format!("Field '{}' not found in descriptor", field_name));
+                type_mismatch(location, kind, field_value)
             }
+        }),
+        _ => Err(type_mismatch(location, kind, field_value)),
+    }
+}
+
+fn integer_to_value(
+    kind: &Kind,
+    int_value: i128,
+    field_value: &JsonValue,
+    location: &FieldLocation,
+) -> Result<ProstReflectValue, PopulateError> {
+    let out_of_range = |_| overflow(kind, int_value, field_value, location);
+
+    match kind {
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => i32::try_from(int_value)
+            .map(ProstReflectValue::I32)
+            .map_err(out_of_range),
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => i64::try_from(int_value)
+            .map(ProstReflectValue::I64)
+            .map_err(out_of_range),
+        Kind::Uint32 | Kind::Fixed32 => u32::try_from(int_value)
+            .map(ProstReflectValue::U32)
+            .map_err(out_of_range),
+        Kind::Uint64 | Kind::Fixed64 => u64::try_from(int_value)
+            .map(ProstReflectValue::U64)
+            .map_err(out_of_range),
+        _ => Err(type_mismatch(location, kind, field_value)),
+    }
+}
+
+/// Floats may be JSON numbers, numeric strings, or one of the special strings `"NaN"`,
+/// `"Infinity"` and `"-Infinity"` defined by canonical proto3 JSON.
+fn json_to_f64(
+    kind: &Kind,
+    field_value: &JsonValue,
+    location: &FieldLocation,
+) -> Result<f64, PopulateError> {
+    match field_value {
+        JsonValue::Number(number) => number
+            .as_f64()
+            .ok_or_else(|| type_mismatch(location, kind, field_value)),
+        JsonValue::String(string_value) => match string_value.as_str() {
+            "NaN" => Ok(f64::NAN),
+            "Infinity" => Ok(f64::INFINITY),
+            "-Infinity" => Ok(f64::NEG_INFINITY),
+            _ => match string_value.parse::<f64>() {
+                Ok(float_value) if float_value.is_finite() => Ok(float_value),
+                // Rust also parses spellings such as "inf" or "nan", which are not canonical.
+                Ok(float_value) if float_value.is_nan() || !string_value.contains(['e', 'E']) => {
+                    Err(type_mismatch(location, kind, field_value))
+                }
+                Ok(_) => Err(overflow(kind, string_value, field_value, location)),
+                Err(_) => Err(type_mismatch(location, kind, field_value)),
+            },
+        },
+        _ => Err(type_mismatch(location, kind, field_value)),
+    }
+}
+
+fn check_unknown_enum_number(
+    enum_descriptor: &EnumDescriptor,
+    enum_number: i32,
+    policy: UnknownEnumPolicy,
+    field_value: &JsonValue,
+    location: &FieldLocation,
+) -> Result<(), PopulateError> {
+    let message = format!(
+        "Enum number {} is not defined in {} for field '{}'",
+        enum_number,
+        enum_descriptor.full_name(),
+        location.name
+    );
+
+    match policy {
+        UnknownEnumPolicy::Reject => Err(PopulateError::new(
+            PopulateErrorKind::InvalidEnumValue,
+            message,
+        )
+        .at(location)
+        .expected(enum_descriptor.full_name())
+        .actual(field_value)),
+        UnknownEnumPolicy::Accept => {
+            debug!("{}", message);
+            Ok(())
         }
+        UnknownEnumPolicy::Warn => {
+            warn!("{}", message);
+            Ok(())
+        }
+    }
+}
+
+fn decode_base64(
+    string_value: &str,
+    field_value: &JsonValue,
+    location: &FieldLocation,
+) -> Result<Vec<u8>, PopulateError> {
+    let engine = if string_value.contains(['-', '_']) {
+        &BASE64_URL_SAFE_INDIFFERENT
     } else {
-        return Err("Expected a JSON object to populate DynamicMessage".to_string());
+        &BASE64_STANDARD_INDIFFERENT
+    };
+
+    engine.decode(string_value).map_err(|e| {
+        PopulateError::new(
+            PopulateErrorKind::InvalidBase64,
+            format!(
+                "Field '{}' expects base64 encoded bytes: {}",
+                location.name, e
+            ),
+        )
+        .at(location)
+        .expected("bytes")
+        .actual(field_value)
+    })
+}
+
+fn type_mismatch(location: &FieldLocation, kind: &Kind, field_value: &JsonValue) -> PopulateError {
+    PopulateError::new(
+        PopulateErrorKind::TypeMismatch,
+        format!(
+            "Field '{}' expects {}",
+            location.name,
+            expected_value_description(kind)
+        ),
+    )
+    .at(location)
+    .expected(kind_name(kind))
+    .actual(field_value)
+}
+
+fn overflow(
+    kind: &Kind,
+    value: impl std::fmt::Display,
+    field_value: &JsonValue,
+    location: &FieldLocation,
+) -> PopulateError {
+    PopulateError::new(
+        PopulateErrorKind::Overflow,
+        format!(
+            "Field '{}' value {} is out of range for {}",
+            location.name,
+            value,
+            kind_name(kind)
+        ),
+    )
+    .at(location)
+    .expected(kind_name(kind))
+    .actual(field_value)
+}
+
+fn is_well_known_type(message_descriptor: &MessageDescriptor) -> bool {
+    WELL_KNOWN_TYPES.contains(&message_descriptor.full_name())
+}
+
+/// Well-known types have special JSON encodings (RFC 3339 timestamps, `"1.5s"` durations, `@type`
+/// resolution for `Any`, ...), so they are decoded with prost-reflect's canonical JSON mapping.
+/// `Any` payloads are resolved against the pool the descriptor belongs to.
+fn populate_well_known_type(
+    message_descriptor: &MessageDescriptor,
+    location: &FieldLocation,
+    field_value: &JsonValue,
+) -> Result<DynamicMessage, PopulateError> {
+    DynamicMessage::deserialize(message_descriptor.clone(), field_value).map_err(|e| {
+        PopulateError::new(
+            PopulateErrorKind::InvalidWellKnownType,
+            format!(
+                "Field '{}' expects the canonical JSON form of {}: {}",
+                location.name,
+                message_descriptor.full_name(),
+                e
+            ),
+        )
+        .at(location)
+        .expected(message_descriptor.full_name())
+        .actual(field_value)
+    })
+}
+
+/// In canonical proto3 JSON `null` means "use the default value", except for
+/// `google.protobuf.Value` and `google.protobuf.NullValue`, where it is a value of its own.
+fn accepts_json_null(kind: &Kind) -> bool {
+    match kind {
+        Kind::Message(message_descriptor) => {
+            message_descriptor.full_name() == "google.protobuf.Value"
+        }
+        Kind::Enum(enum_descriptor) => enum_descriptor.full_name() == "google.protobuf.NullValue",
+        _ => false,
     }
+}
 
-    Ok(())
+fn escape_json_pointer(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+fn json_type_name(json_value: &JsonValue) -> &'static str {
+    match json_value {
+        JsonValue::Null => "null",
+        JsonValue::Bool(_) => "boolean",
+        JsonValue::Number(_) => "number",
+        JsonValue::String(_) => "string",
+        JsonValue::Array(_) => "array",
+        JsonValue::Object(_) => "object",
+    }
+}
+
+fn kind_name(kind: &Kind) -> &str {
+    match kind {
+        Kind::Double => "double",
+        Kind::Float => "float",
+        Kind::Int32 => "int32",
+        Kind::Int64 => "int64",
+        Kind::Uint32 => "uint32",
+        Kind::Uint64 => "uint64",
+        Kind::Sint32 => "sint32",
+        Kind::Sint64 => "sint64",
+        Kind::Fixed32 => "fixed32",
+        Kind::Fixed64 => "fixed64",
+        Kind::Sfixed32 => "sfixed32",
+        Kind::Sfixed64 => "sfixed64",
+        Kind::Bool => "bool",
+        Kind::String => "string",
+        Kind::Bytes => "bytes",
+        Kind::Enum(enum_descriptor) => enum_descriptor.full_name(),
+        Kind::Message(message_descriptor) => message_descriptor.full_name(),
+    }
+}
+
+fn expected_value_description(kind: &Kind) -> &'static str {
+    match kind {
+        Kind::Double | Kind::Float => "a float or double value",
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => "an integer value",
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => "a 64-bit integer value",
+        Kind::Uint32 | Kind::Fixed32 => "an unsigned integer value",
+        Kind::Uint64 | Kind::Fixed64 => "an unsigned 64-bit integer value",
+        Kind::Bool => "a boolean value",
+        Kind::String => "a string value",
+        Kind::Bytes => "a base64 encoded bytes value",
+        Kind::Enum(_) => "an enum value name or number",
+        Kind::Message(_) => "a nested message object",
+    }
 }
 
 #[tracing::instrument]
@@ -1,13 +1,17 @@
 #[cfg(test)]
 mod tests {
-    use super::*;
-    use dynamic_message::{populate_dynamic_message, serialize_dynamic_message};
+    use dynamic_message::{
+        populate_dynamic_message, populate_dynamic_message_all_errors,
+        populate_dynamic_message_all_errors_with_constraints,
+        populate_dynamic_message_with_constraints, populate_dynamic_message_with_options,
+        serialize_dynamic_message, BytesEncoding, Constraints, FieldNaming, PopulateError,
+        PopulateErrorKind, PopulateOptions, UnknownEnumPolicy,
+    };
     use prost_reflect::{DescriptorPool, DynamicMessage};
     use prost_types::FileDescriptorSet;
     use serde_json::json;
     use std::fs::File;
     use std::io::Read;
-    use std::path::Path;
 
     fn load_test_descriptor() -> Result<FileDescriptorSet, String> {
         let filename = "tests/example.pb";
@@ -18,6 +22,29 @@ mod tests {
         prost::Message::decode(&*buffer).map_err(|e| format!("Failed to decode .pb file: {:?}", e))
     }
 
+    fn load_test_types_pool() -> DescriptorPool {
+        let buffer = std::fs::read("tests/test_types.pb").expect("Failed to read test_types.pb");
+        DescriptorPool::decode(buffer.as_slice()).expect("Failed to decode test_types.pb")
+    }
+
+    fn load_constraints_pool() -> DescriptorPool {
+        let buffer = std::fs::read("tests/constraints.pb").expect("Failed to read constraints.pb");
+        DescriptorPool::decode(buffer.as_slice()).expect("Failed to decode constraints.pb")
+    }
+
+    fn populate(
+        pool: &DescriptorPool,
+        message_name: &str,
+        json_value: &serde_json::Value,
+    ) -> Result<DynamicMessage, PopulateError> {
+        let message_descriptor = pool
+            .get_message_by_name(message_name)
+            .expect("Message not found");
+        let mut dynamic_message = DynamicMessage::new(message_descriptor.clone());
+        populate_dynamic_message(&mut dynamic_message, &message_descriptor, json_value)?;
+        Ok(dynamic_message)
+    }
+
     #[test]
     fn test_populate_dynamic_message_valid() {
         let file_descriptor_set = load_test_descriptor().expect("Failed to load test descriptor");
@@ -150,4 +177,804 @@ mod tests {
 
         assert!(result.is_err(), "Expected error due to invalid field name");
     }
+
+    #[test]
+    fn test_populate_repeated_scalar_fields() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({
+            "id": "order-1",
+            "tags": ["new", "priority"],
+            "counts": [1, 2, 3],
+            "weights": [0.5, 1.25],
+            "flags": [true, false],
+            "statuses": ["STATUS_ACTIVE", "STATUS_INACTIVE"]
+        });
+
+        let dynamic_message =
+            populate(&pool, "dq.test.Order", &json_value).expect("Expected repeated fields");
+
+        let tags = dynamic_message.get_field_by_name("tags").unwrap();
+        assert_eq!(tags.as_list().map(|list| list.len()), Some(2));
+        let statuses = dynamic_message.get_field_by_name("statuses").unwrap();
+        assert_eq!(statuses.as_list().map(|list| list.len()), Some(2));
+    }
+
+    #[test]
+    fn test_populate_repeated_message_field() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({
+            "items": [
+                {"sku": "A-1", "price": 9.99, "quantity": 1},
+                {"sku": "B-2", "price": 1.5, "quantity": 4}
+            ]
+        });
+
+        let dynamic_message =
+            populate(&pool, "dq.test.Order", &json_value).expect("Expected repeated messages");
+
+        let items = dynamic_message.get_field_by_name("items").unwrap();
+        assert_eq!(items.as_list().map(|list| list.len()), Some(2));
+    }
+
+    #[test]
+    fn test_populate_repeated_field_reports_element_index() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({ "counts": [1, "two", 3] });
+        let error = populate(&pool, "dq.test.Order", &json_value).unwrap_err();
+        assert!(
+            error.message.contains("counts[1]"),
+            "Unexpected error: {}",
+            error
+        );
+
+        let json_value = json!({ "items": [{"sku": "A-1"}, {"price": "free"}] });
+        let error = populate(&pool, "dq.test.Order", &json_value).unwrap_err();
+        assert!(
+            error.message.contains("items[1]"),
+            "Unexpected error: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_populate_repeated_field_rejects_non_array() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({ "tags": "single" });
+        let result = populate(&pool, "dq.test.Order", &json_value);
+
+        assert!(
+            result.is_err(),
+            "Expected error for scalar in repeated field"
+        );
+    }
+
+    #[test]
+    fn test_populate_map_fields() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({
+            "stock": {"A-1": 10, "B-2": -3},
+            "labels": {"1": "one", "-2": "minus two"},
+            "flagged": {"true": {"sku": "A-1", "price": 2.5}},
+            "states": {"18446744073709551615": "STATUS_ACTIVE"}
+        });
+
+        let dynamic_message =
+            populate(&pool, "dq.test.Inventory", &json_value).expect("Expected map fields");
+
+        let stock = dynamic_message.get_field_by_name("stock").unwrap();
+        assert_eq!(stock.as_map().map(|map| map.len()), Some(2));
+        let labels = dynamic_message.get_field_by_name("labels").unwrap();
+        assert!(labels
+            .as_map()
+            .unwrap()
+            .contains_key(&prost_reflect::MapKey::I32(-2)));
+    }
+
+    #[test]
+    fn test_populate_map_field_rejects_invalid_key() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({ "labels": {"one": "1"} });
+        let error = populate(&pool, "dq.test.Inventory", &json_value).unwrap_err();
+        assert!(
+            error.message.contains("invalid map key 'one'"),
+            "Unexpected error: {}",
+            error
+        );
+
+        let json_value = json!({ "flagged": {"yes": {"sku": "A-1"}} });
+        assert!(populate(&pool, "dq.test.Inventory", &json_value).is_err());
+    }
+
+    #[test]
+    fn test_populate_map_field_rejects_invalid_value() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({ "stock": {"A-1": "many"} });
+        let error = populate(&pool, "dq.test.Inventory", &json_value).unwrap_err();
+        assert!(
+            error.message.contains("stock[\"A-1\"]"),
+            "Unexpected error: {}",
+            error
+        );
+
+        let json_value = json!({ "stock": [1, 2] });
+        assert!(populate(&pool, "dq.test.Inventory", &json_value).is_err());
+    }
+
+    #[test]
+    fn test_populate_oneof_single_member_per_oneof() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({
+            "id": "event-1",
+            "item": {"sku": "A-1"},
+            "device": "sensor-7"
+        });
+
+        let dynamic_message =
+            populate(&pool, "dq.test.Event", &json_value).expect("Expected valid oneof members");
+
+        assert!(dynamic_message.has_field_by_name("item"));
+        assert!(dynamic_message.has_field_by_name("device"));
+    }
+
+    #[test]
+    fn test_populate_oneof_rejects_multiple_members() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({
+            "id": "event-1",
+            "text": "hello",
+            "number": 42
+        });
+
+        let error = populate(&pool, "dq.test.Event", &json_value).unwrap_err();
+        assert!(
+            error.message.contains("oneof 'payload'"),
+            "Unexpected error: {}",
+            error
+        );
+        assert!(
+            error.message.contains("'text'"),
+            "Unexpected error: {}",
+            error
+        );
+        assert!(
+            error.message.contains("'number'"),
+            "Unexpected error: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_populate_well_known_types() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({
+            "event_time": "2024-01-01T00:00:00Z",
+            "ttl": "1.5s",
+            "update_mask": "id,tags",
+            "note": "wrapped",
+            "sequence": "9007199254740993",
+            "attributes": {"region": "eu", "retries": 3, "nested": {"ok": true}},
+            "extra": null,
+            "history": [1, "two", {"three": 3}],
+            "payload": {"@type": "type.googleapis.com/dq.test.Item", "sku": "A-1"},
+            "checkpoints": ["2024-01-01T00:00:00Z", "2024-01-02T12:30:00.5+01:00"]
+        });
+
+        let dynamic_message = populate(&pool, "dq.test.Envelope", &json_value)
+            .expect("Expected canonical JSON for well-known types");
+
+        assert!(dynamic_message.has_field_by_name("event_time"));
+        assert!(dynamic_message.has_field_by_name("extra"));
+        assert!(dynamic_message.has_field_by_name("payload"));
+    }
+
+    #[test]
+    fn test_populate_well_known_types_rejects_invalid_values() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({ "event_time": "yesterday" });
+        let error = populate(&pool, "dq.test.Envelope", &json_value).unwrap_err();
+        assert!(
+            error.message.contains("google.protobuf.Timestamp"),
+            "Unexpected error: {}",
+            error
+        );
+
+        let json_value = json!({ "ttl": 15 });
+        assert!(populate(&pool, "dq.test.Envelope", &json_value).is_err());
+
+        let json_value = json!({
+            "payload": {"@type": "type.googleapis.com/dq.test.Unknown", "sku": "A-1"}
+        });
+        assert!(populate(&pool, "dq.test.Envelope", &json_value).is_err());
+    }
+
+    #[test]
+    fn test_populate_null_leaves_field_unset() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({ "id": null, "text": null, "number": 1 });
+        let dynamic_message =
+            populate(&pool, "dq.test.Event", &json_value).expect("Expected null to be accepted");
+
+        assert!(!dynamic_message.has_field_by_name("text"));
+        assert!(dynamic_message.has_field_by_name("number"));
+    }
+
+    #[test]
+    fn test_populate_integer_overflow_is_reported() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({ "quantity": 4294967296u64 });
+        let error = populate(&pool, "dq.test.Item", &json_value).unwrap_err();
+        assert_eq!(error.kind, PopulateErrorKind::Overflow);
+
+        let json_value = json!({ "quantity": -1 });
+        let error = populate(&pool, "dq.test.Item", &json_value).unwrap_err();
+        assert_eq!(error.kind, PopulateErrorKind::Overflow);
+
+        let json_value = json!({ "labels": {"4294967297": "wrapped"} });
+        let error = populate(&pool, "dq.test.Inventory", &json_value).unwrap_err();
+        assert_eq!(error.kind, PopulateErrorKind::Overflow);
+
+        let json_value = json!({ "counts": ["9223372036854775808"] });
+        let error = populate(&pool, "dq.test.Order", &json_value).unwrap_err();
+        assert_eq!(error.kind, PopulateErrorKind::Overflow);
+
+        let json_value = json!({ "weights": [1e39] });
+        let error = populate(&pool, "dq.test.Order", &json_value).unwrap_err();
+        assert_eq!(error.kind, PopulateErrorKind::Overflow);
+    }
+
+    #[test]
+    fn test_populate_integer_accepts_canonical_forms() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({ "counts": ["9223372036854775807", -5, 1e3] });
+        let dynamic_message =
+            populate(&pool, "dq.test.Order", &json_value).expect("Expected canonical integers");
+
+        let counts = dynamic_message.get_field_by_name("counts").unwrap();
+        let counts = counts.as_list().unwrap();
+        assert_eq!(counts[0].as_i64(), Some(i64::MAX));
+        assert_eq!(counts[2].as_i64(), Some(1000));
+    }
+
+    #[test]
+    fn test_populate_integer_rejects_fractions_and_garbage() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({ "counts": [1.5] });
+        let error = populate(&pool, "dq.test.Order", &json_value).unwrap_err();
+        assert_eq!(error.kind, PopulateErrorKind::TypeMismatch);
+
+        let json_value = json!({ "counts": ["12abc"] });
+        let error = populate(&pool, "dq.test.Order", &json_value).unwrap_err();
+        assert_eq!(error.kind, PopulateErrorKind::TypeMismatch);
+    }
+
+    #[test]
+    fn test_populate_float_special_values() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({ "weights": ["NaN", "Infinity", "-Infinity", "2.5"] });
+        let dynamic_message =
+            populate(&pool, "dq.test.Order", &json_value).expect("Expected special floats");
+
+        let weights = dynamic_message.get_field_by_name("weights").unwrap();
+        let weights = weights.as_list().unwrap();
+        assert!(weights[0].as_f32().unwrap().is_nan());
+        assert_eq!(weights[1].as_f32(), Some(f32::INFINITY));
+        assert_eq!(weights[3].as_f32(), Some(2.5));
+
+        let json_value = json!({ "price": "inf" });
+        let error = populate(&pool, "dq.test.Item", &json_value).unwrap_err();
+        assert_eq!(error.kind, PopulateErrorKind::TypeMismatch);
+
+        let json_value = json!({ "price": "1e400" });
+        let error = populate(&pool, "dq.test.Item", &json_value).unwrap_err();
+        assert_eq!(error.kind, PopulateErrorKind::Overflow);
+    }
+
+    #[test]
+    fn test_populate_bytes_decodes_base64_variants() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({
+            "payload": "aGVsbG8/Pz4+",
+            "chunks": ["aGVsbG8_Pz4-", "aGk", "aGk="]
+        });
+        let dynamic_message =
+            populate(&pool, "dq.test.Blob", &json_value).expect("Expected base64 bytes");
+
+        let payload = dynamic_message.get_field_by_name("payload").unwrap();
+        assert_eq!(payload.as_bytes().unwrap().as_ref(), b"hello??>>");
+        let chunks = dynamic_message.get_field_by_name("chunks").unwrap();
+        let chunks = chunks.as_list().unwrap();
+        assert_eq!(chunks[0].as_bytes().unwrap().as_ref(), b"hello??>>");
+        assert_eq!(chunks[1].as_bytes().unwrap().as_ref(), b"hi");
+        assert_eq!(chunks[2].as_bytes().unwrap().as_ref(), b"hi");
+    }
+
+    #[test]
+    fn test_populate_bytes_rejects_invalid_base64() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({ "payload": "not base64!" });
+        let error = populate(&pool, "dq.test.Blob", &json_value).unwrap_err();
+
+        assert_eq!(error.kind, PopulateErrorKind::InvalidBase64);
+    }
+
+    #[test]
+    fn test_populate_bytes_raw_string_mode() {
+        let pool = load_test_types_pool();
+        let message_descriptor = pool.get_message_by_name("dq.test.Blob").unwrap();
+        let mut dynamic_message = DynamicMessage::new(message_descriptor.clone());
+        let options = PopulateOptions::new().bytes_encoding(BytesEncoding::RawString);
+
+        let json_value = json!({ "payload": "not base64!" });
+        populate_dynamic_message_with_options(
+            &mut dynamic_message,
+            &message_descriptor,
+            &json_value,
+            &options,
+        )
+        .expect("Expected raw string bytes");
+
+        let payload = dynamic_message.get_field_by_name("payload").unwrap();
+        assert_eq!(payload.as_bytes().unwrap().as_ref(), b"not base64!");
+    }
+
+    #[test]
+    fn test_populate_enum_by_name_and_number() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({ "statuses": ["STATUS_ACTIVE", 2, 0] });
+        let dynamic_message =
+            populate(&pool, "dq.test.Order", &json_value).expect("Expected enum names and numbers");
+
+        let statuses = dynamic_message.get_field_by_name("statuses").unwrap();
+        let statuses = statuses.as_list().unwrap();
+        assert_eq!(statuses[0].as_enum_number(), Some(1));
+        assert_eq!(statuses[1].as_enum_number(), Some(2));
+    }
+
+    #[test]
+    fn test_populate_unknown_enum_number_policy() {
+        let pool = load_test_types_pool();
+        let message_descriptor = pool.get_message_by_name("dq.test.Order").unwrap();
+        let json_value = json!({ "statuses": [7] });
+
+        let error = populate(&pool, "dq.test.Order", &json_value).unwrap_err();
+        assert_eq!(error.kind, PopulateErrorKind::InvalidEnumValue);
+
+        for policy in [UnknownEnumPolicy::Accept, UnknownEnumPolicy::Warn] {
+            let mut dynamic_message = DynamicMessage::new(message_descriptor.clone());
+            let options = PopulateOptions::new().unknown_enum_policy(policy);
+            populate_dynamic_message_with_options(
+                &mut dynamic_message,
+                &message_descriptor,
+                &json_value,
+                &options,
+            )
+            .expect("Expected unknown enum number to be accepted");
+
+            let statuses = dynamic_message.get_field_by_name("statuses").unwrap();
+            assert_eq!(statuses.as_list().unwrap()[0].as_enum_number(), Some(7));
+        }
+    }
+
+    #[test]
+    fn test_populate_unknown_enum_name_is_always_rejected() {
+        let pool = load_test_types_pool();
+        let message_descriptor = pool.get_message_by_name("dq.test.Order").unwrap();
+        let mut dynamic_message = DynamicMessage::new(message_descriptor.clone());
+        let options = PopulateOptions::new().unknown_enum_policy(UnknownEnumPolicy::Accept);
+
+        let result = populate_dynamic_message_with_options(
+            &mut dynamic_message,
+            &message_descriptor,
+            &json!({ "statuses": ["STATUS_DELETED"] }),
+            &options,
+        );
+
+        assert_eq!(
+            result.unwrap_err().kind,
+            PopulateErrorKind::InvalidEnumValue
+        );
+    }
+
+    fn populate_with_naming(
+        pool: &DescriptorPool,
+        json_value: &serde_json::Value,
+        field_naming: FieldNaming,
+    ) -> Result<DynamicMessage, PopulateError> {
+        let message_descriptor = pool.get_message_by_name("dq.test.Profile").unwrap();
+        let mut dynamic_message = DynamicMessage::new(message_descriptor.clone());
+        let options = PopulateOptions::new().field_naming(field_naming);
+        populate_dynamic_message_with_options(
+            &mut dynamic_message,
+            &message_descriptor,
+            json_value,
+            &options,
+        )?;
+        Ok(dynamic_message)
+    }
+
+    #[test]
+    fn test_populate_accepts_proto_and_json_names() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({ "userId": "u-1", "login_count": 3, "name": "Ada" });
+        let dynamic_message = populate(&pool, "dq.test.Profile", &json_value)
+            .expect("Expected mixed field names to be accepted");
+
+        assert_eq!(
+            dynamic_message
+                .get_field_by_name("user_id")
+                .unwrap()
+                .as_str(),
+            Some("u-1")
+        );
+        assert_eq!(
+            dynamic_message
+                .get_field_by_name("display_name")
+                .unwrap()
+                .as_str(),
+            Some("Ada")
+        );
+    }
+
+    #[test]
+    fn test_populate_rejects_field_set_under_both_names() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({ "user_id": "u-1", "userId": "u-2" });
+        let error = populate(&pool, "dq.test.Profile", &json_value).unwrap_err();
+
+        assert_eq!(error.kind, PopulateErrorKind::DuplicateField);
+    }
+
+    #[test]
+    fn test_populate_strict_field_naming() {
+        let pool = load_test_types_pool();
+
+        let proto_names = json!({ "user_id": "u-1", "display_name": "Ada" });
+        let json_names = json!({ "userId": "u-1", "name": "Ada" });
+
+        assert!(populate_with_naming(&pool, &proto_names, FieldNaming::ProtoName).is_ok());
+        assert!(populate_with_naming(&pool, &json_names, FieldNaming::JsonName).is_ok());
+
+        let error = populate_with_naming(&pool, &json_names, FieldNaming::ProtoName).unwrap_err();
+        assert_eq!(error.kind, PopulateErrorKind::FieldNaming);
+        assert!(
+            error.message.contains("must use the proto field name"),
+            "Unexpected error: {}",
+            error
+        );
+
+        let error = populate_with_naming(&pool, &proto_names, FieldNaming::JsonName).unwrap_err();
+        assert_eq!(error.kind, PopulateErrorKind::FieldNaming);
+    }
+
+    #[test]
+    fn test_populate_all_errors_reports_every_violation() {
+        let pool = load_test_types_pool();
+        let message_descriptor = pool.get_message_by_name("dq.test.Order").unwrap();
+
+        let json_value = json!({
+            "id": 7,
+            "tags": ["ok", false],
+            "items": [
+                { "sku": "A", "price": 1.5 },
+                { "sku": "B", "price": "cheap", "quantity": -1 }
+            ],
+            "unknown": true
+        });
+        let mut dynamic_message = DynamicMessage::new(message_descriptor.clone());
+        let violations = populate_dynamic_message_all_errors(
+            &mut dynamic_message,
+            &message_descriptor,
+            &json_value,
+            &PopulateOptions::default(),
+        )
+        .unwrap_err();
+
+        let summary: Vec<(&str, PopulateErrorKind)> = violations
+            .iter()
+            .map(|violation| (violation.path.as_str(), violation.kind))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("/id", PopulateErrorKind::TypeMismatch),
+                ("/items/1/price", PopulateErrorKind::TypeMismatch),
+                ("/items/1/quantity", PopulateErrorKind::Overflow),
+                ("/tags/1", PopulateErrorKind::TypeMismatch),
+                ("/unknown", PopulateErrorKind::UnknownField),
+            ]
+        );
+
+        let price = &violations[1];
+        assert_eq!(price.expected.as_deref(), Some("double"));
+        assert_eq!(price.actual.as_deref(), Some("string"));
+        assert!(price.message.contains("items[1].price"));
+
+        let serialized = serde_json::to_value(price).unwrap();
+        assert_eq!(serialized["code"], "type_mismatch");
+        assert_eq!(serialized["path"], "/items/1/price");
+    }
+
+    #[test]
+    fn test_populate_error_path_escapes_json_pointer() {
+        let pool = load_test_types_pool();
+
+        let json_value = json!({ "stock": { "a/b~c": "many" } });
+        let error = populate(&pool, "dq.test.Inventory", &json_value).unwrap_err();
+
+        assert_eq!(error.path, "/stock/a~1b~0c");
+        assert_eq!(error.expected.as_deref(), Some("int64"));
+        assert_eq!(error.actual.as_deref(), Some("string"));
+    }
+
+    #[test]
+    fn test_constraints_accept_valid_message() {
+        let pool = load_constraints_pool();
+
+        let json_value = json!({
+            "name": "Ada",
+            "age": 36,
+            "email": "ada@example.com",
+            "country": "NL",
+            "tags": ["vip", "eu"],
+            "address": { "city": "Utrecht" }
+        });
+        assert!(populate(&pool, "dq.test.Customer", &json_value).is_ok());
+    }
+
+    #[test]
+    fn test_constraints_report_violations() {
+        let pool = load_constraints_pool();
+
+        let error = populate(
+            &pool,
+            "dq.test.Customer",
+            &json!({ "name": "Ada", "age": 150 }),
+        )
+        .unwrap_err();
+        assert_eq!(error.kind, PopulateErrorKind::ConstraintViolation);
+        assert_eq!(error.path, "/age");
+        assert_eq!(error.expected.as_deref(), Some("int32.lt = 150"));
+
+        let error = populate(
+            &pool,
+            "dq.test.Customer",
+            &json!({ "name": "Ada", "email": "not-an-email" }),
+        )
+        .unwrap_err();
+        assert_eq!(error.path, "/email");
+        assert!(error.message.contains("does not match"));
+
+        let error = populate(&pool, "dq.test.Customer", &json!({ "age": 3 })).unwrap_err();
+        assert_eq!(error.path, "/name");
+        assert_eq!(error.message, "Field 'name' is required");
+    }
+
+    #[test]
+    fn test_constraints_skip_unset_fields_with_presence() {
+        let pool = load_constraints_pool();
+
+        // `country` has presence and is only checked when set; `email` has no presence and an
+        // empty value does not match its pattern.
+        let error = populate(&pool, "dq.test.Customer", &json!({ "name": "Ada" })).unwrap_err();
+        assert_eq!(error.path, "/email");
+
+        let json_value = json!({ "name": "Ada", "email": "a@b", "country": "FR" });
+        let error = populate(&pool, "dq.test.Customer", &json_value).unwrap_err();
+        assert_eq!(error.path, "/country");
+        assert_eq!(
+            error.expected.as_deref(),
+            Some("string.in = [\"NL\", \"BE\", \"DE\"]")
+        );
+    }
+
+    #[test]
+    fn test_constraints_all_errors_reports_every_violation() {
+        let pool = load_constraints_pool();
+        let message_descriptor = pool.get_message_by_name("dq.test.Customer").unwrap();
+
+        let json_value = json!({
+            "name": "",
+            "age": "old",
+            "email": "a@b",
+            "tags": ["ok", "x", "yz"],
+            "address": {}
+        });
+        let mut dynamic_message = DynamicMessage::new(message_descriptor.clone());
+        let violations = populate_dynamic_message_all_errors(
+            &mut dynamic_message,
+            &message_descriptor,
+            &json_value,
+            &PopulateOptions::default(),
+        )
+        .unwrap_err();
+
+        let summary: Vec<(&str, PopulateErrorKind)> = violations
+            .iter()
+            .map(|violation| (violation.path.as_str(), violation.kind))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("/address/city", PopulateErrorKind::ConstraintViolation),
+                ("/age", PopulateErrorKind::TypeMismatch),
+                ("/name", PopulateErrorKind::ConstraintViolation),
+                ("/name", PopulateErrorKind::ConstraintViolation),
+                ("/tags", PopulateErrorKind::ConstraintViolation),
+                ("/tags/1", PopulateErrorKind::ConstraintViolation),
+            ]
+        );
+
+        let serialized = serde_json::to_value(&violations[3]).unwrap();
+        assert_eq!(serialized["code"], "constraint_violation");
+        assert_eq!(serialized["expected"], "string.min_len = 1");
+    }
+
+    #[test]
+    fn test_constraints_skip_lists_with_invalid_elements() {
+        let pool = load_constraints_pool();
+        let message_descriptor = pool.get_message_by_name("dq.test.Order").unwrap();
+
+        // The rejected element is left out of the list, which must not count as too few items.
+        let mut dynamic_message = DynamicMessage::new(message_descriptor.clone());
+        let violations = populate_dynamic_message_all_errors(
+            &mut dynamic_message,
+            &message_descriptor,
+            &json!({ "quantities": ["many"] }),
+            &PopulateOptions::default(),
+        )
+        .unwrap_err();
+        let summary: Vec<(&str, PopulateErrorKind)> = violations
+            .iter()
+            .map(|violation| (violation.path.as_str(), violation.kind))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("/quantities/0", PopulateErrorKind::TypeMismatch)]
+        );
+
+        let error = populate(&pool, "dq.test.Order", &json!({ "quantities": [] })).unwrap_err();
+        assert_eq!(error.path, "/quantities");
+        assert_eq!(error.expected.as_deref(), Some("repeated.min_items = 1"));
+    }
+
+    #[test]
+    fn test_constraints_parsed_once_match_the_field_options() {
+        let pool = load_constraints_pool();
+        let constraints = Constraints::from_files(pool.files()).unwrap();
+        assert!(!constraints.is_empty());
+        let message_descriptor = pool.get_message_by_name("dq.test.Customer").unwrap();
+
+        let error = |json_value: serde_json::Value| {
+            let mut dynamic_message = DynamicMessage::new(message_descriptor.clone());
+            populate_dynamic_message_with_constraints(
+                &mut dynamic_message,
+                &message_descriptor,
+                &json_value,
+                &PopulateOptions::default(),
+                &constraints,
+            )
+            .unwrap_err()
+        };
+        for json_value in [
+            json!({ "name": "Ada", "age": 150 }),
+            json!({ "name": "Ada", "email": "not-an-email" }),
+            json!({ "age": 3 }),
+        ] {
+            let expected = populate(&pool, "dq.test.Customer", &json_value).unwrap_err();
+            assert_eq!(error(json_value), expected);
+        }
+
+        let json_value = json!({ "name": "", "email": "a@b", "tags": ["ok", "x", "yz"] });
+        let mut dynamic_message = DynamicMessage::new(message_descriptor.clone());
+        let expected = populate_dynamic_message_all_errors(
+            &mut dynamic_message,
+            &message_descriptor,
+            &json_value,
+            &PopulateOptions::default(),
+        )
+        .unwrap_err();
+        let mut dynamic_message = DynamicMessage::new(message_descriptor.clone());
+        let violations = populate_dynamic_message_all_errors_with_constraints(
+            &mut dynamic_message,
+            &message_descriptor,
+            &json_value,
+            &PopulateOptions::default(),
+            &constraints,
+        )
+        .unwrap_err();
+        assert_eq!(violations, expected);
+    }
+
+    #[test]
+    fn test_constraints_reject_invalid_pattern() {
+        let buffer = std::fs::read("tests/invalid_constraints.pb")
+            .expect("Failed to read invalid_constraints.pb");
+        let pool = DescriptorPool::decode(buffer.as_slice())
+            .expect("Failed to decode invalid_constraints.pb");
+
+        let error = Constraints::from_files(pool.files()).unwrap_err();
+        assert!(error.contains("dq.test.Account.iban"), "{}", error);
+        assert!(error.contains("string.pattern \"[A-Z\""), "{}", error);
+
+        // Lenient parsing leaves the field unchecked instead.
+        let constraints = Constraints::from_pool(&pool);
+        let message_descriptor = pool.get_message_by_name("dq.test.Account").unwrap();
+        let mut dynamic_message = DynamicMessage::new(message_descriptor.clone());
+        assert!(populate_dynamic_message_with_constraints(
+            &mut dynamic_message,
+            &message_descriptor,
+            &json!({ "iban": "anything" }),
+            &PopulateOptions::default(),
+            &constraints,
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_constraints_reject_rules_for_other_types() {
+        let buffer = std::fs::read("tests/mismatched_constraints.pb")
+            .expect("Failed to read mismatched_constraints.pb");
+        let pool = DescriptorPool::decode(buffer.as_slice())
+            .expect("Failed to decode mismatched_constraints.pb");
+
+        let error = Constraints::from_files(pool.files()).unwrap_err();
+        let mut reasons: Vec<&str> = error.split("; ").collect();
+        reasons.sort();
+        assert_eq!(
+            reasons,
+            vec![
+                "Invalid (dq.rules) on field 'dq.test.Mismatched.count': string rules do not apply to a field of type int32",
+                "Invalid (dq.rules) on field 'dq.test.Mismatched.ids': repeated.items.uint64 rules do not apply to elements of type int64",
+                "Invalid (dq.rules) on field 'dq.test.Mismatched.name': repeated rules only apply to repeated fields",
+                "Invalid (dq.rules) on field 'dq.test.Mismatched.stock': int32 rules are not supported on map fields",
+                "Invalid (dq.rules) on field 'dq.test.Mismatched.tags': string rules do not apply to a repeated field, use repeated.items",
+            ]
+        );
+
+        // Without parsed constraints the mismatched rules are left out once per pool, and the
+        // rules that do apply are still checked.
+        let json_value = json!({
+            "count": 5,
+            "tags": [""],
+            "name": "x",
+            "ids": [0],
+            "stock": { "a": -1 },
+            "delta": -5,
+            "prices": { "a": 1 }
+        });
+        assert!(populate(&pool, "dq.test.Mismatched", &json_value).is_ok());
+
+        let error = populate(
+            &pool,
+            "dq.test.Mismatched",
+            &json!({ "delta": -20, "prices": { "a": 1 } }),
+        )
+        .unwrap_err();
+        assert_eq!(error.path, "/delta");
+        assert_eq!(error.expected.as_deref(), Some("int32.gte = -10"));
+
+        let error = populate(&pool, "dq.test.Mismatched", &json!({})).unwrap_err();
+        assert_eq!(error.message, "Field 'prices' is required");
+    }
 }
@@ -2,19 +2,17 @@
 
 use clap::Parser;
 use futures::future::join_all;
-use reqwest::{self, Client};
+use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use serde_json;
 use serde_json::{json, Value};
 use std::fs::File;
 use std::io::Read;
 use std::io::{self, Write};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio;
 use tokio::sync::Semaphore;
 use tokio::time::sleep;
-use tracing::{debug, error, info, span, trace, warn, Level};
+use tracing::{error, info, span, trace, warn, Level};
 
 use data_quality_settings::{load_env_variables, load_logging_config, parse_log_level};
 
@@ -87,6 +85,7 @@ struct ValidationRequest {
 }
 
 impl ValidationRequest {
+    #[allow(dead_code)]
     fn new_with_field_check(json_data: Value, field_name: String, field_value_check: i64) -> Self {
         Self {
             protobuf: "MyMessage".to_string(),